@@ -11,22 +11,52 @@ pub enum ClientMessage<'a> {
         name: &'a str,
         pass: &'a str,
     },
+    /// Logs in a bot account with its API token.
+    BotLogin {
+        token: &'a str,
+    },
+    /// Posts the text. A post isn't answered unless it fails,
+    /// then the reply has the `request` id the client picked for it, which isn't [`NO_REQUEST`].
     Say {
+        request: u64,
         chan: u32,
         text: &'a str,
     },
     File {
+        request: u64,
         chan: u32,
         ext: &'a str,
         bytes: &'a [u8],
     },
+    /// Runs a slash command, like `/topic Releases`, in the channel.
+    Command {
+        request: u64,
+        chan: u32,
+        name: &'a str,
+        args: &'a str,
+    },
+    /// Registers a command of a bot, users run it like the server ones.
+    RegisterCommand {
+        name: &'a str,
+        usage: &'a str,
+        description: &'a str,
+    },
+    /// Answers the command a bot is asked to run,
+    /// either to the user who ran it or to the channel.
+    CommandReply {
+        id: u64,
+        text: &'a str,
+        public: bool,
+    },
 }
 
-#[derive(Decode, Encode)]
+#[derive(Clone, Copy, Debug, Decode, Encode, PartialEq, Eq)]
 pub enum LoginError {
     NameAlreadyExists,
     AlreadyLogged,
     WrongNameOrPass,
+    Locked { retry_after_secs: u32 },
+    WrongToken,
 }
 
 impl fmt::Display for LoginError {
@@ -35,6 +65,10 @@ impl fmt::Display for LoginError {
             Self::NameAlreadyExists => write!(f, "name already exists"),
             Self::AlreadyLogged => write!(f, "alreadyL logged"),
             Self::WrongNameOrPass => write!(f, "wrong name or pass"),
+            Self::Locked { retry_after_secs } => {
+                write!(f, "too many attempts, try again in {retry_after_secs}s")
+            }
+            Self::WrongToken => write!(f, "wrong token"),
         }
     }
 }
@@ -44,6 +78,7 @@ pub struct User {
     pub id: u32,
     pub name: String,
     pub avatar: Option<String>,
+    pub bot: bool,
 }
 
 #[derive(Decode, Encode)]
@@ -51,13 +86,33 @@ pub struct Channel {
     pub id: u32,
     pub name: String,
     pub icon: Option<String>,
+    pub topic: Option<String>,
     pub history: Vec<Message>,
 }
 
 #[derive(Clone, Decode, Encode)]
 pub enum MessageType {
     Text(String),
-    File(String),
+    File {
+        thumb: String,
+        orig: String,
+    },
+    /// Something the sender does, posted with `/me`.
+    Action(String),
+}
+
+/// The sender of messages which don't come from a user, like webhook ones.
+pub const NOBODY: u32 = u32::MAX;
+
+/// The request id of failures which don't answer a post, like ones of a `CommandReply`.
+/// Clients pick other ids for their posts.
+pub const NO_REQUEST: u64 = 0;
+
+/// A name and an avatar shown instead of the sender's ones.
+#[derive(Clone, Decode, Encode)]
+pub struct Author {
+    pub name: String,
+    pub avatar: Option<String>,
 }
 
 #[derive(Clone, Decode, Encode)]
@@ -65,6 +120,16 @@ pub struct Message {
     pub from: u32,
     pub chan: u32,
     pub content: MessageType,
+    pub author: Option<Author>,
+}
+
+/// A slash command users can run.
+#[derive(Clone, Decode, Encode)]
+pub struct CommandInfo {
+    pub name: String,
+    /// Arguments it takes, like `<name> [minutes]`.
+    pub usage: String,
+    pub description: String,
 }
 
 #[derive(Decode, Encode)]
@@ -74,4 +139,36 @@ pub enum ServerMessage {
     User(User),
     Channel(Channel),
     Message(Message),
+    ServerShutdown {
+        reconnect_after: u32,
+    },
+    /// The post with the `request` id isn't accepted, it's [`NO_REQUEST`] for a `CommandReply`.
+    RateLimited {
+        request: u64,
+        retry_after_ms: u32,
+    },
+    TooLarge {
+        request: u64,
+        max_size: u32,
+    },
+    /// Commands users can run, sent after logging in and whenever bots change them.
+    Commands(Vec<CommandInfo>),
+    /// A command result only the user sees.
+    Notice {
+        chan: u32,
+        text: String,
+    },
+    /// The topic of the channel is changed.
+    Topic {
+        chan: u32,
+        topic: Option<String>,
+    },
+    /// Asks a bot to run its command, the bot answers with `CommandReply`.
+    Invoke {
+        id: u64,
+        chan: u32,
+        from: u32,
+        name: String,
+        args: String,
+    },
 }
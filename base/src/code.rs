@@ -1,13 +1,18 @@
 use bincode::{
-    config::{Configuration, Fixint, LittleEndian, NoLimit, SkipFixedArrayLength},
+    config::{Configuration, Fixint, Limit, LittleEndian, SkipFixedArrayLength},
     error::{DecodeError, EncodeError},
     BorrowDecode, Encode,
 };
 
-const CONFIG: Configuration<LittleEndian, Fixint, SkipFixedArrayLength, NoLimit> =
+/// Max number of bytes a decoded message can claim.
+/// A length prefix over it fails the decoding instead of allocating the memory.
+pub const DECODE_LIMIT: usize = 64 * 1024 * 1024;
+
+const CONFIG: Configuration<LittleEndian, Fixint, SkipFixedArrayLength, Limit<DECODE_LIMIT>> =
     bincode::config::standard()
         .with_fixed_int_encoding()
-        .skip_fixed_array_length();
+        .skip_fixed_array_length()
+        .with_limit::<DECODE_LIMIT>();
 
 pub fn encode<M>(message: &M, buf: &mut Vec<u8>) -> Result<u32, EncodeError>
 where
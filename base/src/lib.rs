@@ -1,4 +1,5 @@
 pub mod api;
 mod code;
 
-pub use self::code::{decode, encode};
+pub use self::code::{decode, encode, DECODE_LIMIT};
+pub use bincode::error::{DecodeError, EncodeError};
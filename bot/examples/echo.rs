@@ -0,0 +1,48 @@
+//! Answers `!echo <text>` and the `/echo <text>` command with the text.
+//!
+//! Create a bot account and run it with its token:
+//!
+//! ```sh
+//! VOKI_URL=ws://localhost:4567 VOKI_TOKEN=<token> cargo run --example echo
+//! ```
+
+use bot::{Bot, Command, Event};
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt::init();
+
+    let url = std::env::var("VOKI_URL").unwrap_or_else(|_| "ws://localhost:4567".into());
+    let token = std::env::var("VOKI_TOKEN").expect("VOKI_TOKEN is set");
+    let (bot, mut events) = Bot::builder(url, token).connect();
+    bot.register("echo", "<text>", "Repeats the text")
+        .expect("the bot is connecting");
+
+    let mut me = None;
+    while let Some(event) = events.recv().await {
+        match event {
+            Event::Ready { id } => me = Some(id),
+            // Skip own messages, so the bot doesn't answer itself
+            Event::Message(message) if Some(message.from) != me => {
+                let command = bot::text(&message).and_then(|text| Command::parse(text, "!"));
+                if let Some(Command { name: "echo", rest }) = command {
+                    if !rest.is_empty() && bot.say(message.chan, rest).is_err() {
+                        break;
+                    }
+                }
+            }
+            Event::Command(command) if command.name == "echo" => {
+                let replied = match command.args.as_str() {
+                    "" => bot.reply_privately(&command, "Usage: /echo <text>"),
+                    text => bot.reply(&command, text),
+                };
+
+                if replied.is_err() {
+                    break;
+                }
+            }
+            Event::LoginFailed(err) => eprintln!("couldn't log in: {err}"),
+            _ => {}
+        }
+    }
+}
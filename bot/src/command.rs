@@ -0,0 +1,57 @@
+/// A command in a text message, like `!deploy web "release 2"`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Command<'a> {
+    /// The word after the prefix.
+    pub name: &'a str,
+    /// The text after the name, trimmed.
+    pub rest: &'a str,
+}
+
+impl<'a> Command<'a> {
+    /// Parses the text which starts with the prefix and a name right after it.
+    pub fn parse(text: &'a str, prefix: &str) -> Option<Self> {
+        let text = text.trim_start().strip_prefix(prefix)?;
+        let (name, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        if name.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            name,
+            rest: rest.trim(),
+        })
+    }
+
+    /// Splits the rest into arguments by whitespace,
+    /// double quotes keep an argument with spaces together.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec![];
+        let mut arg = String::new();
+        let mut quoted = false;
+        let mut started = false;
+        for c in self.rest.chars() {
+            match c {
+                '"' => {
+                    quoted = !quoted;
+                    started = true;
+                }
+                c if c.is_whitespace() && !quoted => {
+                    if started {
+                        args.push(std::mem::take(&mut arg));
+                        started = false;
+                    }
+                }
+                c => {
+                    arg.push(c);
+                    started = true;
+                }
+            }
+        }
+
+        if started {
+            args.push(arg);
+        }
+
+        args
+    }
+}
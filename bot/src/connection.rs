@@ -0,0 +1,186 @@
+use crate::{Event, Invocation};
+use base::api::{LoginError, ServerMessage};
+use client::{Receiver, Sender};
+use std::time::Duration;
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver},
+    time,
+};
+use tracing::{debug, info, warn};
+
+pub enum Request {
+    /// Sent once the bot is logged in.
+    Send(client::Request),
+    /// Closes the connection, the bot stops.
+    Close,
+}
+
+/// How a session ends.
+enum End {
+    /// The bot is closed or can't log in.
+    Stop,
+    /// The connection is lost, the server may tell when to come back.
+    Lost {
+        logged: bool,
+        delay: Option<Duration>,
+    },
+}
+
+/// Keeps the connection of a bot, it runs until the bot is closed.
+pub struct Connection {
+    pub url: String,
+    pub token: String,
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    pub requests: UnboundedReceiver<Request>,
+    pub events: mpsc::Sender<Event>,
+    /// Commands of the bot by name, they're registered again after every login.
+    pub commands: Vec<(String, client::Request)>,
+}
+
+impl Connection {
+    pub async fn run(mut self) {
+        let mut backoff = self.min_backoff;
+        loop {
+            let end = match client::connect(&self.url).await {
+                Ok((sender, receiver)) => self.session(sender, receiver).await,
+                Err(err) => {
+                    debug!(error = %err, "couldn't connect");
+                    End::Lost {
+                        logged: false,
+                        delay: None,
+                    }
+                }
+            };
+
+            let End::Lost { logged, delay } = end else {
+                return;
+            };
+
+            // Attempts back off until the bot logs in again
+            if logged {
+                backoff = self.min_backoff;
+            }
+
+            let reconnect_after = delay.unwrap_or(backoff);
+            if delay.is_none() {
+                backoff = (backoff * 2).min(self.max_backoff);
+            }
+
+            info!(?reconnect_after, "disconnected");
+            let event = Event::Disconnected { reconnect_after };
+            if self.events.send(event).await.is_err() {
+                return;
+            }
+
+            tokio::select! {
+                () = time::sleep(reconnect_after) => {}
+                () = self.events.closed() => return,
+            }
+        }
+    }
+
+    /// Keeps the registration of a command, it replaces an earlier one with the same name.
+    fn register(&mut self, name: String, request: client::Request) {
+        match self.commands.iter_mut().find(|(old, _)| *old == name) {
+            Some((_, old)) => *old = request,
+            None => self.commands.push((name, request)),
+        }
+    }
+
+    async fn session(&mut self, sender: Sender, mut receiver: Receiver) -> End {
+        let mut logged = false;
+        let mut delay = None;
+        let lost = |logged, delay| End::Lost { logged, delay };
+
+        let token = self.token.clone();
+        if let Err(err) = sender.send(client::Request::BotLogin { token }) {
+            debug!(error = %err, "couldn't log in");
+            return lost(logged, delay);
+        }
+
+        loop {
+            let event = tokio::select! {
+                event = receiver.recv() => {
+                    // Failures of posts come as events, so replies aren't needed
+                    let message = match event {
+                        Some(client::Event::Message(message)) => message,
+                        Some(client::Event::Reply { .. }) => continue,
+                        None => return lost(logged, delay),
+                    };
+
+                    match message {
+                        ServerMessage::Closed => return lost(logged, delay),
+                        ServerMessage::LoggedIn(Ok(id)) => {
+                            logged = true;
+                            for (_, command) in &self.commands {
+                                if let Err(err) = sender.send(command.clone()) {
+                                    debug!(error = %err, "couldn't register a command");
+                                    return lost(logged, delay);
+                                }
+                            }
+
+                            Event::Ready { id }
+                        }
+                        ServerMessage::LoggedIn(Err(LoginError::Locked { retry_after_secs })) => {
+                            let delay = Duration::from_secs(retry_after_secs.into());
+                            return lost(logged, Some(delay));
+                        }
+                        ServerMessage::LoggedIn(Err(err)) => {
+                            warn!(error = %err, "couldn't log in");
+                            let _ = self.events.send(Event::LoginFailed(err)).await;
+                            sender.close();
+                            return End::Stop;
+                        }
+                        ServerMessage::User(user) => Event::User(user),
+                        ServerMessage::Channel(chan) => Event::Channel(chan),
+                        ServerMessage::Message(message) => Event::Message(message),
+                        ServerMessage::ServerShutdown { reconnect_after } => {
+                            delay = Some(Duration::from_secs(reconnect_after.into()));
+                            continue;
+                        }
+                        ServerMessage::RateLimited { retry_after_ms, .. } => {
+                            let retry_after = Duration::from_millis(retry_after_ms.into());
+                            Event::RateLimited { retry_after }
+                        }
+                        ServerMessage::TooLarge { max_size, .. } => Event::TooLarge { max_size },
+                        ServerMessage::Commands(commands) => Event::Commands(commands),
+                        ServerMessage::Notice { chan, text } => Event::Notice { chan, text },
+                        ServerMessage::Topic { chan, topic } => Event::Topic { chan, topic },
+                        ServerMessage::Invoke { id, chan, from, name, args } => {
+                            Event::Command(Invocation { id, chan, from, name, args })
+                        }
+                    }
+                }
+                // Requests wait until the bot is logged in
+                request = self.requests.recv(), if logged => {
+                    let request = match request {
+                        Some(Request::Send(request)) => request,
+                        Some(Request::Close) | None => {
+                            sender.close();
+                            return End::Stop;
+                        }
+                    };
+
+                    if let client::Request::RegisterCommand { name, .. } = &request {
+                        self.register(name.clone(), request.clone());
+                    }
+
+                    // The client picks the ids of posts, so their failures aren't mixed up
+                    if let Err(err) = sender.send(request) {
+                        debug!(error = %err, "couldn't send a request");
+                        return lost(logged, delay);
+                    }
+
+                    continue;
+                }
+            };
+
+            // Nobody listens to the bot anymore
+            if self.events.send(event).await.is_err() {
+                sender.close();
+                return End::Stop;
+            }
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Writes voki bots.
+//!
+//! A bot logs in with the token of its bot account, which an admin creates with
+//! `POST /api/bots`. It gets chat events from a stream and keeps the connection
+//! on its own, reconnecting whenever it's lost. The connection is the one of the
+//! [`client`] crate, so requests are sent the same way.
+//!
+//! Besides reading messages, a bot can register slash commands with
+//! [`Bot::register`]. Users see them among the server ones, and a bot gets
+//! [`Event::Command`] when one is run.
+//!
+//! ```no_run
+//! # async fn example() {
+//! use bot::{Bot, Command, Event};
+//!
+//! let (bot, mut events) = Bot::builder("ws://localhost:4567", "token").connect();
+//! while let Some(event) = events.recv().await {
+//!     if let Event::Message(message) = event {
+//!         if let Some(cmd) = bot::text(&message).and_then(|text| Command::parse(text, "!")) {
+//!             if cmd.name == "ping" {
+//!                 let _ = bot.say(message.chan, "pong");
+//!             }
+//!         }
+//!     }
+//! }
+//! # }
+//! ```
+
+mod command;
+mod connection;
+
+pub use {
+    self::command::Command,
+    base::api::{self, Channel, CommandInfo, LoginError, Message, MessageType, User},
+};
+
+use self::connection::{Connection, Request};
+use futures::Stream;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+/// How many events wait for the bot before the connection stops reading.
+const EVENTS: usize = 256;
+
+/// Configures a bot before it connects.
+pub struct BotBuilder {
+    url: String,
+    token: String,
+    min_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl BotBuilder {
+    /// Sets delays between reconnection attempts, they double after every failed one.
+    pub fn backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.min_backoff = min;
+        self.max_backoff = max.max(min);
+        self
+    }
+
+    /// Starts connecting in the background and returns the bot with its events.
+    ///
+    /// It must be called within a tokio runtime.
+    pub fn connect(self) -> (Bot, Events) {
+        let (requests, requests_rx) = mpsc::unbounded_channel();
+        let (events_tx, events) = mpsc::channel(EVENTS);
+        let connection = Connection {
+            url: self.url,
+            token: self.token,
+            min_backoff: self.min_backoff,
+            max_backoff: self.max_backoff,
+            requests: requests_rx,
+            events: events_tx,
+            commands: vec![],
+        };
+
+        tokio::spawn(connection.run());
+        (Bot { requests }, Events { events })
+    }
+}
+
+/// Sends requests of a bot, it can be cloned to send from several tasks.
+///
+/// Requests made while the bot is disconnected are sent after it logs in again.
+#[derive(Clone)]
+pub struct Bot {
+    requests: mpsc::UnboundedSender<Request>,
+}
+
+impl Bot {
+    /// Configures a bot which connects to the websocket url,
+    /// like `ws://localhost:4567`, and logs in with the token.
+    pub fn builder<U, T>(url: U, token: T) -> BotBuilder
+    where
+        U: Into<String>,
+        T: Into<String>,
+    {
+        BotBuilder {
+            url: url.into(),
+            token: token.into(),
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    /// Posts the text to the channel.
+    pub fn say<S>(&self, chan: u32, text: S) -> Result<(), Closed>
+    where
+        S: Into<String>,
+    {
+        let text = text.into();
+        self.send(Request::Send(client::Request::Say { chan, text }))
+    }
+
+    /// Uploads an image with the extension, like `png`, to the channel.
+    pub fn upload<S>(&self, chan: u32, ext: S, bytes: Vec<u8>) -> Result<(), Closed>
+    where
+        S: Into<String>,
+    {
+        let ext = ext.into();
+        self.send(Request::Send(client::Request::File { chan, ext, bytes }))
+    }
+
+    /// Registers the slash command, like `/deploy`, which users run in the chat.
+    /// The bot gets [`Event::Command`] when it's run and answers it with
+    /// [`reply`](Self::reply) or [`reply_privately`](Self::reply_privately).
+    ///
+    /// Commands are registered again after every reconnection,
+    /// and the server forgets them when the bot disconnects.
+    pub fn register<N, U, D>(&self, name: N, usage: U, description: D) -> Result<(), Closed>
+    where
+        N: Into<String>,
+        U: Into<String>,
+        D: Into<String>,
+    {
+        self.send(Request::Send(client::Request::RegisterCommand {
+            name: name.into(),
+            usage: usage.into(),
+            description: description.into(),
+        }))
+    }
+
+    /// Answers the command in its channel, everyone sees it as a message of the bot.
+    pub fn reply<S>(&self, command: &Invocation, text: S) -> Result<(), Closed>
+    where
+        S: Into<String>,
+    {
+        self.answer(command, text.into(), true)
+    }
+
+    /// Answers the command to the user who ran it only.
+    pub fn reply_privately<S>(&self, command: &Invocation, text: S) -> Result<(), Closed>
+    where
+        S: Into<String>,
+    {
+        self.answer(command, text.into(), false)
+    }
+
+    fn answer(&self, command: &Invocation, text: String, public: bool) -> Result<(), Closed> {
+        let id = command.id;
+        self.send(Request::Send(client::Request::CommandReply {
+            id,
+            text,
+            public,
+        }))
+    }
+
+    /// Closes the connection, the events end after it.
+    pub fn close(&self) {
+        let _ = self.send(Request::Close);
+    }
+
+    fn send(&self, request: Request) -> Result<(), Closed> {
+        self.requests.send(request).map_err(|_| Closed)
+    }
+}
+
+/// Events of a bot in the order they come.
+///
+/// The stream ends when the bot is closed or it can't log in with its token.
+pub struct Events {
+    events: mpsc::Receiver<Event>,
+}
+
+impl Events {
+    /// Waits for the next event.
+    pub async fn recv(&mut self) -> Option<Event> {
+        self.events.recv().await
+    }
+}
+
+impl Stream for Events {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+/// What happens in the chat.
+pub enum Event {
+    /// The bot is logged in with the user id, after every reconnection too.
+    /// Users and channels with their history follow it.
+    Ready {
+        id: u32,
+    },
+    User(User),
+    Channel(Channel),
+    /// A new message, messages posted while the bot was disconnected aren't sent.
+    Message(Message),
+    /// The last request is over the rate limit.
+    RateLimited {
+        retry_after: Duration,
+    },
+    /// The last request is over the size limit.
+    TooLarge {
+        max_size: u32,
+    },
+    /// The connection is lost, it's tried again after the delay.
+    Disconnected {
+        reconnect_after: Duration,
+    },
+    /// The bot can't log in, like if its token is revoked. It's the last event.
+    LoginFailed(LoginError),
+    /// Commands users can run, it comes after login and when bots change them.
+    Commands(Vec<CommandInfo>),
+    /// A server reply only the bot sees, like the usage of a command.
+    Notice {
+        chan: u32,
+        text: String,
+    },
+    /// The topic of the channel is changed.
+    Topic {
+        chan: u32,
+        topic: Option<String>,
+    },
+    /// A user runs a command the bot registered.
+    Command(Invocation),
+}
+
+/// A command of the bot a user runs.
+pub struct Invocation {
+    /// Answers are matched to the command with it.
+    pub id: u64,
+    pub chan: u32,
+    /// The user who runs it.
+    pub from: u32,
+    pub name: String,
+    pub args: String,
+}
+
+impl Invocation {
+    /// Returns the command, so its arguments can be split.
+    pub fn command(&self) -> Command<'_> {
+        Command {
+            name: &self.name,
+            rest: &self.args,
+        }
+    }
+}
+
+/// Returns the text of a text message.
+pub fn text(message: &Message) -> Option<&str> {
+    match &message.content {
+        MessageType::Text(text) => Some(text),
+        MessageType::File { .. } | MessageType::Action(_) => None,
+    }
+}
+
+/// The bot is closed, so it can't send requests.
+#[derive(Debug)]
+pub struct Closed;
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the bot is closed")
+    }
+}
+
+impl std::error::Error for Closed {}
@@ -0,0 +1,446 @@
+use base::{
+    api::{ClientMessage, ServerMessage},
+    decode, encode,
+};
+use bot::{Bot, Command, Event, Events, LoginError, MessageType};
+use futures::{SinkExt, StreamExt};
+use server::Server;
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{net::TcpStream, time};
+use websocket::{tungstenite::Message as WsMessage, MaybeTlsStream, WebSocketStream};
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+// The default admin
+const ADMIN: u32 = 0;
+
+async fn server(address: &str, data: &Path) -> Server {
+    let data = data.to_owned();
+    Server::builder()
+        .address(address)
+        .configure(|config| {
+            config.storage.data = Some(data);
+            config.shutdown.reconnect_after = 1;
+        })
+        .bind()
+        .await
+        .expect("bind")
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("voki-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn connect(server: &Server, token: &str) -> (Bot, Events) {
+    let url = format!("ws://{}", server.local_addr().expect("address"));
+    Bot::builder(url, token)
+        .backoff(Duration::from_millis(100), Duration::from_secs(1))
+        .connect()
+}
+
+async fn next(events: &mut Events) -> Option<Event> {
+    time::timeout(Duration::from_secs(10), events.recv())
+        .await
+        .expect("event in time")
+}
+
+async fn ready(events: &mut Events) -> u32 {
+    loop {
+        if let Event::Ready { id } = next(events).await.expect("event") {
+            return id;
+        }
+    }
+}
+
+/// A websocket client of a user, who runs commands of bots.
+struct User(WebSocketStream<MaybeTlsStream<TcpStream>>);
+
+impl User {
+    async fn login(server: &Server, name: &str) -> Self {
+        Self::connect(server, &ClientMessage::Login { name, pass: name }).await
+    }
+
+    /// Logs in as a bot without the bot crate, to send raw replies.
+    async fn bot(server: &Server, token: &str) -> Self {
+        Self::connect(server, &ClientMessage::BotLogin { token }).await
+    }
+
+    async fn connect(server: &Server, login: &ClientMessage<'_>) -> Self {
+        let url = format!("ws://{}", server.local_addr().expect("address"));
+        let (stream, _) = websocket::connect_async(url).await.expect("connect");
+        let mut user = Self(stream);
+        user.send(login).await;
+        user
+    }
+
+    async fn send(&mut self, message: &ClientMessage<'_>) {
+        let mut buf = vec![];
+        encode(message, &mut buf).expect("encode");
+        self.0
+            .send(WsMessage::Binary(buf.into()))
+            .await
+            .expect("send");
+    }
+
+    async fn recv_until<F>(&mut self, mut f: F) -> ServerMessage
+    where
+        F: FnMut(&ServerMessage) -> bool,
+    {
+        let wait = async {
+            while let Some(Ok(message)) = self.0.next().await {
+                if let WsMessage::Binary(bytes) = message {
+                    let message = decode(&bytes).expect("decode");
+                    if f(&message) {
+                        return message;
+                    }
+                }
+            }
+
+            panic!("connection closed");
+        };
+
+        time::timeout(Duration::from_secs(10), wait)
+            .await
+            .expect("message in time")
+    }
+}
+
+#[test]
+fn commands() {
+    let command = Command::parse("  !deploy web \"release 2\"  now ", "!").expect("command");
+    assert_eq!(command.name, "deploy");
+    assert_eq!(command.rest, "web \"release 2\"  now");
+    assert_eq!(command.args(), ["web", "release 2", "now"]);
+
+    let command = Command::parse("/ping", "/").expect("command");
+    assert_eq!(command.name, "ping");
+    assert!(command.args().is_empty());
+    assert_eq!(Command::parse("! ping", "!"), None);
+    assert_eq!(Command::parse("ping", "!"), None);
+}
+
+#[tokio::test]
+async fn echo() {
+    let data = temp_dir("bot-echo");
+    let server = server("127.0.0.1:0", &data).await;
+    let service = server.service();
+    let (_, token) = service.create_bot(ADMIN, "echo", None).expect("bot");
+    let (bot, mut events) = connect(&server, &token);
+    let me = ready(&mut events).await;
+
+    service.say(ADMIN, 0, "!echo hi", LOCALHOST).await.unwrap();
+    loop {
+        let Event::Message(message) = next(&mut events).await.expect("event") else {
+            continue;
+        };
+
+        if message.from == me {
+            assert_eq!(bot::text(&message), Some("hi"));
+            break;
+        }
+
+        let text = bot::text(&message).expect("text");
+        let command = Command::parse(text, "!").expect("command");
+        assert_eq!(command.name, "echo");
+        bot.say(message.chan, command.rest).unwrap();
+    }
+
+    // The bot is a user other clients see
+    let users = service.users();
+    let user = users.iter().find(|user| user.id == me).expect("bot user");
+    assert_eq!(user.name, "echo");
+    assert!(user.bot);
+
+    bot.close();
+    while next(&mut events).await.is_some() {}
+    server.shutdown().await;
+    let _ = std::fs::remove_dir_all(data);
+}
+
+#[tokio::test]
+async fn slash_commands() {
+    let data = temp_dir("bot-slash-commands");
+    let server = server("127.0.0.1:0", &data).await;
+    let service = server.service();
+    let (_, token) = service.create_bot(ADMIN, "dice", None).expect("bot");
+    let (bot, mut events) = connect(&server, &token);
+    bot.register("roll", "[sides]", "Rolls a die").unwrap();
+    let me = ready(&mut events).await;
+
+    // Users see the command once the bot registers it
+    let mut user = User::login(&server, "test0").await;
+    user.recv_until(|message| match message {
+        ServerMessage::Commands(commands) => commands.iter().any(|command| command.name == "roll"),
+        _ => false,
+    })
+    .await;
+
+    for args in ["", "6"] {
+        let command = ClientMessage::Command {
+            request: 0,
+            chan: 0,
+            name: "roll",
+            args,
+        };
+
+        user.send(&command).await;
+        let command = loop {
+            if let Event::Command(command) = next(&mut events).await.expect("event") {
+                break command;
+            }
+        };
+
+        assert_eq!(command.chan, 0);
+        assert_eq!(command.name, "roll");
+        assert_eq!(
+            command.command().args(),
+            args.split_whitespace().collect::<Vec<_>>()
+        );
+        match command.args.as_str() {
+            "" => bot
+                .reply_privately(&command, "Usage: /roll [sides]")
+                .unwrap(),
+            sides => bot.reply(&command, format!("rolled 4 of {sides}")).unwrap(),
+        }
+    }
+
+    // A private reply comes as a notice, a public one as a message of the bot
+    let notice = user
+        .recv_until(|message| matches!(message, ServerMessage::Notice { .. }))
+        .await;
+
+    assert!(matches!(
+        notice,
+        ServerMessage::Notice { chan: 0, text } if text == "Usage: /roll [sides]"
+    ));
+
+    let message = user
+        .recv_until(
+            |message| matches!(message, ServerMessage::Message(message) if message.from == me),
+        )
+        .await;
+
+    let ServerMessage::Message(message) = message else {
+        unreachable!();
+    };
+
+    assert!(matches!(message.content, MessageType::Text(text) if text == "rolled 4 of 6"));
+
+    // Commands are gone with the bot
+    bot.close();
+    while next(&mut events).await.is_some() {}
+    user.recv_until(|message| match message {
+        ServerMessage::Commands(commands) => commands.iter().all(|command| command.name != "roll"),
+        _ => false,
+    })
+    .await;
+
+    user.send(&ClientMessage::Command {
+        request: 0,
+        chan: 0,
+        name: "roll",
+        args: "",
+    })
+    .await;
+
+    let notice = user
+        .recv_until(|message| matches!(message, ServerMessage::Notice { .. }))
+        .await;
+
+    assert!(matches!(
+        notice,
+        ServerMessage::Notice { text, .. } if text == "Unknown command /roll"
+    ));
+
+    server.shutdown().await;
+    let _ = std::fs::remove_dir_all(data);
+}
+
+#[tokio::test]
+async fn reply_errors() {
+    let data = temp_dir("bot-reply-errors");
+    let server = server("127.0.0.1:0", &data).await;
+    let (_, token) = server
+        .service()
+        .create_bot(ADMIN, "dice", None)
+        .expect("bot");
+
+    let mut bot = User::bot(&server, &token).await;
+    bot.send(&ClientMessage::RegisterCommand {
+        name: "roll",
+        usage: "",
+        description: "Rolls a die",
+    })
+    .await;
+
+    let mut admin = User::login(&server, "admin").await;
+    admin
+        .recv_until(|message| match message {
+            ServerMessage::Commands(commands) => {
+                commands.iter().any(|command| command.name == "roll")
+            }
+            _ => false,
+        })
+        .await;
+
+    admin
+        .send(&ClientMessage::Command {
+            request: 0,
+            chan: 0,
+            name: "mute",
+            args: "dice",
+        })
+        .await;
+
+    bot.recv_until(|message| matches!(message, ServerMessage::Notice { .. }))
+        .await;
+
+    admin
+        .send(&ClientMessage::Command {
+            request: 0,
+            chan: 1,
+            name: "roll",
+            args: "",
+        })
+        .await;
+
+    let invoke = bot
+        .recv_until(|message| matches!(message, ServerMessage::Invoke { .. }))
+        .await;
+
+    let ServerMessage::Invoke { id, chan: 1, .. } = invoke else {
+        panic!("unexpected invoke");
+    };
+
+    // The error of the reply comes in the channel of the command
+    bot.send(&ClientMessage::CommandReply {
+        id,
+        text: "rolled 4",
+        public: true,
+    })
+    .await;
+
+    let notice = bot
+        .recv_until(|message| matches!(message, ServerMessage::Notice { .. }))
+        .await;
+
+    assert!(matches!(
+        notice,
+        ServerMessage::Notice { chan: 1, text } if text.starts_with("you're muted for")
+    ));
+
+    server.shutdown().await;
+    let _ = std::fs::remove_dir_all(data);
+}
+
+#[tokio::test]
+async fn reconnects() {
+    let data = temp_dir("bot-reconnects");
+    let server = server("127.0.0.1:0", &data).await;
+    let addr = server.local_addr().expect("address").to_string();
+    let service = server.service();
+    let (bot, token) = service.create_bot(ADMIN, "steady", None).expect("bot");
+    let (_bot, mut events) = connect(&server, &token);
+    ready(&mut events).await;
+    server.shutdown().await;
+
+    // The server tells when to come back
+    let reconnect_after = loop {
+        if let Event::Disconnected { reconnect_after } = next(&mut events).await.expect("event") {
+            break reconnect_after;
+        }
+    };
+
+    assert_eq!(reconnect_after, Duration::from_secs(1));
+
+    // Bots are kept in the data directory, so the token works after a restart
+    let server = self::server(&addr, &data).await;
+    ready(&mut events).await;
+
+    // A revoked token stops the bot the next time it logs in
+    let service = server.service();
+    service.revoke_bot(ADMIN, bot.id).expect("revoke");
+    server.shutdown().await;
+    let server = self::server(&addr, &data).await;
+    loop {
+        match next(&mut events).await {
+            Some(Event::LoginFailed(LoginError::WrongToken)) => break,
+            Some(Event::Ready { .. }) => panic!("logged in with a revoked token"),
+            Some(_) => {}
+            None => panic!("no login error"),
+        }
+    }
+
+    assert!(next(&mut events).await.is_none());
+    server.shutdown().await;
+    let _ = std::fs::remove_dir_all(data);
+}
+
+#[tokio::test]
+async fn revoked() {
+    let data = temp_dir("bot-revoked");
+    let server = server("127.0.0.1:0", &data).await;
+    let service = server.service();
+    let (bot, token) = service.create_bot(ADMIN, "leaky", None).expect("bot");
+    let (_bot, mut events) = connect(&server, &token);
+    ready(&mut events).await;
+
+    // Revoking disconnects the bot right away, and its token doesn't work anymore
+    service.revoke_bot(ADMIN, bot.id).expect("revoke");
+    loop {
+        match next(&mut events).await {
+            Some(Event::LoginFailed(LoginError::WrongToken)) => break,
+            Some(Event::Ready { .. }) => panic!("logged in with a revoked token"),
+            Some(_) => {}
+            None => panic!("no login error"),
+        }
+    }
+
+    assert!(next(&mut events).await.is_none());
+    server.shutdown().await;
+    let _ = std::fs::remove_dir_all(data);
+}
+
+#[tokio::test]
+async fn frequent_logins() {
+    let data = temp_dir("bot-frequent-logins");
+    let server = server("127.0.0.1:0", &data).await;
+    let (_, token) = server
+        .service()
+        .create_bot(ADMIN, "busy", None)
+        .expect("bot");
+
+    // Valid tokens don't spend the login budget, which is 5 attempts by default
+    for _ in 0..10 {
+        let mut bot = User::bot(&server, &token).await;
+        let logged = bot
+            .recv_until(|message| matches!(message, ServerMessage::LoggedIn(_)))
+            .await;
+
+        assert!(matches!(logged, ServerMessage::LoggedIn(Ok(_))));
+    }
+
+    // Unknown ones do
+    let mut logged = None;
+    for _ in 0..6 {
+        let mut bot = User::bot(&server, "nope").await;
+        logged = Some(
+            bot.recv_until(|message| matches!(message, ServerMessage::LoggedIn(_)))
+                .await,
+        );
+    }
+
+    assert!(matches!(
+        logged,
+        Some(ServerMessage::LoggedIn(Err(LoginError::Locked { .. })))
+    ));
+
+    server.shutdown().await;
+    let _ = std::fs::remove_dir_all(data);
+}
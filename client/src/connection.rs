@@ -0,0 +1,163 @@
+use crate::{
+    platform,
+    request::{Reply, Request, Tracker},
+};
+use base::{
+    api::{LoginError, ServerMessage, NO_REQUEST},
+    decode,
+};
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    select, Sink, SinkExt, Stream, StreamExt,
+};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Connects to the websocket url, like `ws://localhost:4567`.
+///
+/// The connection runs in the background, a tokio runtime is needed for it
+/// on native targets. It's closed when both halves are dropped.
+pub async fn connect(url: &str) -> Result<(Sender, Receiver), Error> {
+    let (write, read) = platform::open(url).await?;
+    let (requests, requests_rx) = mpsc::unbounded();
+    let (events_tx, events) = mpsc::unbounded();
+    platform::spawn(run(write, read, requests_rx, events_tx));
+
+    // Ids start after `NO_REQUEST`, so failures of other requests aren't taken for ours
+    let sender = Sender {
+        requests,
+        next_id: Arc::new(AtomicU64::new(NO_REQUEST + 1)),
+    };
+
+    Ok((sender, Receiver { events }))
+}
+
+/// Sends requests, it can be cloned to send from several places.
+#[derive(Clone)]
+pub struct Sender {
+    requests: UnboundedSender<(u64, Request)>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Sender {
+    /// Sends the request and returns its id, a reply to it comes with the id.
+    pub fn send(&self, request: Request) -> Result<u64, Error> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.requests
+            .unbounded_send((id, request))
+            .map_err(|_| Error::Closed)?;
+
+        Ok(id)
+    }
+
+    /// Closes the connection.
+    pub fn close(&self) {
+        self.requests.close_channel();
+    }
+}
+
+/// Receives events of the connection in the order they come.
+pub struct Receiver {
+    events: UnboundedReceiver<Event>,
+}
+
+impl Receiver {
+    /// Waits for the next event, returns `None` when the connection is closed.
+    pub async fn recv(&mut self) -> Option<Event> {
+        self.events.next().await
+    }
+}
+
+pub enum Event {
+    Message(ServerMessage),
+    /// The server answers the request with the id, it comes after the message with the answer.
+    Reply {
+        id: u64,
+        reply: Reply,
+    },
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Connect(String),
+    Closed,
+    Login(LoginError),
+    /// The server answers the request with a reply of another kind.
+    UnexpectedReply(Reply),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Connect(err) => write!(f, "couldn't connect: {err}"),
+            Self::Closed => write!(f, "the connection is closed"),
+            Self::Login(err) => write!(f, "couldn't log in: {err}"),
+            Self::UnexpectedReply(reply) => write!(f, "unexpected reply: {reply:?}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<LoginError> for Error {
+    fn from(err: LoginError) -> Self {
+        Self::Login(err)
+    }
+}
+
+/// Writes requests and reads messages until the connection is closed.
+async fn run<W, R>(
+    mut write: W,
+    read: R,
+    requests: UnboundedReceiver<(u64, Request)>,
+    events: UnboundedSender<Event>,
+) where
+    W: Sink<Vec<u8>> + Unpin,
+    R: Stream<Item = Vec<u8>> + Unpin,
+{
+    let mut tracker = Tracker::default();
+    let mut read = read.fuse();
+    let mut requests = requests.fuse();
+    loop {
+        select! {
+            bytes = read.next() => {
+                let Some(bytes) = bytes else {
+                    break;
+                };
+
+                let Ok(message) = decode::<ServerMessage>(&bytes) else {
+                    continue;
+                };
+
+                // A sign-up is answered like a login but doesn't log in,
+                // so it's only passed as the reply
+                let reply = tracker.reply(&message);
+                let signed_up = matches!(reply, Some((_, Reply::SignedUp(_))));
+                if !signed_up && events.unbounded_send(Event::Message(message)).is_err() {
+                    break;
+                }
+
+                if let Some((id, reply)) = reply {
+                    let _ = events.unbounded_send(Event::Reply { id, reply });
+                }
+            }
+            request = requests.next() => {
+                let Some((id, request)) = request else {
+                    break;
+                };
+
+                tracker.sent(id, &request);
+                if write.send(request.encode(id)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    let _ = write.close().await;
+}
@@ -0,0 +1,132 @@
+//! The voki client core shared by the web and terminal clients.
+//!
+//! It connects to the chat, matches replies to requests and folds server messages
+//! into a [`State`]. The same code runs in the browser and on native targets with tokio.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), client::Error> {
+//! use client::Client;
+//!
+//! let mut client = Client::connect("ws://localhost:4567").await?;
+//! client.login("test0", "test0").await?;
+//! client.say(0, "hi")?;
+//! while client.next().await.is_some() {
+//!     let state = client.state();
+//!     // ..
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod connection;
+mod request;
+pub mod state;
+
+#[cfg_attr(target_arch = "wasm32", path = "wasm.rs")]
+#[cfg_attr(not(target_arch = "wasm32"), path = "native.rs")]
+mod platform;
+
+pub use self::{
+    connection::{connect, Error, Event, Receiver, Sender},
+    request::{Reply, Request},
+    state::State,
+};
+
+/// A connection with the state it builds.
+pub struct Client {
+    sender: Sender,
+    receiver: Receiver,
+    state: State,
+}
+
+impl Client {
+    /// Connects to the websocket url, like `ws://localhost:4567`.
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        let (sender, receiver) = connect(url).await?;
+        Ok(Self {
+            sender,
+            receiver,
+            state: State::default(),
+        })
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Returns the sender, so requests can be sent while the client waits for events.
+    pub fn sender(&self) -> &Sender {
+        &self.sender
+    }
+
+    /// Logs in and returns the user id, events before the reply update the state.
+    pub async fn login(&mut self, name: &str, pass: &str) -> Result<u32, Error> {
+        let login = Request::Login {
+            name: name.into(),
+            pass: pass.into(),
+        };
+
+        self.logged(login).await
+    }
+
+    /// Signs up a new user and logs in as it.
+    pub async fn sign_up(&mut self, name: &str, pass: &str) -> Result<u32, Error> {
+        let sign_up = Request::SignUp {
+            name: name.into(),
+            pass: pass.into(),
+        };
+
+        let id = self.sender.send(sign_up)?;
+        match self.reply(id).await? {
+            Reply::SignedUp(signed) => signed?,
+            reply => return Err(Error::UnexpectedReply(reply)),
+        };
+
+        self.login(name, pass).await
+    }
+
+    async fn logged(&mut self, request: Request) -> Result<u32, Error> {
+        let id = self.sender.send(request)?;
+        match self.reply(id).await? {
+            Reply::LoggedIn(logged) => Ok(logged?),
+            reply => Err(Error::UnexpectedReply(reply)),
+        }
+    }
+
+    /// Posts the text or runs the command, see [`Request::text`].
+    pub fn say(&self, chan: u32, text: &str) -> Result<u64, Error> {
+        self.sender.send(Request::text(chan, text))
+    }
+
+    /// Uploads an image with the extension, like `png`.
+    pub fn upload(&self, chan: u32, ext: &str, bytes: Vec<u8>) -> Result<u64, Error> {
+        let ext = ext.into();
+        self.sender.send(Request::File { chan, ext, bytes })
+    }
+
+    /// Waits for the reply to the request, events before it update the state.
+    pub async fn reply(&mut self, id: u64) -> Result<Reply, Error> {
+        loop {
+            match self.next().await {
+                Some(Event::Reply { id: replied, reply }) if replied == id => return Ok(reply),
+                Some(_) => {}
+                None => return Err(Error::Closed),
+            }
+        }
+    }
+
+    /// Waits for the next event and folds it into the state.
+    /// Returns `None` when the connection is closed.
+    pub async fn next(&mut self) -> Option<Event> {
+        let event = self.receiver.recv().await?;
+        if let Event::Message(message) = &event {
+            self.state.apply(message);
+        }
+
+        Some(event)
+    }
+
+    pub fn close(&self) {
+        self.sender.close();
+    }
+}
@@ -0,0 +1,41 @@
+use crate::Error;
+use futures::{future, Sink, SinkExt, Stream, StreamExt};
+use std::future::Future;
+use websocket::tungstenite::{self, Message};
+
+pub async fn open(
+    url: &str,
+) -> Result<
+    (
+        impl Sink<Vec<u8>> + Send + Unpin,
+        impl Stream<Item = Vec<u8>> + Send + Unpin,
+    ),
+    Error,
+> {
+    let (stream, _) = websocket::connect_async(url)
+        .await
+        .map_err(|err| Error::Connect(err.to_string()))?;
+
+    let (write, read) = stream.split();
+    let write = write.with(|bytes: Vec<u8>| {
+        future::ready(Ok::<_, tungstenite::Error>(Message::Binary(bytes.into())))
+    });
+
+    let read = read
+        .take_while(|message| future::ready(message.is_ok()))
+        .filter_map(|message| {
+            future::ready(match message {
+                Ok(Message::Binary(bytes)) => Some(bytes.to_vec()),
+                _ => None,
+            })
+        });
+
+    Ok((write, read))
+}
+
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(future);
+}
@@ -0,0 +1,184 @@
+use base::{
+    api::{ClientMessage, LoginError, ServerMessage},
+    encode,
+};
+use std::{collections::VecDeque, time::Duration};
+
+/// A request to the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Request {
+    Login {
+        name: String,
+        pass: String,
+    },
+    SignUp {
+        name: String,
+        pass: String,
+    },
+    /// Logs in with the token of a bot account.
+    BotLogin {
+        token: String,
+    },
+    Say {
+        chan: u32,
+        text: String,
+    },
+    Command {
+        chan: u32,
+        name: String,
+        args: String,
+    },
+    File {
+        chan: u32,
+        ext: String,
+        bytes: Vec<u8>,
+    },
+    /// Registers a slash command of a bot.
+    RegisterCommand {
+        name: String,
+        usage: String,
+        description: String,
+    },
+    /// Answers the command a bot is asked to run.
+    CommandReply {
+        id: u64,
+        text: String,
+        public: bool,
+    },
+}
+
+impl Request {
+    /// Posts the text to the channel, or runs the command if it starts with a slash.
+    /// A double slash posts the text with a single one.
+    pub fn text(chan: u32, text: &str) -> Self {
+        match text.strip_prefix('/') {
+            Some(text) if text.starts_with('/') => Self::Say {
+                chan,
+                text: text.into(),
+            },
+            Some(command) => {
+                let (name, args) = command
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((command, ""));
+
+                Self::Command {
+                    chan,
+                    name: name.into(),
+                    args: args.trim().into(),
+                }
+            }
+            None => Self::Say {
+                chan,
+                text: text.into(),
+            },
+        }
+    }
+
+    /// Encodes the request, posts carry the id so their failures can be matched to them.
+    pub fn encode(&self, id: u64) -> Vec<u8> {
+        let request = id;
+        let message = match self {
+            Self::Login { name, pass } => ClientMessage::Login { name, pass },
+            Self::SignUp { name, pass } => ClientMessage::SignUp { name, pass },
+            Self::BotLogin { token } => ClientMessage::BotLogin { token },
+            Self::Say { chan, text } => ClientMessage::Say {
+                request,
+                chan: *chan,
+                text,
+            },
+            Self::Command { chan, name, args } => ClientMessage::Command {
+                request,
+                chan: *chan,
+                name,
+                args,
+            },
+            Self::File { chan, ext, bytes } => ClientMessage::File {
+                request,
+                chan: *chan,
+                ext,
+                bytes,
+            },
+            Self::RegisterCommand {
+                name,
+                usage,
+                description,
+            } => ClientMessage::RegisterCommand {
+                name,
+                usage,
+                description,
+            },
+            Self::CommandReply { id, text, public } => ClientMessage::CommandReply {
+                id: *id,
+                text,
+                public: *public,
+            },
+        };
+
+        let mut buf = Vec::with_capacity(64);
+        encode(&message, &mut buf).expect("encode");
+        buf
+    }
+}
+
+/// How the server answers a request.
+///
+/// Logins and sign-ups are always answered. Posts are answered only if they fail
+/// with [`Reply::RateLimited`] or [`Reply::TooLarge`], other failures come as notices.
+/// Failures of command replies aren't matched to a request, they come with
+/// [`NO_REQUEST`](base::api::NO_REQUEST).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reply {
+    LoggedIn(Result<u32, LoginError>),
+    /// The account is created with the id, the user logs in with it then.
+    SignedUp(Result<u32, LoginError>),
+    RateLimited {
+        retry_after: Duration,
+    },
+    TooLarge {
+        max_size: u32,
+    },
+}
+
+/// Matches server replies to the requests they answer.
+///
+/// The server answers logins and sign-ups in order,
+/// failures of posts come with the id the post is sent with.
+#[derive(Default)]
+pub(crate) struct Tracker {
+    /// Ids of logins and sign-ups with `true` for the latter.
+    logins: VecDeque<(u64, bool)>,
+}
+
+impl Tracker {
+    pub fn sent(&mut self, id: u64, request: &Request) {
+        match request {
+            Request::Login { .. } | Request::BotLogin { .. } => self.logins.push_back((id, false)),
+            Request::SignUp { .. } => self.logins.push_back((id, true)),
+            Request::Say { .. }
+            | Request::Command { .. }
+            | Request::File { .. }
+            | Request::RegisterCommand { .. }
+            | Request::CommandReply { .. } => {}
+        }
+    }
+
+    pub fn reply(&mut self, message: &ServerMessage) -> Option<(u64, Reply)> {
+        match *message {
+            ServerMessage::LoggedIn(logged) => match self.logins.pop_front()? {
+                (id, false) => Some((id, Reply::LoggedIn(logged))),
+                (id, true) => Some((id, Reply::SignedUp(logged))),
+            },
+            ServerMessage::RateLimited {
+                request,
+                retry_after_ms,
+            } => {
+                let retry_after = Duration::from_millis(retry_after_ms.into());
+                Some((request, Reply::RateLimited { retry_after }))
+            }
+            ServerMessage::TooLarge { request, max_size } => {
+                Some((request, Reply::TooLarge { max_size }))
+            }
+            _ => None,
+        }
+    }
+}
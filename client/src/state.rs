@@ -0,0 +1,315 @@
+use base::api::{self, Author, CommandInfo, MessageType, ServerMessage, NOBODY};
+use im::{HashMap, OrdMap, Vector};
+use std::{fmt, rc::Rc};
+
+#[derive(Clone, PartialEq)]
+pub enum MessageContent {
+    Text(Rc<str>),
+    File {
+        thumb: Rc<str>,
+        orig: Rc<str>,
+    },
+    /// Something the sender does, posted with `/me`.
+    Action(Rc<str>),
+    /// A command result only this user sees.
+    Notice(Rc<str>),
+}
+
+impl From<MessageType> for MessageContent {
+    fn from(message: MessageType) -> Self {
+        match message {
+            MessageType::Text(text) => MessageContent::Text(text.into()),
+            MessageType::File { thumb, orig } => MessageContent::File {
+                thumb: thumb.into(),
+                orig: orig.into(),
+            },
+            MessageType::Action(text) => MessageContent::Action(text.into()),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Message {
+    pub from: u32,
+    pub content: MessageContent,
+    /// Shown instead of the sender, set for webhook messages.
+    pub author: Option<User>,
+}
+
+impl Message {
+    /// A command result from the server only this user sees.
+    pub fn notice(text: &str) -> Self {
+        Self {
+            from: NOBODY,
+            content: MessageContent::Notice(text.into()),
+            author: Some(User {
+                name: "voki".into(),
+                avatar: None,
+            }),
+        }
+    }
+}
+
+impl From<&api::Message> for Message {
+    fn from(message: &api::Message) -> Self {
+        Self {
+            from: message.from,
+            content: message.content.clone().into(),
+            author: message.author.clone().map(Into::into),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Channel {
+    name: Rc<str>,
+    icon: Option<Rc<str>>,
+    topic: Option<Rc<str>>,
+    messages: Vector<Message>,
+}
+
+impl Channel {
+    pub fn new(name: &str, icon: Option<&str>, topic: Option<&str>) -> Self {
+        Self {
+            name: name.into(),
+            icon: icon.map(Into::into),
+            topic: topic.map(Into::into),
+            messages: Vector::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn icon(&self) -> Option<&str> {
+        self.icon.as_ref().map(Rc::as_ref)
+    }
+
+    pub fn last_message(&self) -> LastMessage<'_> {
+        self.messages
+            .last()
+            .map(|message| match &message.content {
+                MessageContent::Text(text)
+                | MessageContent::Action(text)
+                | MessageContent::Notice(text) => text.as_ref(),
+                MessageContent::File { .. } => "..",
+            })
+            .unwrap_or_default()
+            .into()
+    }
+}
+
+pub struct LastMessage<'a>(&'a str);
+
+impl LastMessage<'_> {
+    const LEN: usize = 14;
+}
+
+impl fmt::Display for LastMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.chars().count() {
+            0 => Ok(()),
+            Self::LEN => write!(f, "{} ..", self.0),
+            _ => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for LastMessage<'a> {
+    fn from(text: &'a str) -> Self {
+        if text.trim().is_empty() {
+            Self("")
+        } else {
+            let len = text.chars().take(Self::LEN).map(char::len_utf8).sum();
+            Self(&text[..len])
+        }
+    }
+}
+
+impl PartialEq for Channel {
+    fn eq(&self, rhs: &Self) -> bool {
+        fn possibly_eq<T: Clone + PartialEq>(lhs: &Vector<T>, rhs: &Vector<T>) -> bool {
+            if lhs.len() != rhs.len() {
+                return false;
+            }
+
+            if lhs.is_inline() {
+                lhs == rhs
+            } else {
+                lhs.ptr_eq(rhs)
+            }
+        }
+
+        self.name == rhs.name
+            && self.icon == rhs.icon
+            && self.topic == rhs.topic
+            && possibly_eq(&self.messages, &rhs.messages)
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct User {
+    pub name: Rc<str>,
+    pub avatar: Option<Rc<str>>,
+}
+
+impl From<Author> for User {
+    fn from(author: Author) -> Self {
+        Self {
+            name: author.name.into(),
+            avatar: author.avatar.map(Into::into),
+        }
+    }
+}
+
+impl Default for User {
+    fn default() -> Self {
+        Self {
+            name: "unknown".into(),
+            avatar: None,
+        }
+    }
+}
+
+/// A slash command the user can run.
+#[derive(Clone, PartialEq)]
+pub struct Command {
+    pub name: Rc<str>,
+    pub usage: Rc<str>,
+    pub description: Rc<str>,
+}
+
+impl From<CommandInfo> for Command {
+    fn from(info: CommandInfo) -> Self {
+        Self {
+            name: info.name.into(),
+            usage: info.usage.into(),
+            description: info.description.into(),
+        }
+    }
+}
+
+/// Everything the client knows about the chat, built from server messages.
+#[derive(Default, PartialEq)]
+pub struct State {
+    channels: OrdMap<u32, Channel>,
+    users: HashMap<u32, User>,
+    commands: Vector<Command>,
+    pub retry: bool,
+    login: Option<u32>,
+}
+
+impl State {
+    /// Folds the server message into the state.
+    /// Returns `false` if the message doesn't change it.
+    pub fn apply(&mut self, message: &ServerMessage) -> bool {
+        match message {
+            ServerMessage::LoggedIn(Ok(id)) => self.set_login(*id),
+            ServerMessage::LoggedIn(Err(_)) => self.retry = true,
+            ServerMessage::User(user) => self.push_user(
+                user.id,
+                User {
+                    name: user.name.as_str().into(),
+                    avatar: user.avatar.as_deref().map(Into::into),
+                },
+            ),
+            ServerMessage::Channel(chan) => {
+                let icon = chan.icon.as_deref();
+                let topic = chan.topic.as_deref();
+                self.push_channel(chan.id, Channel::new(&chan.name, icon, topic));
+                for message in &chan.history {
+                    self.push_message(message.chan, message.into());
+                }
+            }
+            ServerMessage::Message(message) => self.push_message(message.chan, message.into()),
+            ServerMessage::Commands(commands) => {
+                let commands = commands.iter().cloned().map(Into::into).collect();
+                self.set_commands(commands);
+            }
+            ServerMessage::Notice { chan, text } => self.push_message(*chan, Message::notice(text)),
+            ServerMessage::Topic { chan, topic } => self.set_topic(*chan, topic.as_deref()),
+            ServerMessage::Closed
+            | ServerMessage::ServerShutdown { .. }
+            | ServerMessage::RateLimited { .. }
+            | ServerMessage::TooLarge { .. }
+            | ServerMessage::Invoke { .. } => return false,
+        }
+
+        true
+    }
+
+    pub fn login(&self) -> Option<u32> {
+        self.login
+    }
+
+    pub fn set_login(&mut self, id: u32) {
+        self.login = Some(id);
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = &Channel> {
+        self.channels.values()
+    }
+
+    /// Returns messages of the channel grouped by consecutive senders.
+    pub fn messages(&self, chan: u32) -> Vector<(User, Vector<MessageContent>)> {
+        use itertools::Itertools;
+
+        self.channels
+            .get(&chan)
+            .map(|chan| {
+                chan.messages
+                    .iter()
+                    .group_by(|message| (message.from, message.author.clone()))
+                    .into_iter()
+                    .map(|((from, author), messages)| {
+                        let user = author
+                            .or_else(|| self.user(from).cloned())
+                            .unwrap_or_default();
+
+                        (
+                            user,
+                            messages.map(|message| &message.content).cloned().collect(),
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn topic(&self, chan: u32) -> Option<Rc<str>> {
+        self.channels.get(&chan).and_then(|chan| chan.topic.clone())
+    }
+
+    pub fn set_topic(&mut self, chan: u32, topic: Option<&str>) {
+        if let Some(chan) = self.channels.get_mut(&chan) {
+            chan.topic = topic.map(Into::into);
+        }
+    }
+
+    pub fn commands(&self) -> Vector<Command> {
+        self.commands.clone()
+    }
+
+    pub fn set_commands(&mut self, commands: Vector<Command>) {
+        self.commands = commands;
+    }
+
+    pub fn user(&self, user: u32) -> Option<&User> {
+        self.users.get(&user)
+    }
+
+    pub fn push_channel(&mut self, id: u32, chan: Channel) {
+        self.channels.insert(id, chan);
+    }
+
+    pub fn push_message(&mut self, chan: u32, message: Message) {
+        if let Some(chan) = self.channels.get_mut(&chan) {
+            chan.messages.push_back(message);
+        }
+    }
+
+    pub fn push_user(&mut self, id: u32, user: User) {
+        self.users.insert(id, user);
+    }
+}
@@ -0,0 +1,37 @@
+use crate::Error;
+use futures::{future, Sink, SinkExt, Stream, StreamExt};
+use gloo_net::websocket::{futures::WebSocket, Message, WebSocketError};
+use std::future::Future;
+
+pub async fn open(
+    url: &str,
+) -> Result<
+    (
+        impl Sink<Vec<u8>> + Unpin,
+        impl Stream<Item = Vec<u8>> + Unpin,
+    ),
+    Error,
+> {
+    let socket = WebSocket::open(url).map_err(|err| Error::Connect(err.to_string()))?;
+    let (write, read) = socket.split();
+    let write =
+        write.with(|bytes: Vec<u8>| future::ready(Ok::<_, WebSocketError>(Message::Bytes(bytes))));
+
+    let read = read
+        .take_while(|message| future::ready(message.is_ok()))
+        .filter_map(|message| {
+            future::ready(match message {
+                Ok(Message::Bytes(bytes)) => Some(bytes),
+                _ => None,
+            })
+        });
+
+    Ok((write, read))
+}
+
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + 'static,
+{
+    wasm_bindgen_futures::spawn_local(future);
+}
@@ -0,0 +1,183 @@
+use base::api::{self, LoginError, MessageType, ServerMessage};
+use client::{state::MessageContent, Client, Error, Event, Reply, Request, State};
+use server::Server;
+use std::time::Duration;
+use tokio::time;
+
+async fn server() -> Server {
+    Server::builder()
+        .address("127.0.0.1:0")
+        .configure(|config| config.limits.text_size = 64)
+        .bind()
+        .await
+        .expect("bind")
+}
+
+async fn connect(server: &Server) -> Client {
+    let url = format!("ws://{}", server.local_addr().expect("address"));
+    Client::connect(&url).await.expect("connect")
+}
+
+/// Waits until the client state matches the predicate.
+async fn until<F>(client: &mut Client, mut f: F)
+where
+    F: FnMut(&State) -> bool,
+{
+    let wait = async {
+        while !f(client.state()) {
+            client.next().await.expect("event");
+        }
+    };
+
+    time::timeout(Duration::from_secs(10), wait)
+        .await
+        .expect("state in time");
+}
+
+fn text(text: &str) -> MessageContent {
+    MessageContent::Text(text.into())
+}
+
+#[test]
+fn requests() {
+    assert_eq!(
+        Request::text(1, "hi"),
+        Request::Say {
+            chan: 1,
+            text: "hi".into()
+        }
+    );
+
+    assert_eq!(
+        Request::text(1, "/topic  Releases "),
+        Request::Command {
+            chan: 1,
+            name: "topic".into(),
+            args: "Releases".into()
+        }
+    );
+
+    assert_eq!(
+        Request::text(1, "//slash"),
+        Request::Say {
+            chan: 1,
+            text: "/slash".into()
+        }
+    );
+}
+
+#[test]
+fn state() {
+    let message = |from, text: &str| api::Message {
+        from,
+        chan: 0,
+        content: MessageType::Text(text.into()),
+        author: None,
+    };
+
+    let mut state = State::default();
+    let user = |id, name: &str| api::User {
+        id,
+        name: name.into(),
+        avatar: None,
+        bot: false,
+    };
+
+    assert!(state.apply(&ServerMessage::LoggedIn(Ok(1))));
+    assert!(state.apply(&ServerMessage::User(user(1, "alice"))));
+    assert!(state.apply(&ServerMessage::User(user(2, "bob"))));
+    assert!(state.apply(&ServerMessage::Channel(api::Channel {
+        id: 0,
+        name: "general".into(),
+        icon: None,
+        topic: None,
+        history: vec![message(1, "hi"), message(1, "there")],
+    })));
+
+    assert!(state.apply(&ServerMessage::Message(message(2, "hello"))));
+    assert!(state.apply(&ServerMessage::Topic {
+        chan: 0,
+        topic: Some("Releases".into()),
+    }));
+
+    assert!(!state.apply(&ServerMessage::TooLarge {
+        request: 0,
+        max_size: 1,
+    }));
+    assert_eq!(state.login(), Some(1));
+    assert_eq!(state.topic(0).as_deref(), Some("Releases"));
+
+    // Messages are grouped by consecutive senders
+    let messages = state.messages(0);
+    assert_eq!(messages.len(), 2);
+    let (alice, rows) = &messages[0];
+    assert_eq!(&*alice.name, "alice");
+    assert!(rows.iter().eq(&[text("hi"), text("there")]));
+    let (bob, rows) = &messages[1];
+    assert_eq!(&*bob.name, "bob");
+    assert!(rows.iter().eq(&[text("hello")]));
+
+    let chan = state.channels().next().expect("channel");
+    assert_eq!(chan.name(), "general");
+    assert_eq!(chan.last_message().to_string(), "hello");
+    assert!(state.user(3).is_none());
+}
+
+#[tokio::test]
+async fn session() {
+    let server = server().await;
+
+    let mut client = connect(&server).await;
+    let err = client.login("test0", "wrong").await.unwrap_err();
+    assert!(matches!(err, Error::Login(LoginError::WrongNameOrPass)));
+
+    let alice = client.login("test0", "test0").await.expect("login");
+    let mut bob = connect(&server).await;
+    let bob_id = bob.login("test1", "test1").await.expect("login");
+    until(&mut bob, |state| state.channels().count() > 0).await;
+
+    // Posts of one client reach the state of the other
+    client.say(0, "hi").unwrap();
+    client.say(0, "there").unwrap();
+    until(&mut bob, |state| {
+        state
+            .messages(0)
+            .last()
+            .is_some_and(|(_, rows)| rows.len() == 2)
+    })
+    .await;
+
+    let messages = bob.state().messages(0);
+    let (user, _) = messages.last().expect("messages");
+    assert_eq!(&*user.name, "test0");
+    assert_eq!(bob.state().login(), Some(bob_id));
+    assert_ne!(alice, bob_id);
+
+    // A sign-up is followed by a login
+    let mut carol = connect(&server).await;
+    let carol_id = carol.sign_up("carol", "carol").await.expect("sign up");
+    assert_eq!(carol.state().login(), Some(carol_id));
+    let err = carol.sign_up("carol", "carol").await.unwrap_err();
+    assert!(matches!(err, Error::Login(LoginError::AlreadyLogged)));
+
+    // Failures are matched to the request, even if another one is sent before the reply
+    let id = client.say(0, &"x".repeat(100)).unwrap();
+    assert_ne!(id, api::NO_REQUEST);
+    client.say(0, "fits").unwrap();
+    let reply = client.reply(id).await.expect("reply");
+    assert_eq!(reply, Reply::TooLarge { max_size: 64 });
+
+    // Commands go through the same requests
+    client.say(0, "/topic Releases").unwrap();
+    until(&mut bob, |state| {
+        state.topic(0).as_deref() == Some("Releases")
+    })
+    .await;
+
+    client.close();
+    while let Some(event) = client.next().await {
+        assert!(matches!(event, Event::Message(_) | Event::Reply { .. }));
+    }
+
+    server.shutdown().await;
+}
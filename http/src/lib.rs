@@ -1,7 +1,24 @@
+mod probe;
+mod rest;
+mod socket;
+mod supervise;
+
+pub use self::{
+    probe::{health, ready, Chat, Health},
+    rest::{
+        bots, channels, create_bot, create_hook, history, hooks, openapi, post_hook, revoke_bot,
+        revoke_hook, say, users,
+    },
+    socket::ws,
+    supervise::Supervisor,
+};
+
 use rocket::{
     get,
+    http::ContentType,
     serde::{json::Json, Serialize},
 };
+use server::metrics::METRICS;
 
 #[derive(Serialize)]
 #[serde(crate = "rocket::serde")]
@@ -14,3 +31,10 @@ pub async fn index() -> Json<Message<'static>> {
     let message = Message { text: "hello" };
     message.into()
 }
+
+/// Metrics of the embedded chat server in the Prometheus text format.
+#[get("/metrics")]
+pub async fn metrics() -> (ContentType, String) {
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    (content_type, METRICS.render())
+}
@@ -1,12 +1,115 @@
-use http::index;
-use rocket::{fs::FileServer, launch, routes};
-use std::process::Command;
+use http::{
+    bots, channels, create_bot, create_hook, health, history, hooks, index, metrics, openapi,
+    post_hook, ready, revoke_bot, revoke_hook, say, users, ws, Chat, Health, Supervisor,
+};
+use rocket::{fairing::AdHoc, fs::FileServer, routes, Build, Rocket};
+use server::{
+    config::{Config, LogFormat},
+    Error, ServerBuilder,
+};
+use std::{path::PathBuf, process::ExitCode, sync::Arc, time::Duration};
+use tokio::process::Command;
 
-#[launch]
-async fn rocket() -> _ {
-    Command::new("./server").spawn().expect("run server");
+#[rocket::main]
+async fn main() -> ExitCode {
+    let rocket = match rocket() {
+        Ok(rocket) => rocket,
+        Err(err) => {
+            eprintln!("error: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
 
-    rocket::build()
+    match rocket.launch().await {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Builds the http server, failing if the chat can't be configured or started.
+fn rocket() -> Result<Rocket<Build>, Error> {
+    let figment = rocket::Config::figment();
+    let config_path = figment.extract_inner::<PathBuf>("chat_config").ok();
+    let config = match &config_path {
+        Some(path) => Config::read(path)?,
+        None => Config::default(),
+    };
+
+    // Logs of Rocket and the chat server are written together,
+    // set `log_format = "json"` to get a JSON object per line
+    let log_format = figment
+        .extract_inner::<LogFormat>("log_format")
+        .unwrap_or(config.log_format);
+
+    // Rocket's own logger is installed when it's built, so this goes first
+    server::log::init(config.log_level, log_format);
+
+    // By default the chat server runs in this process and websockets
+    // are served at `/ws`, set `embed_server = false` to run it aside
+    let embed = figment.extract_inner("embed_server").unwrap_or(true);
+    let rocket = rocket::custom(figment)
         .mount("/", FileServer::from("./static"))
-        .mount("/api", routes![index])
+        .mount("/api", routes![index, health, ready]);
+
+    let store = config.storage.store();
+    if embed {
+        let server = ServerBuilder::from_config(config).start()?;
+
+        // The REST API shares the state with the chat, so it needs the embedded one
+        let rocket = rocket
+            .manage(server.acceptor())
+            .manage(server.service())
+            .manage(Health::new(Chat::Embedded(server.acceptor()), store))
+            .mount("/", routes![metrics, ws])
+            .mount(
+                "/api",
+                routes![
+                    channels,
+                    history,
+                    say,
+                    users,
+                    openapi,
+                    create_hook,
+                    hooks,
+                    revoke_hook,
+                    post_hook,
+                    create_bot,
+                    bots,
+                    revoke_bot
+                ],
+            )
+            .attach(AdHoc::on_shutdown("Chat server", |_| {
+                Box::pin(server.shutdown())
+            }));
+
+        Ok(rocket)
+    } else {
+        // The server is restarted if it crashes and stopped with Rocket
+        let command = move || {
+            let mut command = Command::new("./server");
+            command.env("VOKI_LOG_FORMAT", log_format.as_str());
+            if let Some(path) = &config_path {
+                command.env("VOKI_CONFIG", path);
+            }
+
+            command
+        };
+
+        // Give the server a second more than it waits for connections to close
+        let grace = Duration::from_secs(config.shutdown.timeout + 1);
+        let supervisor = Supervisor::spawn(command, grace);
+        let health = Health::new(Chat::External(config.address), store)
+            .with_supervisor(Arc::clone(&supervisor));
+
+        let rocket = rocket
+            .manage(health)
+            .attach(AdHoc::on_shutdown("Chat server", |_| {
+                Box::pin(async move { supervisor.shutdown().await })
+            }));
+
+        Ok(rocket)
+    }
 }
@@ -0,0 +1,136 @@
+use crate::Supervisor;
+use rocket::{
+    get,
+    http::Status,
+    serde::{json::Json, Serialize},
+    tokio::{net::TcpStream, time},
+    State,
+};
+use server::{store::BlobStore, Acceptor};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// How long a check can take before it's considered failed.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What the health endpoints check.
+pub struct Health {
+    started: Instant,
+    chat: Chat,
+    store: Arc<dyn BlobStore>,
+    supervisor: Option<Arc<Supervisor>>,
+}
+
+/// Where the chat server runs.
+pub enum Chat {
+    /// In this process, serving websockets at `/ws`.
+    Embedded(Acceptor),
+    /// In another process listening at the address.
+    External(String),
+}
+
+impl Health {
+    pub fn new(chat: Chat, store: Arc<dyn BlobStore>) -> Self {
+        Self {
+            started: Instant::now(),
+            chat,
+            store,
+            supervisor: None,
+        }
+    }
+
+    /// Reports restarts of the supervised chat server.
+    pub fn with_supervisor(mut self, supervisor: Arc<Supervisor>) -> Self {
+        self.supervisor = Some(supervisor);
+        self
+    }
+
+    fn report(&self, status: &'static str, errors: Vec<String>) -> Report {
+        Report {
+            status,
+            uptime_secs: self.started.elapsed().as_secs(),
+            restarts: self
+                .supervisor
+                .as_ref()
+                .map(|supervisor| supervisor.restarts()),
+            errors,
+        }
+    }
+
+    async fn chat(&self) -> Result<(), String> {
+        match &self.chat {
+            Chat::Embedded(acceptor) => {
+                if acceptor.is_serving() {
+                    Ok(())
+                } else {
+                    Err("chat server is shutting down".into())
+                }
+            }
+            Chat::External(_) if self.supervisor.as_ref().is_some_and(|sv| !sv.is_running()) => {
+                Err("chat server isn't running".into())
+            }
+            Chat::External(address) => {
+                let address = local(address);
+                match time::timeout(CHECK_TIMEOUT, TcpStream::connect(&address)).await {
+                    Ok(Ok(_)) => Ok(()),
+                    Ok(Err(err)) => Err(format!("chat server at {address}: {err}")),
+                    Err(_) => Err(format!("chat server at {address}: timed out")),
+                }
+            }
+        }
+    }
+
+    async fn storage(&self) -> Result<(), String> {
+        match time::timeout(CHECK_TIMEOUT, self.store.check()).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(err)) => Err(format!("storage: {err}")),
+            Err(_) => Err("storage: timed out".into()),
+        }
+    }
+}
+
+/// The address to connect to a server listening at the address.
+fn local(address: &str) -> String {
+    match address.rsplit_once(':') {
+        Some(("0.0.0.0", port)) => format!("127.0.0.1:{port}"),
+        Some(("[::]", port)) => format!("[::1]:{port}"),
+        _ => address.to_owned(),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Report {
+    status: &'static str,
+    uptime_secs: u64,
+    /// How many times the supervised chat server has been restarted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    restarts: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<String>,
+}
+
+/// Reports the http server is up, for liveness checks.
+#[get("/health")]
+pub async fn health(health: &State<Health>) -> Json<Report> {
+    Json(health.report("ok", vec![]))
+}
+
+/// Reports whether the chat server is reachable and the storage is writable,
+/// responds with 503 if something isn't.
+#[get("/ready")]
+pub async fn ready(health: &State<Health>) -> (Status, Json<Report>) {
+    let (chat, storage) = rocket::tokio::join!(health.chat(), health.storage());
+    let errors: Vec<_> = [chat, storage]
+        .into_iter()
+        .filter_map(Result::err)
+        .collect();
+    if errors.is_empty() {
+        (Status::Ok, Json(health.report("ok", errors)))
+    } else {
+        let report = health.report("unavailable", errors);
+        (Status::ServiceUnavailable, Json(report))
+    }
+}
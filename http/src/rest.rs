@@ -0,0 +1,569 @@
+use base::api::{self, LoginError, MessageType};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rocket::{
+    delete, get,
+    http::{ContentType, Header, Status},
+    post,
+    request::{FromRequest, Outcome, Request},
+    response::{self, Responder},
+    serde::{
+        json::{self, Json},
+        Deserialize, Serialize,
+    },
+    State,
+};
+use server::service::{Bot, BotError, Hook, HookError, Post, PostError, Service};
+use std::net::IpAddr;
+use tracing::error;
+
+/// How many messages a page of history has by default and at most.
+const PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 100;
+
+/// The OpenAPI description of the endpoints.
+const SPEC: &str = include_str!("../openapi.json");
+
+/// A user authenticated with the `Authorization: Basic` header,
+/// or a bot with its token in the `Authorization: Bearer` one.
+pub struct Auth {
+    user: u32,
+    ip: IpAddr,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Auth {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let service = match req.rocket().state::<Service>() {
+            Some(service) => service,
+            None => {
+                return fail(ApiError::new(
+                    Status::ServiceUnavailable,
+                    "chat isn't served",
+                ))
+            }
+        };
+
+        let Some(ip) = req.client_ip() else {
+            return fail(ApiError::new(Status::BadRequest, "unknown client address"));
+        };
+
+        let header = req.headers().get_one("Authorization");
+        if let Some(token) = header.and_then(|header| header.strip_prefix("Bearer ")) {
+            return match service.verify_bot(token.trim(), ip) {
+                Ok(user) => Outcome::Success(Self { user, ip }),
+                Err(err) => fail(err.into()),
+            };
+        }
+
+        let credentials = header
+            .and_then(|header| header.strip_prefix("Basic "))
+            .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+            .and_then(|decoded| String::from_utf8(decoded).ok());
+
+        let Some((name, pass)) = credentials.as_deref().and_then(|cred| cred.split_once(':'))
+        else {
+            return fail(ApiError::unauthorized("basic authorization is required"));
+        };
+
+        match service.authenticate(name, pass, ip) {
+            Ok(user) => Outcome::Success(Self { user, ip }),
+            Err(err) => fail(err.into()),
+        }
+    }
+}
+
+/// The token of a webhook in the `X-Webhook-Token` header.
+/// It isn't in the path, so it doesn't end up in the request logs.
+pub struct HookToken<'r>(&'r str);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for HookToken<'r> {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.headers().get_one("X-Webhook-Token") {
+            Some(token) => Outcome::Success(Self(token.trim())),
+            None => fail(ApiError::unauthorized("webhook token is required")),
+        }
+    }
+}
+
+fn fail<T>(err: ApiError) -> Outcome<T, ApiError> {
+    Outcome::Error((err.status, err))
+}
+
+/// An error with a JSON body.
+#[derive(Debug)]
+pub struct ApiError {
+    status: Status,
+    message: String,
+    retry_after: Option<u64>,
+}
+
+impl ApiError {
+    fn new<S>(status: Status, message: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            status,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    fn unauthorized(message: &str) -> Self {
+        Self::new(Status::Unauthorized, message)
+    }
+
+    fn not_found() -> Self {
+        Self::new(Status::NotFound, "unknown channel")
+    }
+
+    fn retry_after(mut self, secs: u64) -> Self {
+        self.retry_after = Some(secs);
+        self
+    }
+}
+
+impl From<LoginError> for ApiError {
+    fn from(err: LoginError) -> Self {
+        match err {
+            LoginError::Locked { retry_after_secs } => {
+                Self::new(Status::TooManyRequests, "too many attempts")
+                    .retry_after(retry_after_secs.into())
+            }
+            LoginError::WrongToken => Self::unauthorized("wrong token"),
+            _ => Self::unauthorized("wrong name or password"),
+        }
+    }
+}
+
+impl From<PostError> for ApiError {
+    fn from(err: PostError) -> Self {
+        let message = err.to_string();
+        match err {
+            PostError::UnknownChannel => Self::not_found(),
+            PostError::TooLarge { .. } => Self::new(Status::PayloadTooLarge, message),
+            PostError::RateLimited { retry_after } => {
+                let secs = retry_after.as_secs_f64().ceil() as u64;
+                Self::new(Status::TooManyRequests, message).retry_after(secs)
+            }
+            PostError::Muted { .. } => Self::new(Status::Forbidden, message),
+        }
+    }
+}
+
+impl From<HookError> for ApiError {
+    fn from(err: HookError) -> Self {
+        let message = err.to_string();
+        match err {
+            HookError::NotAdmin => Self::new(Status::Forbidden, message),
+            HookError::UnknownChannel => Self::not_found(),
+            HookError::UnknownHook => Self::new(Status::NotFound, message),
+            HookError::Post(err) => err.into(),
+            HookError::Save(_) => {
+                error!(error = %message, "couldn't save webhooks");
+                Self::new(Status::InternalServerError, "couldn't save webhooks")
+            }
+        }
+    }
+}
+
+impl From<BotError> for ApiError {
+    fn from(err: BotError) -> Self {
+        let message = err.to_string();
+        match err {
+            BotError::NotAdmin => Self::new(Status::Forbidden, message),
+            BotError::UnknownBot => Self::new(Status::NotFound, message),
+            BotError::Save(_) => {
+                error!(error = %message, "couldn't save bots");
+                Self::new(Status::InternalServerError, "couldn't save bots")
+            }
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        #[derive(Serialize)]
+        #[serde(crate = "rocket::serde")]
+        struct Body {
+            error: String,
+        }
+
+        let mut res = Json(Body {
+            error: self.message,
+        })
+        .respond_to(req)?;
+
+        res.set_status(self.status);
+        if self.status == Status::Unauthorized {
+            res.set_header(Header::new("WWW-Authenticate", r#"Basic realm="voki""#));
+        }
+
+        if let Some(secs) = self.retry_after {
+            res.set_header(Header::new("Retry-After", secs.to_string()));
+        }
+
+        Ok(res)
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Channel {
+    id: u32,
+    name: String,
+    icon: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct User {
+    id: u32,
+    name: String,
+    avatar: Option<String>,
+    bot: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Message {
+    id: u64,
+    from: u32,
+    chan: u32,
+    content: Content,
+    /// Set for webhook messages, which aren't sent by a user.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<Author>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Author {
+    name: String,
+    avatar: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "lowercase")]
+pub enum Content {
+    Text {
+        text: String,
+    },
+    File {
+        thumb: String,
+        orig: String,
+    },
+    /// Something the sender does, posted with `/me`.
+    Action {
+        text: String,
+    },
+}
+
+impl From<Post> for Message {
+    fn from(Post { id, message }: Post) -> Self {
+        Self {
+            id,
+            from: message.from,
+            chan: message.chan,
+            content: match message.content {
+                MessageType::Text(text) => Content::Text { text },
+                MessageType::File { thumb, orig } => Content::File { thumb, orig },
+                MessageType::Action(text) => Content::Action { text },
+            },
+            author: message
+                .author
+                .map(|api::Author { name, avatar }| Author { name, avatar }),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Page {
+    messages: Vec<Message>,
+    /// Pass it as `before` to get the previous page, it's absent on the first one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NewMessage<'a> {
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Webhook {
+    id: u32,
+    chan: u32,
+    name: String,
+    avatar: Option<String>,
+    created_by: u32,
+    /// It's only returned when the webhook is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+impl From<Hook> for Webhook {
+    fn from(hook: Hook) -> Self {
+        Self {
+            id: hook.id,
+            chan: hook.chan,
+            name: hook.name,
+            avatar: hook.avatar,
+            created_by: hook.created_by,
+            token: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NewWebhook<'a> {
+    name: &'a str,
+    avatar: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct HookMessage<'a> {
+    text: &'a str,
+    /// Overrides the webhook name for this message.
+    username: Option<&'a str>,
+    /// Overrides the webhook avatar for this message.
+    avatar: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BotAccount {
+    id: u32,
+    /// The user the bot posts as.
+    user: u32,
+    name: String,
+    avatar: Option<String>,
+    created_by: u32,
+    /// It's only returned when the bot is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+impl From<Bot> for BotAccount {
+    fn from(bot: Bot) -> Self {
+        Self {
+            id: bot.id,
+            user: bot.user,
+            name: bot.name,
+            avatar: bot.avatar,
+            created_by: bot.created_by,
+            token: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NewBot<'a> {
+    name: &'a str,
+    avatar: Option<&'a str>,
+}
+
+fn bad_request(err: json::Error) -> ApiError {
+    ApiError::new(Status::BadRequest, err.to_string())
+}
+
+/// Lists channels.
+#[get("/channels")]
+pub async fn channels(
+    auth: Result<Auth, ApiError>,
+    service: &State<Service>,
+) -> ApiResult<Vec<Channel>> {
+    auth?;
+    let channels = service
+        .channels()
+        .into_iter()
+        .map(|chan| Channel {
+            id: chan.id,
+            name: chan.name,
+            icon: chan.icon,
+        })
+        .collect();
+
+    Ok(Json(channels))
+}
+
+/// Reads the channel history page by page from the newest messages.
+#[get("/channels/<chan>/messages?<before>&<limit>")]
+pub async fn history(
+    auth: Result<Auth, ApiError>,
+    service: &State<Service>,
+    chan: u32,
+    before: Option<u64>,
+    limit: Option<usize>,
+) -> ApiResult<Page> {
+    auth?;
+    let limit = limit.unwrap_or(PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let posts = service
+        .history(chan, before, limit)
+        .await
+        .ok_or_else(ApiError::not_found)?;
+
+    // A full page may have older messages before it
+    let before = match posts.first() {
+        Some(first) if posts.len() == limit && first.id > 0 => Some(first.id),
+        _ => None,
+    };
+
+    let messages = posts.into_iter().map(Message::from).collect();
+    Ok(Json(Page { messages, before }))
+}
+
+/// Posts a text message to the channel as the authenticated user.
+#[post("/channels/<chan>/messages", format = "json", data = "<message>")]
+pub async fn say(
+    auth: Result<Auth, ApiError>,
+    service: &State<Service>,
+    chan: u32,
+    message: Result<Json<NewMessage<'_>>, json::Error<'_>>,
+) -> Result<Status, ApiError> {
+    let auth = auth?;
+    let message = message.map_err(bad_request)?;
+    service.say(auth.user, chan, message.text, auth.ip).await?;
+    Ok(Status::Accepted)
+}
+
+/// Lists users.
+#[get("/users")]
+pub async fn users(auth: Result<Auth, ApiError>, service: &State<Service>) -> ApiResult<Vec<User>> {
+    auth?;
+    let users = service
+        .users()
+        .into_iter()
+        .map(|user| User {
+            id: user.id,
+            name: user.name,
+            avatar: user.avatar,
+            bot: user.bot,
+        })
+        .collect();
+
+    Ok(Json(users))
+}
+
+/// Creates a webhook for the channel, only admins can do it.
+#[post("/channels/<chan>/webhooks", format = "json", data = "<hook>")]
+pub async fn create_hook(
+    auth: Result<Auth, ApiError>,
+    service: &State<Service>,
+    chan: u32,
+    hook: Result<Json<NewWebhook<'_>>, json::Error<'_>>,
+) -> Result<(Status, Json<Webhook>), ApiError> {
+    let auth = auth?;
+    let hook = hook.map_err(bad_request)?;
+    let (hook, token) = service.create_hook(auth.user, chan, hook.name, hook.avatar)?;
+    let hook = Webhook {
+        token: Some(token),
+        ..hook.into()
+    };
+
+    Ok((Status::Created, Json(hook)))
+}
+
+/// Lists webhooks of the channel, only admins can do it.
+#[get("/channels/<chan>/webhooks")]
+pub async fn hooks(
+    auth: Result<Auth, ApiError>,
+    service: &State<Service>,
+    chan: u32,
+) -> ApiResult<Vec<Webhook>> {
+    let auth = auth?;
+    let hooks = service.hooks(auth.user, chan)?;
+    Ok(Json(hooks.into_iter().map(Webhook::from).collect()))
+}
+
+/// Revokes the webhook, only admins can do it.
+#[delete("/webhooks/<id>")]
+pub async fn revoke_hook(
+    auth: Result<Auth, ApiError>,
+    service: &State<Service>,
+    id: u32,
+) -> Result<Status, ApiError> {
+    let auth = auth?;
+    service.revoke_hook(auth.user, id)?;
+    Ok(Status::NoContent)
+}
+
+/// Posts a message with the webhook, the token in the header authenticates it.
+#[post("/webhooks", format = "json", data = "<message>")]
+pub async fn post_hook(
+    service: &State<Service>,
+    token: Result<HookToken<'_>, ApiError>,
+    ip: IpAddr,
+    message: Result<Json<HookMessage<'_>>, json::Error<'_>>,
+) -> Result<Status, ApiError> {
+    let HookMessage {
+        text,
+        username,
+        avatar,
+    } = message.map_err(bad_request)?.into_inner();
+
+    let HookToken(token) = token?;
+    service.post_hook(token, text, username, avatar, ip).await?;
+
+    Ok(Status::Accepted)
+}
+
+/// Creates a bot account, only admins can do it.
+#[post("/bots", format = "json", data = "<bot>")]
+pub async fn create_bot(
+    auth: Result<Auth, ApiError>,
+    service: &State<Service>,
+    bot: Result<Json<NewBot<'_>>, json::Error<'_>>,
+) -> Result<(Status, Json<BotAccount>), ApiError> {
+    let auth = auth?;
+    let bot = bot.map_err(bad_request)?;
+    let (bot, token) = service.create_bot(auth.user, bot.name, bot.avatar)?;
+    let bot = BotAccount {
+        token: Some(token),
+        ..bot.into()
+    };
+
+    Ok((Status::Created, Json(bot)))
+}
+
+/// Lists bot accounts, only admins can do it.
+#[get("/bots")]
+pub async fn bots(
+    auth: Result<Auth, ApiError>,
+    service: &State<Service>,
+) -> ApiResult<Vec<BotAccount>> {
+    let auth = auth?;
+    let bots = service.bots(auth.user)?;
+    Ok(Json(bots.into_iter().map(BotAccount::from).collect()))
+}
+
+/// Revokes the token of the bot, only admins can do it.
+#[delete("/bots/<id>")]
+pub async fn revoke_bot(
+    auth: Result<Auth, ApiError>,
+    service: &State<Service>,
+    id: u32,
+) -> Result<Status, ApiError> {
+    let auth = auth?;
+    service.revoke_bot(auth.user, id)?;
+    Ok(Status::NoContent)
+}
+
+/// Serves the OpenAPI description of the endpoints.
+#[get("/openapi.json")]
+pub async fn openapi() -> (ContentType, &'static str) {
+    (ContentType::JSON, SPEC)
+}
@@ -0,0 +1,75 @@
+use rocket::{
+    data::{IoHandler, IoStream},
+    get,
+    http::Status,
+    request::{FromRequest, Outcome, Request},
+    response::{self, Responder, Response},
+    State,
+};
+use server::Acceptor;
+use std::{io, net::SocketAddr, pin::Pin};
+
+/// A websocket handshake request.
+pub struct Handshake {
+    key: String,
+    addr: SocketAddr,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Handshake {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = req.headers();
+        let upgrade = headers
+            .get_one("Upgrade")
+            .is_some_and(|proto| proto.eq_ignore_ascii_case("websocket"));
+
+        match (upgrade, headers.get_one("Sec-WebSocket-Key"), req.remote()) {
+            (true, Some(key), Some(addr)) => Outcome::Success(Self {
+                key: key.to_owned(),
+                addr,
+            }),
+            _ => Outcome::Error((Status::BadRequest, ())),
+        }
+    }
+}
+
+/// Upgrades the connection and passes it to the chat server.
+#[get("/ws")]
+pub fn ws(handshake: Handshake, acceptor: &State<Acceptor>) -> Socket {
+    Socket {
+        handshake,
+        acceptor: acceptor.inner().clone(),
+    }
+}
+
+pub struct Socket {
+    handshake: Handshake,
+    acceptor: Acceptor,
+}
+
+impl<'r> Responder<'r, 'static> for Socket {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let accept = Acceptor::accept_key(self.handshake.key.as_bytes());
+        Response::build()
+            .raw_header("Sec-WebSocket-Accept", accept)
+            .upgrade("websocket", self)
+            .ok()
+    }
+}
+
+#[rocket::async_trait]
+impl IoHandler for Socket {
+    async fn io(self: Pin<Box<Self>>, io: IoStream) -> io::Result<()> {
+        let Self {
+            handshake,
+            acceptor,
+        } = *Pin::into_inner(self);
+
+        acceptor
+            .serve(io, handshake.addr)
+            .await
+            .map_err(io::Error::other)
+    }
+}
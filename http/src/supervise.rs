@@ -0,0 +1,187 @@
+use std::{
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::{Child, Command},
+    sync::watch,
+    task::JoinHandle,
+    time,
+};
+use tracing::{error, info, warn};
+
+/// The first delay before a restart, it doubles on every crash in a row.
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// A child which runs this long is considered healthy, so the backoff is reset.
+const STABLE_RUN: Duration = Duration::from_secs(30);
+
+/// Runs a child process and restarts it when it exits.
+///
+/// The child writes logs in the same format, so its stdout is passed through
+/// line by line, and stderr lines are logged as warnings.
+pub struct Supervisor {
+    restarts: AtomicU32,
+    running: AtomicBool,
+    stop: watch::Sender<bool>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Supervisor {
+    /// Starts supervising the child made by the command function.
+    /// On shutdown the child gets `SIGTERM` and is killed after the grace period.
+    pub fn spawn<F>(command: F, grace: Duration) -> Arc<Self>
+    where
+        F: Fn() -> Command + Send + 'static,
+    {
+        let (stop, stopped) = watch::channel(false);
+        let supervisor = Arc::new(Self {
+            restarts: AtomicU32::new(0),
+            running: AtomicBool::new(false),
+            stop,
+            task: Mutex::new(None),
+        });
+
+        let task = tokio::spawn(Arc::clone(&supervisor).run(command, grace, stopped));
+        *supervisor.lock() = Some(task);
+        supervisor
+    }
+
+    /// How many times the child has been restarted.
+    pub fn restarts(&self) -> u32 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Stops the child and waits until it exits.
+    pub async fn shutdown(&self) {
+        self.stop.send_replace(true);
+        let task = self.lock().take();
+        if let Some(task) = task {
+            let _ = task.await;
+        }
+    }
+
+    async fn run<F>(
+        self: Arc<Self>,
+        command: F,
+        grace: Duration,
+        mut stopped: watch::Receiver<bool>,
+    ) where
+        F: Fn() -> Command,
+    {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let mut command = command();
+            command
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true);
+
+            let started = Instant::now();
+            match command.spawn() {
+                Ok(mut child) => {
+                    info!(pid = child.id(), "chat server started");
+                    forward(&mut child);
+                    self.running.store(true, Ordering::Relaxed);
+                    let status = tokio::select! {
+                        status = child.wait() => status,
+                        () = stop(&mut stopped) => {
+                            terminate(&mut child, grace).await;
+                            self.running.store(false, Ordering::Relaxed);
+                            return;
+                        }
+                    };
+
+                    self.running.store(false, Ordering::Relaxed);
+                    match status {
+                        Ok(status) => warn!(%status, "chat server exited"),
+                        Err(err) => error!(error = %err, "couldn't wait for chat server"),
+                    }
+                }
+                Err(err) => error!(error = %err, "couldn't start chat server"),
+            }
+
+            if started.elapsed() >= STABLE_RUN {
+                backoff = MIN_BACKOFF;
+            }
+
+            info!(after = ?backoff, "restarting chat server");
+            tokio::select! {
+                () = time::sleep(backoff) => {}
+                () = stop(&mut stopped) => return,
+            }
+
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            self.restarts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<JoinHandle<()>>> {
+        self.task.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Waits until the supervisor is asked to stop.
+async fn stop(stopped: &mut watch::Receiver<bool>) {
+    let _ = stopped.wait_for(|&stop| stop).await;
+}
+
+/// Writes the output of the child to the logs.
+fn forward(child: &mut Child) {
+    fn lines<R, F>(output: R, write: F)
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        F: Fn(String) + Send + 'static,
+    {
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(output).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                write(line);
+            }
+        });
+    }
+
+    if let Some(stdout) = child.stdout.take() {
+        lines(stdout, |line| println!("{line}"));
+    }
+
+    if let Some(stderr) = child.stderr.take() {
+        lines(stderr, |line| warn!(target: "server", "{line}"));
+    }
+}
+
+/// Asks the child to shut down gracefully and kills it if it doesn't in time.
+async fn terminate(child: &mut Child, grace: Duration) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: sending a signal doesn't touch memory of this process
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+    }
+
+    #[cfg(not(unix))]
+    let _ = child.start_kill();
+
+    let status = match time::timeout(grace, child.wait()).await {
+        Ok(status) => status,
+        Err(_) => {
+            warn!("chat server didn't stop in time, killing it");
+            let _ = child.start_kill();
+            child.wait().await
+        }
+    };
+
+    match status {
+        Ok(status) => info!(%status, "chat server stopped"),
+        Err(err) => error!(error = %err, "couldn't wait for chat server"),
+    }
+}
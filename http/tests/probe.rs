@@ -0,0 +1,62 @@
+use http::{health, ready, Chat, Health};
+use rocket::{http::Status, local::asynchronous::Client, routes, serde::json::Value};
+use server::{store::FsStore, Server};
+use std::{net::TcpListener, path::PathBuf, sync::Arc};
+
+fn files(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("voki-{name}-{}", std::process::id()))
+}
+
+async fn client(chat: Chat, name: &str) -> Client {
+    let store = Arc::new(FsStore::new(files(name), "/images"));
+    let rocket = rocket::build()
+        .manage(Health::new(chat, store))
+        .mount("/api", routes![health, ready]);
+
+    Client::tracked(rocket).await.expect("rocket")
+}
+
+async fn get(client: &Client, uri: &str) -> (Status, Value) {
+    let res = client.get(uri).dispatch().await;
+    let status = res.status();
+    (status, res.into_json().await.expect("json"))
+}
+
+#[rocket::async_test]
+async fn ready_embedded() {
+    let server = Server::builder().start().expect("start");
+    let client = client(Chat::Embedded(server.acceptor()), "ready-embedded").await;
+
+    let (status, health) = get(&client, "/api/health").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(health["status"], "ok");
+
+    let (status, ready) = get(&client, "/api/ready").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(ready["status"], "ok");
+
+    server.shutdown().await;
+    let (status, ready) = get(&client, "/api/ready").await;
+    assert_eq!(status, Status::ServiceUnavailable);
+    assert_eq!(ready["errors"][0], "chat server is shutting down");
+    let _ = std::fs::remove_dir_all(files("ready-embedded"));
+}
+
+#[rocket::async_test]
+async fn unreachable_chat() {
+    // Take a free port and release it, so nothing listens there
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("free port");
+
+    let client = client(Chat::External(addr.to_string()), "unreachable-chat").await;
+    let (status, health) = get(&client, "/api/health").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(health["status"], "ok");
+
+    let (status, ready) = get(&client, "/api/ready").await;
+    assert_eq!(status, Status::ServiceUnavailable);
+    assert_eq!(ready["status"], "unavailable");
+    assert_eq!(ready["errors"].as_array().map(Vec::len), Some(1));
+    let _ = std::fs::remove_dir_all(files("unreachable-chat"));
+}
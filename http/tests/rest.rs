@@ -0,0 +1,336 @@
+use http::{
+    bots, channels, create_bot, create_hook, history, hooks, openapi, post_hook, revoke_bot,
+    revoke_hook, say, users,
+};
+use rocket::{
+    http::{ContentType, Header, Status},
+    local::asynchronous::{Client, LocalRequest},
+    routes,
+    serde::json::Value,
+};
+use server::Server;
+use std::{net::SocketAddr, path::PathBuf};
+
+// Default users have the name as the password, it's `admin:admin` in base64
+const ADMIN: &str = "Basic YWRtaW46YWRtaW4=";
+
+// A user who isn't an admin, `test0:test0`
+const TEST: &str = "Basic dGVzdDA6dGVzdDA=";
+
+async fn client(server: &Server) -> Client {
+    let rocket = rocket::build().manage(server.service()).mount(
+        "/api",
+        routes![
+            channels,
+            history,
+            say,
+            users,
+            openapi,
+            create_hook,
+            hooks,
+            revoke_hook,
+            post_hook,
+            create_bot,
+            bots,
+            revoke_bot
+        ],
+    );
+
+    Client::tracked(rocket).await.expect("rocket")
+}
+
+fn remote(req: LocalRequest<'_>) -> LocalRequest<'_> {
+    let remote: SocketAddr = "127.0.0.1:4000".parse().expect("address");
+    req.remote(remote)
+}
+
+fn authorized(req: LocalRequest<'_>) -> LocalRequest<'_> {
+    remote(req).header(Header::new("Authorization", ADMIN))
+}
+
+async fn get(client: &Client, uri: &str) -> (Status, Value) {
+    let res = authorized(client.get(uri.to_owned())).dispatch().await;
+    let status = res.status();
+    (status, res.into_json().await.expect("json"))
+}
+
+async fn post(client: &Client, uri: &str, body: &str) -> Status {
+    authorized(client.post(uri.to_owned()))
+        .header(ContentType::JSON)
+        .body(body)
+        .dispatch()
+        .await
+        .status()
+}
+
+fn texts(page: &Value) -> Vec<&str> {
+    page["messages"]
+        .as_array()
+        .expect("messages")
+        .iter()
+        .map(|message| message["content"]["text"].as_str().expect("text"))
+        .collect()
+}
+
+#[rocket::async_test]
+async fn requires_auth() {
+    let server = Server::builder().start().expect("start");
+    let client = client(&server).await;
+    let remote: SocketAddr = "127.0.0.1:4000".parse().expect("address");
+
+    let res = client.get("/api/channels").remote(remote).dispatch().await;
+    assert_eq!(res.status(), Status::Unauthorized);
+    assert!(res.headers().get_one("WWW-Authenticate").is_some());
+
+    let res = client
+        .get("/api/users")
+        .remote(remote)
+        .header(Header::new("Authorization", "Basic YWRtaW46bm9wZQ=="))
+        .dispatch()
+        .await;
+
+    assert_eq!(res.status(), Status::Unauthorized);
+    let body: Value = res.into_json().await.expect("json");
+    assert_eq!(body["error"], "wrong name or password");
+
+    // The description is public
+    let res = client.get("/api/openapi.json").dispatch().await;
+    assert_eq!(res.status(), Status::Ok);
+    let spec: Value = res.into_json().await.expect("json");
+    assert!(spec["paths"]["/channels/{chan}/messages"].is_object());
+    server.shutdown().await;
+}
+
+#[rocket::async_test]
+async fn channels_and_users() {
+    let server = Server::builder().start().expect("start");
+    let client = client(&server).await;
+
+    let (status, channels) = get(&client, "/api/channels").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(channels.as_array().map(Vec::len), Some(4));
+    assert_eq!(channels[0]["id"], 0);
+
+    let (status, users) = get(&client, "/api/users").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(users.as_array().map(Vec::len), Some(6));
+    assert_eq!(users[0]["name"], "admin");
+    server.shutdown().await;
+}
+
+#[rocket::async_test]
+async fn post_and_read_history() {
+    let server = Server::builder().start().expect("start");
+    let client = client(&server).await;
+
+    for text in ["one", "two", "three"] {
+        let body = format!(r#"{{"text":"{text}"}}"#);
+        assert_eq!(
+            post(&client, "/api/channels/1/messages", &body).await,
+            Status::Accepted
+        );
+    }
+
+    let (status, page) = get(&client, "/api/channels/1/messages").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(texts(&page), ["one", "two", "three"]);
+    assert_eq!(page["messages"][0]["from"], 0);
+    assert_eq!(page["messages"][0]["content"]["type"], "text");
+    assert!(page.get("before").is_none());
+
+    let (_, page) = get(&client, "/api/channels/1/messages?limit=2").await;
+    assert_eq!(texts(&page), ["two", "three"]);
+    let before = page["before"].as_u64().expect("before");
+
+    let uri = format!("/api/channels/1/messages?limit=2&before={before}");
+    let (_, page) = get(&client, &uri).await;
+    assert_eq!(texts(&page), ["one"]);
+    assert!(page.get("before").is_none());
+
+    // Other channels don't have the messages
+    let (_, page) = get(&client, "/api/channels/2/messages").await;
+    assert!(texts(&page).is_empty());
+
+    let (status, _) = get(&client, "/api/channels/9/messages").await;
+    assert_eq!(status, Status::NotFound);
+    let status = post(&client, "/api/channels/9/messages", r#"{"text":"hi"}"#).await;
+    assert_eq!(status, Status::NotFound);
+    let status = post(&client, "/api/channels/1/messages", r#"{"txt":"hi"}"#).await;
+    assert_eq!(status, Status::BadRequest);
+    server.shutdown().await;
+}
+
+/// Posts with the webhook token in its header.
+async fn post_hook_message(client: &Client, token: &str, body: &str) -> Status {
+    let req = client
+        .post("/api/webhooks")
+        .header(Header::new("X-Webhook-Token", token.to_owned()));
+    post_json(req, body).await.0
+}
+
+async fn post_json(req: LocalRequest<'_>, body: &str) -> (Status, Value) {
+    let res = remote(req)
+        .header(ContentType::JSON)
+        .body(body)
+        .dispatch()
+        .await;
+
+    let status = res.status();
+    (status, res.into_json().await.unwrap_or_default())
+}
+
+#[rocket::async_test]
+async fn webhooks() {
+    let data = std::env::temp_dir().join(format!("voki-hooks-{}", std::process::id()));
+    let start = |data: PathBuf| {
+        Server::builder()
+            .configure(|config| config.storage.data = Some(data))
+            .start()
+            .expect("start")
+    };
+
+    let server = start(data.clone());
+    let api = client(&server).await;
+    let new_hook = r#"{"name":"CI","avatar":"/ci.png"}"#;
+    let req = api.post("/api/channels/1/webhooks");
+    let (status, _) = post_json(req.header(Header::new("Authorization", TEST)), new_hook).await;
+    assert_eq!(status, Status::Forbidden);
+
+    let req = api.post("/api/channels/9/webhooks");
+    let (status, _) = post_json(req.header(Header::new("Authorization", ADMIN)), new_hook).await;
+    assert_eq!(status, Status::NotFound);
+
+    let req = api.post("/api/channels/1/webhooks");
+    let (status, hook) = post_json(req.header(Header::new("Authorization", ADMIN)), new_hook).await;
+    assert_eq!(status, Status::Created);
+    assert_eq!(hook["name"], "CI");
+    let id = hook["id"].as_u64().expect("id");
+    let token = hook["token"].as_str().expect("token").to_owned();
+
+    // The token isn't shown again
+    let (status, hooks) = get(&api, "/api/channels/1/webhooks").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(hooks[0]["id"], id);
+    assert!(hooks[0].get("token").is_none());
+
+    let status = post_hook_message(&api, &token, r#"{"text":"build passed"}"#).await;
+    assert_eq!(status, Status::Accepted);
+    let body = r#"{"text":"deployed","username":"CD"}"#;
+    let status = post_hook_message(&api, &token, body).await;
+    assert_eq!(status, Status::Accepted);
+    let status = post_hook_message(&api, "nope", body).await;
+    assert_eq!(status, Status::NotFound);
+    let (status, _) = post_json(api.post("/api/webhooks"), body).await;
+    assert_eq!(status, Status::Unauthorized);
+
+    let (_, page) = get(&api, "/api/channels/1/messages").await;
+    assert_eq!(texts(&page), ["build passed", "deployed"]);
+    assert_eq!(page["messages"][0]["author"]["name"], "CI");
+    assert_eq!(page["messages"][0]["author"]["avatar"], "/ci.png");
+    assert_eq!(page["messages"][1]["author"]["name"], "CD");
+    assert_eq!(page["messages"][1]["author"]["avatar"], "/ci.png");
+    server.shutdown().await;
+
+    // Webhooks are kept in the data directory
+    let server = start(data.clone());
+    let api = client(&server).await;
+    let status = post_hook_message(&api, &token, body).await;
+    assert_eq!(status, Status::Accepted);
+
+    let revoke = format!("/api/webhooks/{id}");
+    let req = api
+        .delete(revoke.clone())
+        .header(Header::new("Authorization", TEST));
+    assert_eq!(remote(req).dispatch().await.status(), Status::Forbidden);
+    let req = api.delete(revoke.clone());
+    assert_eq!(authorized(req).dispatch().await.status(), Status::NoContent);
+    let status = post_hook_message(&api, &token, body).await;
+    assert_eq!(status, Status::NotFound);
+    let req = api.delete(revoke);
+    assert_eq!(authorized(req).dispatch().await.status(), Status::NotFound);
+
+    server.shutdown().await;
+    let _ = std::fs::remove_dir_all(data);
+}
+
+#[rocket::async_test]
+async fn bot_accounts() {
+    let data = std::env::temp_dir().join(format!("voki-bots-{}", std::process::id()));
+    let start = |data: PathBuf| {
+        Server::builder()
+            .configure(|config| config.storage.data = Some(data))
+            .start()
+            .expect("start")
+    };
+
+    let server = start(data.clone());
+    let api = client(&server).await;
+    let new_bot = r#"{"name":"deploy"}"#;
+    let req = api.post("/api/bots");
+    let (status, _) = post_json(req.header(Header::new("Authorization", TEST)), new_bot).await;
+    assert_eq!(status, Status::Forbidden);
+
+    let req = api.post("/api/bots");
+    let (status, bot) = post_json(req.header(Header::new("Authorization", ADMIN)), new_bot).await;
+    assert_eq!(status, Status::Created);
+    assert_eq!(bot["name"], "deploy");
+    let id = bot["id"].as_u64().expect("id");
+    let token = bot["token"].as_str().expect("token").to_owned();
+    let bearer = Header::new("Authorization", format!("Bearer {token}"));
+
+    // The token isn't shown again
+    let (status, bots) = get(&api, "/api/bots").await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(bots[0]["id"], id);
+    assert!(bots[0].get("token").is_none());
+
+    // The bot is a user which authenticates with its token
+    let req = api.post("/api/channels/0/messages").header(bearer.clone());
+    let (status, _) = post_json(req, r#"{"text":"deployed"}"#).await;
+    assert_eq!(status, Status::Accepted);
+    let (_, page) = get(&api, "/api/channels/0/messages").await;
+    assert_eq!(texts(&page), ["deployed"]);
+    let (_, users) = get(&api, "/api/users").await;
+    let user = users
+        .as_array()
+        .expect("users")
+        .iter()
+        .find(|user| user["id"] == page["messages"][0]["from"])
+        .expect("bot user");
+
+    assert_eq!(user["name"], "deploy");
+    assert_eq!(user["bot"], true);
+
+    // Valid tokens don't spend the login budget, which is 5 attempts by default
+    for _ in 0..10 {
+        let req = api.get("/api/channels").header(bearer.clone());
+        assert_eq!(remote(req).dispatch().await.status(), Status::Ok);
+    }
+
+    let req = api
+        .get("/api/channels")
+        .header(Header::new("Authorization", "Bearer nope"));
+    assert_eq!(remote(req).dispatch().await.status(), Status::Unauthorized);
+    server.shutdown().await;
+
+    // Bots are kept in the data directory
+    let server = start(data.clone());
+    let api = client(&server).await;
+    let req = api.get("/api/channels").header(bearer.clone());
+    assert_eq!(remote(req).dispatch().await.status(), Status::Ok);
+
+    let revoke = format!("/api/bots/{id}");
+    let req = api
+        .delete(revoke.clone())
+        .header(Header::new("Authorization", TEST));
+    assert_eq!(remote(req).dispatch().await.status(), Status::Forbidden);
+    let req = api.delete(revoke.clone());
+    assert_eq!(authorized(req).dispatch().await.status(), Status::NoContent);
+    let req = api.get("/api/channels").header(bearer);
+    assert_eq!(remote(req).dispatch().await.status(), Status::Unauthorized);
+    let req = api.delete(revoke);
+    assert_eq!(authorized(req).dispatch().await.status(), Status::NotFound);
+
+    server.shutdown().await;
+    let _ = std::fs::remove_dir_all(data);
+}
@@ -0,0 +1,27 @@
+use std::{process::Stdio, time::Duration};
+use tokio::{process::Command, time};
+
+#[tokio::test]
+async fn bad_config() {
+    let dir = std::env::temp_dir().join(format!("voki-http-{}", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_http"))
+        .env("ROCKET_CHAT_CONFIG", dir.join("missing.toml"))
+        .env("ROCKET_PORT", "0")
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+
+    // The server exits with an error instead of panicking or serving without the chat
+    let output = time::timeout(Duration::from_secs(30), output)
+        .await
+        .expect("exit in time")
+        .expect("run http");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.starts_with("error: config error: failed to read"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
@@ -0,0 +1,49 @@
+#![cfg(unix)]
+
+use http::Supervisor;
+use std::time::{Duration, Instant};
+use tokio::{process::Command, time};
+
+fn shell(script: &'static str) -> impl Fn() -> Command {
+    move || {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        command
+    }
+}
+
+async fn wait_until<F>(mut f: F)
+where
+    F: FnMut() -> bool,
+{
+    for _ in 0..100 {
+        if f() {
+            return;
+        }
+
+        time::sleep(Duration::from_millis(50)).await;
+    }
+
+    panic!("condition isn't met in time");
+}
+
+#[tokio::test]
+async fn restarts_crashed_child() {
+    let supervisor = Supervisor::spawn(shell("echo started; exit 1"), Duration::from_secs(5));
+    wait_until(|| supervisor.restarts() >= 2).await;
+    supervisor.shutdown().await;
+    assert!(!supervisor.is_running());
+}
+
+#[tokio::test]
+async fn terminates_child_on_shutdown() {
+    let supervisor = Supervisor::spawn(shell("exec sleep 60"), Duration::from_secs(5));
+    wait_until(|| supervisor.is_running()).await;
+
+    // The child exits on the signal, so it isn't killed after the grace period
+    let start = Instant::now();
+    supervisor.shutdown().await;
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert!(!supervisor.is_running());
+    assert_eq!(supervisor.restarts(), 0);
+}
@@ -0,0 +1,101 @@
+use base::api::{Message, MessageType, ServerMessage};
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use server::client::{self, Client};
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, SocketAddr},
+};
+use tokio::sync::mpsc::{self, Receiver};
+use websocket::tungstenite::{self, protocol::Role, WebSocket};
+
+/// A socket which drops what is written, so only the framing is measured.
+struct Discard;
+
+impl Read for Discard {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+}
+
+impl Write for Discard {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A connected client with its queue and the socket the queue is written to,
+/// like a connection task does.
+struct Connection {
+    client: Client,
+    queue: Receiver<Bytes>,
+    socket: WebSocket<Discard>,
+}
+
+impl Connection {
+    fn new() -> Self {
+        let (sender, queue) = mpsc::channel(1);
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        Self {
+            client: Client::new(addr, sender),
+            queue,
+            socket: WebSocket::from_raw_socket(Discard, Role::Server, None),
+        }
+    }
+
+    /// Writes the queued frame to the socket.
+    fn write(&mut self) {
+        let frame = self.queue.try_recv().expect("queued frame");
+        self.socket
+            .send(tungstenite::Message::Binary(frame))
+            .expect("write frame");
+    }
+}
+
+fn message() -> ServerMessage {
+    ServerMessage::Message(Message {
+        from: 0,
+        chan: 0,
+        content: MessageType::Text("Hello, how is it going? ".repeat(8)),
+        author: None,
+    })
+}
+
+fn fan_out(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fan_out");
+    for n in [100, 500, 1000] {
+        let message = message();
+
+        // Every client encodes its own copy of the message
+        let mut per_client: Vec<_> = (0..n).map(|_| Connection::new()).collect();
+        group.bench_with_input(BenchmarkId::new("encode_per_client", n), &n, |b, _| {
+            b.iter(|| {
+                for conn in &mut per_client {
+                    assert!(conn.client.send(&message));
+                    conn.write();
+                }
+            })
+        });
+
+        // The message is encoded once and the frame is shared
+        let mut shared: Vec<_> = (0..n).map(|_| Connection::new()).collect();
+        group.bench_with_input(BenchmarkId::new("encode_once", n), &n, |b, _| {
+            b.iter(|| {
+                let frame = client::frame(&message).expect("encode");
+                for conn in &mut shared {
+                    assert!(conn.client.send_frame(frame.clone()));
+                    conn.write();
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, fan_out);
+criterion_main!(benches);
@@ -0,0 +1,168 @@
+//! Load test of a running server.
+//!
+//! Connects many clients, makes every client post messages
+//! and measures how fast they're delivered to everyone:
+//! ```sh
+//! cargo run --release --example load -- 127.0.0.1:4567 --clients 2000
+//! ```
+
+use base::{
+    api::{ClientMessage, ServerMessage},
+    decode, encode,
+};
+use clap::Parser;
+use futures::{SinkExt, StreamExt};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Barrier;
+use websocket::tungstenite::Message;
+
+#[derive(Parser)]
+struct Args {
+    /// Server address
+    #[clap(default_value = "127.0.0.1:4567")]
+    address: String,
+
+    /// Number of simulated clients
+    #[clap(long, default_value_t = 1000)]
+    clients: usize,
+
+    /// Number of messages every client posts
+    #[clap(long, default_value_t = 5)]
+    messages: usize,
+
+    /// Number of channels to spread messages over
+    #[clap(long, default_value_t = 4)]
+    channels: u32,
+
+    /// Number of messages all clients post per second
+    #[clap(long, default_value_t = 50)]
+    rate: u32,
+}
+
+fn request(message: &ClientMessage) -> Message {
+    let mut buf = Vec::with_capacity(64);
+    encode(message, &mut buf).expect("encode");
+    Message::Binary(buf.into())
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let url = format!("ws://{}", args.address);
+    let run = std::process::id();
+
+    let ready = Arc::new(Barrier::new(args.clients + 1));
+    let received = Arc::new(AtomicU64::new(0));
+    let expected = (args.clients * args.messages) as u64;
+
+    let mut tasks = Vec::with_capacity(args.clients);
+    for n in 0..args.clients {
+        let url = url.clone();
+        let ready = Arc::clone(&ready);
+        let received = Arc::clone(&received);
+        let messages = args.messages;
+        let chan = n as u32 % args.channels.max(1);
+        // Clients take turns to keep the overall rate
+        let delay = Duration::from_secs(args.clients as u64) / args.rate.max(1);
+        let offset = delay * n as u32 / args.clients as u32;
+
+        tasks.push(tokio::spawn(async move {
+            let (stream, _) = websocket::connect_async(&url).await.expect("connect");
+            let (mut write, mut read) = stream.split();
+
+            let name = format!("load-{run}-{n}");
+            let (name, pass) = (name.as_str(), name.as_str());
+            write
+                .send(request(&ClientMessage::SignUp { name, pass }))
+                .await
+                .expect("sign up");
+            write
+                .send(request(&ClientMessage::Login { name, pass }))
+                .await
+                .expect("log in");
+
+            // Wait for the sign-up and the login, then for everyone else
+            let mut replies = 0;
+            while replies < 2 {
+                match read.next().await {
+                    Some(Ok(Message::Binary(bytes))) => {
+                        if let Ok(ServerMessage::LoggedIn(_)) = decode(&bytes) {
+                            replies += 1;
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    _ => panic!("connection closed before login"),
+                }
+            }
+
+            ready.wait().await;
+
+            let write = tokio::spawn(async move {
+                tokio::time::sleep(offset).await;
+                for i in 0..messages {
+                    let text = format!("message {i} from {n}");
+                    let message = request(&ClientMessage::Say {
+                        request: 0,
+                        chan,
+                        text: &text,
+                    });
+                    if write.send(message).await.is_err() {
+                        break;
+                    }
+
+                    tokio::time::sleep(delay).await;
+                }
+
+                write
+            });
+
+            let mut count = 0;
+            while count < expected {
+                match read.next().await {
+                    Some(Ok(Message::Binary(bytes))) => {
+                        if let Ok(ServerMessage::Message(_)) = decode(&bytes) {
+                            count += 1;
+                            received.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    _ => break,
+                }
+            }
+
+            let _ = write.await;
+            count == expected
+        }));
+    }
+
+    ready.wait().await;
+    println!("{} clients logged in", args.clients);
+    let start = Instant::now();
+
+    let mut complete = 0;
+    for task in tasks {
+        if let Ok(true) = task.await {
+            complete += 1;
+        }
+    }
+
+    let elapsed = start.elapsed();
+    let delivered = received.load(Ordering::Relaxed);
+    println!("posted: {expected} messages");
+    println!(
+        "delivered: {delivered} of {}",
+        expected * args.clients as u64
+    );
+    println!("clients got everything: {complete} of {}", args.clients);
+    println!("elapsed: {elapsed:.2?}");
+    println!(
+        "throughput: {:.0} deliveries/s",
+        delivered as f64 / elapsed.as_secs_f64(),
+    );
+}
@@ -1,38 +1,169 @@
+use crate::{
+    config::{Config, ConfigError, LogFormat, LogLevel, Tls, S3},
+    store::Url,
+};
 use clap::Parser;
-use std::{fmt, str::FromStr};
+use std::path::PathBuf;
 
-struct Address(String);
+/// Command line flags, every one can be also set with an environment variable.
+/// Flags take precedence over variables and variables over the config file.
+#[derive(Parser)]
+#[clap(author, version, about)]
+pub struct Args {
+    /// Server local address [default: 0.0.0.0:4567]
+    #[clap(env = "VOKI_ADDRESS")]
+    address: Option<String>,
 
-impl Default for Address {
-    fn default() -> Self {
-        Self("0.0.0.0:4567".into())
-    }
-}
+    /// Local address to serve Prometheus metrics at
+    #[clap(long, env = "VOKI_METRICS_ADDRESS")]
+    metrics_address: Option<String>,
 
-impl fmt::Display for Address {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.0.fmt(f)
-    }
-}
+    /// Path to the TOML config file
+    #[clap(long, env = "VOKI_CONFIG")]
+    config: Option<PathBuf>,
 
-impl FromStr for Address {
-    type Err = String;
+    /// Log level: error, warn, info, debug or trace
+    #[clap(long, env = "VOKI_LOG_LEVEL")]
+    log_level: Option<LogLevel>,
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(s.into()))
-    }
-}
+    /// Log format: text or json
+    #[clap(long, env = "VOKI_LOG_FORMAT")]
+    log_format: Option<LogFormat>,
 
-#[derive(Parser)]
-#[clap(author, version, about)]
-pub struct Args {
-    /// Server local address
-    #[clap(default_value_t)]
-    address: Address,
+    /// Directory to save uploaded files in
+    #[clap(long, env = "VOKI_FILES")]
+    files: Option<PathBuf>,
+
+    /// Url the directory of uploaded files is served at
+    #[clap(long, env = "VOKI_FILES_URL")]
+    files_url: Option<String>,
+
+    /// Endpoint of an S3-compatible storage to save uploaded files in
+    #[clap(long, env = "VOKI_S3_ENDPOINT")]
+    s3_endpoint: Option<Url>,
+
+    /// Bucket of the S3-compatible storage
+    #[clap(long, env = "VOKI_S3_BUCKET")]
+    s3_bucket: Option<String>,
+
+    /// Region of the S3-compatible storage
+    #[clap(long, env = "VOKI_S3_REGION")]
+    s3_region: Option<String>,
+
+    /// Public url of the bucket, if it differs from the endpoint one
+    #[clap(long, env = "VOKI_S3_URL")]
+    s3_url: Option<String>,
+
+    /// Access key of the S3-compatible storage
+    #[clap(long, env = "AWS_ACCESS_KEY_ID", hide_env_values = true)]
+    s3_access_key: Option<String>,
+
+    /// Secret key of the S3-compatible storage
+    #[clap(long, env = "AWS_SECRET_ACCESS_KEY", hide_env_values = true)]
+    s3_secret_key: Option<String>,
+
+    /// Directory to keep the server state in
+    #[clap(long, env = "VOKI_DATA")]
+    data: Option<PathBuf>,
+
+    /// Max size of an uploaded file in bytes
+    #[clap(long, env = "VOKI_UPLOAD_SIZE")]
+    upload_size: Option<usize>,
+
+    /// How many last messages a channel keeps
+    #[clap(long, env = "VOKI_HISTORY")]
+    history: Option<usize>,
+
+    /// How many messages a user can send per second
+    #[clap(long, env = "VOKI_RATE_MESSAGES")]
+    rate_messages: Option<f64>,
+
+    /// How many messages a user can send at once
+    #[clap(long, env = "VOKI_RATE_BURST")]
+    rate_burst: Option<u32>,
+
+    /// PEM file with the TLS certificate chain
+    #[clap(long, env = "VOKI_TLS_CERT", requires = "tls-key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM file with the TLS private key
+    #[clap(long, env = "VOKI_TLS_KEY", requires = "tls-cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Seconds to wait for connections to close on shutdown
+    #[clap(long, env = "VOKI_SHUTDOWN_TIMEOUT")]
+    shutdown_timeout: Option<u64>,
+
+    /// Seconds clients should wait before reconnecting after shutdown
+    #[clap(long, env = "VOKI_RECONNECT_AFTER")]
+    reconnect_after: Option<u64>,
 }
 
 impl Args {
-    pub fn address(&self) -> String {
-        self.address.0.clone()
+    /// Reads the config file and overrides it with the flags.
+    pub fn config(self) -> Result<Config, ConfigError> {
+        fn set<T>(field: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+
+        let mut config = match &self.config {
+            Some(path) => Config::read(path)?,
+            None => Config::default(),
+        };
+
+        set(&mut config.address, self.address);
+        set(&mut config.metrics_address, self.metrics_address.map(Some));
+        set(&mut config.log_level, self.log_level);
+        set(&mut config.log_format, self.log_format);
+
+        let storage = &mut config.storage;
+        set(&mut storage.files, self.files);
+        set(&mut storage.files_url, self.files_url);
+        set(&mut storage.data, self.data.map(Some));
+        if let Some(endpoint) = self.s3_endpoint {
+            match &mut storage.s3 {
+                Some(s3) => s3.endpoint = endpoint,
+                None => storage.s3 = Some(S3::new(endpoint)),
+            }
+        }
+
+        match &mut storage.s3 {
+            Some(s3) => {
+                set(&mut s3.bucket, self.s3_bucket);
+                set(&mut s3.region, self.s3_region);
+                set(&mut s3.url, self.s3_url.map(Some));
+                set(&mut s3.access_key, self.s3_access_key);
+                set(&mut s3.secret_key, self.s3_secret_key);
+            }
+            None if self.s3_bucket.is_some() => {
+                return Err(ConfigError::Invalid(
+                    "storage.s3.endpoint",
+                    "is required to use a bucket".into(),
+                ))
+            }
+            None => {}
+        }
+
+        set(&mut config.limits.upload_size, self.upload_size);
+        set(&mut config.limits.history, self.history);
+        set(&mut config.rate.messages.rate, self.rate_messages);
+        set(&mut config.rate.messages.burst, self.rate_burst);
+        if let (Some(cert), Some(key)) = (self.tls_cert, self.tls_key) {
+            match &mut config.tls {
+                Some(tls) => {
+                    tls.cert = cert;
+                    tls.key = key;
+                }
+                None => config.tls = Some(Tls::new(cert, key)),
+            }
+        }
+
+        set(&mut config.shutdown.timeout, self.shutdown_timeout);
+        set(&mut config.shutdown.reconnect_after, self.reconnect_after);
+
+        config.validate()?;
+        Ok(config)
     }
 }
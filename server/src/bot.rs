@@ -0,0 +1,146 @@
+use crate::{
+    chat::Users,
+    data::{self, hash},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+/// The file bot accounts are kept in, inside the data directory.
+const FILE: &str = "bots.json";
+
+/// A bot account, it logs in with an API token instead of a password.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Bot {
+    pub id: u32,
+    /// The user the bot posts as. Users aren't saved,
+    /// so it's given again every time the bots are loaded.
+    #[serde(skip)]
+    pub user: u32,
+    pub name: String,
+    pub avatar: Option<String>,
+    /// The admin who created it.
+    pub created_by: u32,
+    /// Only a hash of the token is kept, the token is shown once when it's created.
+    token_hash: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct State {
+    next_id: u32,
+    bots: Vec<Bot>,
+}
+
+/// Bot accounts saved in the data directory if it's set.
+pub struct Bots {
+    file: Option<PathBuf>,
+    state: Mutex<State>,
+}
+
+impl Bots {
+    /// Reads bots from the data directory and adds their users.
+    pub fn load(data: Option<&Path>, users: &mut Users) -> io::Result<Self> {
+        let file = data.map(|dir| dir.join(FILE));
+        let mut state: State = data::load(file.as_deref())?;
+        for bot in &mut state.bots {
+            bot.user = users.push_bot(&bot.name, bot.avatar.as_deref());
+        }
+
+        Ok(Self {
+            file,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Creates a bot of the user and returns it with its token.
+    pub fn create(
+        &self,
+        user: u32,
+        name: &str,
+        avatar: Option<&str>,
+        created_by: u32,
+    ) -> io::Result<(Bot, String)> {
+        let token = data::token();
+        let mut state = self.lock();
+        let bot = Bot {
+            id: state.next_id,
+            user,
+            name: name.to_owned(),
+            avatar: avatar.map(Into::into),
+            created_by,
+            token_hash: hash(&token),
+        };
+
+        // The id is taken only if it's saved, a failed save leaves no gap
+        state.bots.push(bot.clone());
+        state.next_id += 1;
+        if let Err(err) = self.save(&state) {
+            state.next_id -= 1;
+            state.bots.pop();
+            return Err(err);
+        }
+
+        Ok((bot, token))
+    }
+
+    /// Returns all bots.
+    pub fn list(&self) -> Vec<Bot> {
+        self.lock().bots.clone()
+    }
+
+    /// Removes the bot, so its token stops working, and returns it.
+    /// Returns `None` if there's no such bot.
+    pub fn revoke(&self, id: u32) -> io::Result<Option<Bot>> {
+        let mut state = self.lock();
+        let Some(pos) = state.bots.iter().position(|bot| bot.id == id) else {
+            return Ok(None);
+        };
+
+        let bot = state.bots.remove(pos);
+        if let Err(err) = self.save(&state) {
+            state.bots.insert(pos, bot);
+            return Err(err);
+        }
+
+        Ok(Some(bot))
+    }
+
+    /// Finds the bot by its token.
+    pub fn find(&self, token: &str) -> Option<Bot> {
+        let token_hash = hash(token);
+        let state = self.lock();
+        let mut bots = state.bots.iter();
+        bots.find(|bot| bot.token_hash == token_hash).cloned()
+    }
+
+    fn save(&self, state: &State) -> io::Result<()> {
+        data::save(self.file.as_deref(), state)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Why a bot account can't be managed.
+#[derive(Debug)]
+pub enum BotError {
+    NotAdmin,
+    UnknownBot,
+    Save(io::Error),
+}
+
+impl fmt::Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotAdmin => write!(f, "only admins manage bots"),
+            Self::UnknownBot => write!(f, "unknown bot"),
+            Self::Save(err) => write!(f, "couldn't save bots: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for BotError {}
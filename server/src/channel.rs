@@ -0,0 +1,208 @@
+use crate::{
+    client::{frame, Client},
+    metrics::METRICS,
+    notify::Notifier,
+};
+use base::api::{self, Message, ServerMessage};
+use std::{collections::VecDeque, sync::Arc, time::Instant};
+use tokio::sync::{
+    mpsc::{self, Receiver, Sender},
+    oneshot,
+};
+
+/// How many commands can wait to be handled by a channel.
+const CHANNEL_QUEUE: usize = 256;
+
+#[derive(Clone)]
+pub struct Channel {
+    pub id: u32,
+    pub name: String,
+    pub icon: Option<String>,
+}
+
+/// A message in the channel history.
+#[derive(Clone)]
+pub struct Post {
+    /// Messages of a channel are numbered in the order they're posted.
+    pub id: u64,
+    pub message: Message,
+}
+
+enum Command {
+    Join(Arc<Client>),
+    Leave(Arc<Client>),
+    Post(Message),
+    SetTopic(Option<String>),
+    Topic(oneshot::Sender<Option<String>>),
+    History {
+        before: Option<u64>,
+        limit: usize,
+        reply: oneshot::Sender<Vec<Post>>,
+    },
+    Flush(oneshot::Sender<()>),
+}
+
+/// A handle to the task which owns the channel history and its members.
+#[derive(Clone)]
+pub struct ChannelHandle {
+    info: Arc<Channel>,
+    sender: Sender<Command>,
+}
+
+impl ChannelHandle {
+    /// Spawns the channel task, which keeps up to `history` last messages
+    /// and passes posted ones to the notifier.
+    pub fn spawn(chan: Channel, history: usize, notifier: Arc<Notifier>) -> Self {
+        let info = Arc::new(chan);
+        let (sender, receiver) = mpsc::channel(CHANNEL_QUEUE);
+        tokio::spawn(run(Arc::clone(&info), history, notifier, receiver));
+        Self { info, sender }
+    }
+
+    pub fn info(&self) -> &Channel {
+        &self.info
+    }
+
+    /// Sends the channel with its history to the client
+    /// and then keeps it updated with new messages.
+    pub async fn join(&self, client: Arc<Client>) {
+        let _ = self.sender.send(Command::Join(client)).await;
+    }
+
+    /// Removes the client from members without waiting.
+    /// If the queue is full, the client is forgotten on the next post instead,
+    /// since a closed client can't be sent to.
+    pub fn leave(&self, client: Arc<Client>) {
+        let _ = self.sender.try_send(Command::Leave(client));
+    }
+
+    /// Posts the message to all members of the channel.
+    pub async fn post(&self, message: Message) {
+        let _ = self.sender.send(Command::Post(message)).await;
+    }
+
+    /// Changes the topic and sends it to all members.
+    pub async fn set_topic(&self, topic: Option<String>) {
+        let _ = self.sender.send(Command::SetTopic(topic)).await;
+    }
+
+    pub async fn topic(&self) -> Option<String> {
+        let (reply, receiver) = oneshot::channel();
+        if self.sender.send(Command::Topic(reply)).await.is_err() {
+            return None;
+        }
+
+        receiver.await.unwrap_or_default()
+    }
+
+    /// Returns up to `limit` last messages posted before the `before` one,
+    /// or the last ones if it's not set, oldest first.
+    pub async fn history(&self, before: Option<u64>, limit: usize) -> Vec<Post> {
+        let (reply, receiver) = oneshot::channel();
+        let command = Command::History {
+            before,
+            limit,
+            reply,
+        };
+
+        if self.sender.send(command).await.is_err() {
+            return vec![];
+        }
+
+        receiver.await.unwrap_or_default()
+    }
+
+    /// Waits until all posted messages are sent to members.
+    pub async fn flush(&self) {
+        let (sender, receiver) = oneshot::channel();
+        if self.sender.send(Command::Flush(sender)).await.is_ok() {
+            let _ = receiver.await;
+        }
+    }
+}
+
+async fn run(
+    chan: Arc<Channel>,
+    limit: usize,
+    notifier: Arc<Notifier>,
+    mut receiver: Receiver<Command>,
+) {
+    let mut history: VecDeque<Post> = VecDeque::new();
+    let mut next_id = 0;
+    let mut members: Vec<Arc<Client>> = vec![];
+    let mut topic = None;
+
+    while let Some(command) = receiver.recv().await {
+        match command {
+            Command::Join(client) => {
+                let message = ServerMessage::Channel(api::Channel {
+                    id: chan.id,
+                    name: chan.name.clone(),
+                    icon: chan.icon.clone(),
+                    topic: topic.clone(),
+                    history: history.iter().map(|post| post.message.clone()).collect(),
+                });
+
+                if client.send(&message) {
+                    members.push(client);
+                }
+            }
+            Command::Leave(client) => members.retain(|member| !Arc::ptr_eq(member, &client)),
+            Command::Post(message) => {
+                let start = Instant::now();
+                let broadcast = match frame(&ServerMessage::Message(message.clone())) {
+                    Ok(frame) => frame,
+                    Err(err) => {
+                        tracing::error!(chan = chan.id, error = %err, "couldn't encode message");
+                        continue;
+                    }
+                };
+
+                // Send this to all members and forget the gone ones
+                members.retain(|client| client.send_frame(broadcast.clone()));
+                METRICS.post(chan.id, start.elapsed());
+                if history.len() == limit {
+                    history.pop_front();
+                }
+
+                let post = Post {
+                    id: next_id,
+                    message,
+                };
+
+                notifier.post(&post);
+                history.push_back(post);
+                next_id += 1;
+            }
+            Command::SetTopic(new) => {
+                topic = new;
+                let message = ServerMessage::Topic {
+                    chan: chan.id,
+                    topic: topic.clone(),
+                };
+
+                members.retain(|client| client.send(&message));
+            }
+            Command::Topic(reply) => {
+                let _ = reply.send(topic.clone());
+            }
+            Command::History {
+                before,
+                limit,
+                reply,
+            } => {
+                // Ids grow along the history, so the older ones are found by a binary search
+                let end = match before {
+                    Some(before) => history.partition_point(|post| post.id < before),
+                    None => history.len(),
+                };
+
+                let start = end.saturating_sub(limit);
+                let _ = reply.send(history.range(start..end).cloned().collect());
+            }
+            Command::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
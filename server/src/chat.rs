@@ -0,0 +1,553 @@
+use crate::{
+    bot::{Bot, Bots},
+    channel::{Channel, ChannelHandle},
+    client::Client,
+    command::Commands,
+    config::{Config, Heartbeat, Limits, UserSeed},
+    hook::{HookError, Hooks},
+    limit::{Action, Limiter},
+    notify::Notifier,
+    store::BlobStore,
+};
+use base::api::{Author, LoginError, Message, MessageType, ServerMessage, NOBODY};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    fmt, io,
+    net::IpAddr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{Duration, Instant},
+};
+use tracing::{debug, info};
+
+/// Max size of a name or an avatar url set by a webhook message.
+const AUTHOR_SIZE: usize = 256;
+
+#[derive(Clone)]
+pub struct User {
+    pub id: u32,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub admin: bool,
+    pub bot: bool,
+}
+
+pub struct Users {
+    ids: HashMap<u32, Arc<User>>,
+    names: HashMap<(String, String), Arc<User>>,
+}
+
+impl Users {
+    pub fn new(seed: &[UserSeed]) -> Self {
+        let mut users = Self {
+            ids: HashMap::default(),
+            names: HashMap::default(),
+        };
+
+        for user in seed {
+            users.push(&user.name, &user.pass, user.avatar.as_deref(), user.admin);
+        }
+
+        users
+    }
+
+    pub fn push_new(&mut self, name: &str, pass: &str, avatar: Option<&str>) -> Option<u32> {
+        self.push(name, pass, avatar, false)
+    }
+
+    fn push(&mut self, name: &str, pass: &str, avatar: Option<&str>, admin: bool) -> Option<u32> {
+        let name = name.to_owned();
+        let pass = pass.to_owned();
+        let id = self.ids.len() as u32;
+
+        match self.names.entry((name.clone(), pass)) {
+            Entry::Occupied(_) => None,
+            Entry::Vacant(en) => {
+                let user = Arc::new(User {
+                    id,
+                    name,
+                    avatar: avatar.map(Into::into),
+                    admin,
+                    bot: false,
+                });
+                en.insert(Arc::clone(&user));
+                self.ids.insert(id, user);
+                Some(id)
+            }
+        }
+    }
+
+    /// Adds a bot, it can't log in with a password, so it has no name entry.
+    pub fn push_bot(&mut self, name: &str, avatar: Option<&str>) -> u32 {
+        let id = self.ids.len() as u32;
+        let user = User {
+            id,
+            name: name.to_owned(),
+            avatar: avatar.map(Into::into),
+            admin: false,
+            bot: true,
+        };
+
+        self.ids.insert(id, Arc::new(user));
+        id
+    }
+
+    /// Removes the bot which was just added, when it can't be saved.
+    /// Ids follow the count of users, so it has to be the last one.
+    pub fn pop_bot(&mut self, id: u32) {
+        if id as usize + 1 == self.ids.len() {
+            self.ids.remove(&id);
+        }
+    }
+
+    pub fn get(&self, name: &str, pass: &str) -> Option<u32> {
+        let key = (name.to_owned(), pass.to_owned());
+        self.names.get(&key).map(|user| user.id)
+    }
+
+    pub fn name(&self, id: u32) -> Option<&str> {
+        self.ids.get(&id).map(|user| user.name.as_str())
+    }
+
+    /// Returns ids of users with the name, it isn't unique.
+    pub fn find(&self, name: &str) -> Vec<u32> {
+        let users = self.ids.values().filter(|user| user.name == name);
+        users.map(|user| user.id).collect()
+    }
+
+    pub fn is_admin(&self, id: u32) -> bool {
+        self.ids.get(&id).is_some_and(|user| user.admin)
+    }
+
+    pub fn is_bot(&self, id: u32) -> bool {
+        self.ids.get(&id).is_some_and(|user| user.bot)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = User> + '_ {
+        self.ids.values().map(|user| user.as_ref().clone())
+    }
+}
+
+/// State shared between connections.
+pub struct Chat {
+    users: RwLock<Users>,
+    channels: BTreeMap<u32, ChannelHandle>,
+    store: Arc<dyn BlobStore>,
+    limits: Limits,
+    heartbeat: Heartbeat,
+    log_messages: bool,
+    limiter: Limiter,
+    hooks: Hooks,
+    bots: Bots,
+    notifier: Arc<Notifier>,
+    commands: Commands,
+    /// Connections of logged in users, a user can have several.
+    online: Mutex<HashMap<u32, Vec<Arc<Client>>>>,
+    /// Users who can't post until the time.
+    muted: Mutex<HashMap<u32, Instant>>,
+}
+
+impl Chat {
+    /// Creates the chat, loads saved webhooks and bots and spawns a task for every channel.
+    pub fn new(config: &Config, store: Arc<dyn BlobStore>) -> io::Result<Self> {
+        let data = config.storage.data.as_deref();
+        let hooks = Hooks::load(data)?;
+        let mut users = Users::new(&config.users);
+        let bots = Bots::load(data, &mut users)?;
+        let subs = config.channels.iter().zip(0..).flat_map(|(seed, id)| {
+            let subs = seed.webhooks.iter().cloned();
+            subs.map(move |sub| (id, sub))
+        });
+
+        let notifier = Notifier::spawn(subs, config.delivery.clone(), data)?;
+        let history = config.limits.history;
+        let channels = config.channels.iter().zip(0..).map(|(seed, id)| {
+            let chan = Channel {
+                id,
+                name: seed.name.clone(),
+                icon: seed.icon.clone(),
+            };
+
+            let notifier = Arc::clone(&notifier);
+            (id, ChannelHandle::spawn(chan, history, notifier))
+        });
+
+        Ok(Self {
+            users: RwLock::new(users),
+            channels: channels.collect(),
+            store,
+            limits: config.limits.clone(),
+            heartbeat: config.heartbeat.clone(),
+            log_messages: config.log_messages,
+            limiter: Limiter::new(config.rate.clone()),
+            hooks,
+            bots,
+            notifier,
+            commands: Commands::default(),
+            online: Mutex::default(),
+            muted: Mutex::default(),
+        })
+    }
+
+    pub fn users(&self) -> RwLockReadGuard<'_, Users> {
+        self.users.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn users_mut(&self) -> RwLockWriteGuard<'_, Users> {
+        self.users.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn channel(&self, id: u32) -> Option<&ChannelHandle> {
+        self.channels.get(&id)
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = &ChannelHandle> {
+        self.channels.values()
+    }
+
+    /// Waits until all channels send posted messages.
+    pub async fn flush(&self) {
+        for chan in self.channels() {
+            chan.flush().await;
+        }
+    }
+
+    pub fn store(&self) -> &dyn BlobStore {
+        self.store.as_ref()
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn heartbeat(&self) -> &Heartbeat {
+        &self.heartbeat
+    }
+
+    pub fn limiter(&self) -> &Limiter {
+        &self.limiter
+    }
+
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    pub fn bots(&self) -> &Bots {
+        &self.bots
+    }
+
+    pub fn notifier(&self) -> &Notifier {
+        &self.notifier
+    }
+
+    pub fn commands(&self) -> &Commands {
+        &self.commands
+    }
+
+    /// Remembers the connection of the user and returns `true` if it's the first one.
+    pub fn go_online(&self, user: u32, client: Arc<Client>) -> bool {
+        let mut online = lock(&self.online);
+        let clients = online.entry(user).or_default();
+        clients.push(client);
+        clients.len() == 1
+    }
+
+    /// Forgets the connection of the user and returns `true` if it was the last one.
+    pub fn go_offline(&self, user: u32, client: &Arc<Client>) -> bool {
+        let mut online = lock(&self.online);
+        let Some(clients) = online.get_mut(&user) else {
+            return false;
+        };
+
+        clients.retain(|other| !Arc::ptr_eq(other, client));
+        if clients.is_empty() {
+            online.remove(&user);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns connections of the user.
+    pub fn connections(&self, user: u32) -> Vec<Arc<Client>> {
+        lock(&self.online).get(&user).cloned().unwrap_or_default()
+    }
+
+    /// Sends the message to every connection of the user.
+    /// Returns `false` if the user isn't online.
+    pub fn send_to(&self, user: u32, message: &ServerMessage) -> bool {
+        let clients = self.connections(user);
+        for client in &clients {
+            client.send(message);
+        }
+
+        !clients.is_empty()
+    }
+
+    /// Sends the message to every logged in connection.
+    pub fn broadcast(&self, message: &ServerMessage) {
+        let online = lock(&self.online);
+        for client in online.values().flatten() {
+            client.send(message);
+        }
+    }
+
+    /// Returns `false` if a mute can't last that long.
+    pub fn can_mute(&self, duration: Duration) -> bool {
+        Instant::now().checked_add(duration).is_some()
+    }
+
+    /// Stops the user from posting for a while, a zero duration lets it post again.
+    /// A duration which is too long leaves the user as it is.
+    pub fn mute(&self, user: u32, duration: Duration) {
+        let mut muted = lock(&self.muted);
+        if duration.is_zero() {
+            muted.remove(&user);
+        } else if let Some(until) = Instant::now().checked_add(duration) {
+            muted.insert(user, until);
+        }
+    }
+
+    /// Fails if the user is muted.
+    pub fn check_muted(&self, user: u32) -> Result<(), PostError> {
+        let mut muted = lock(&self.muted);
+        let Some(&until) = muted.get(&user) else {
+            return Ok(());
+        };
+
+        let remaining = until.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            muted.remove(&user);
+            Ok(())
+        } else {
+            Err(PostError::Muted { remaining })
+        }
+    }
+
+    /// Checks the credentials, failures in a row lock the name for the address for a while.
+    pub fn authenticate(&self, name: &str, pass: &str, ip: IpAddr) -> Result<u32, LoginError> {
+        let limiter = &self.limiter;
+        if let Some(wait) = limiter.locked(name, ip) {
+            return Err(locked(wait));
+        }
+
+        limiter.check(Action::Login, None, ip).map_err(locked)?;
+        let found = self.users().get(name, pass);
+        match found {
+            Some(id) => {
+                limiter.login_succeeded(name, ip);
+                Ok(id)
+            }
+            None => {
+                limiter.login_failed(name, ip);
+                Err(LoginError::WrongNameOrPass)
+            }
+        }
+    }
+
+    /// Checks the credentials sent with every request of a stateless client.
+    /// Unlike logins, only failed attempts count towards the login rate.
+    pub fn verify(&self, name: &str, pass: &str, ip: IpAddr) -> Result<u32, LoginError> {
+        let limiter = &self.limiter;
+        if let Some(wait) = limiter.locked(name, ip) {
+            return Err(locked(wait));
+        }
+
+        let found = self.users().get(name, pass);
+        match found {
+            Some(id) => Ok(id),
+            None => {
+                limiter.login_failed(name, ip);
+                limiter.check(Action::Login, None, ip).map_err(locked)?;
+                Err(LoginError::WrongNameOrPass)
+            }
+        }
+    }
+
+    /// Checks the token of a bot, for websocket logins and REST calls alike.
+    /// Only unknown tokens count against the login limit, like wrong passwords
+    /// do in [`Chat::verify`], so a bot which reconnects often isn't locked out.
+    pub fn verify_bot(&self, token: &str, ip: IpAddr) -> Result<u32, LoginError> {
+        match self.bots.find(token) {
+            Some(bot) => Ok(bot.user),
+            None => {
+                self.limiter
+                    .check(Action::Login, None, ip)
+                    .map_err(locked)?;
+                Err(LoginError::WrongToken)
+            }
+        }
+    }
+
+    /// Adds a bot user and returns the bot with its token.
+    pub fn create_bot(
+        &self,
+        name: &str,
+        avatar: Option<&str>,
+        created_by: u32,
+    ) -> io::Result<(Bot, String)> {
+        // Users are locked until the bot is saved, so nobody takes the next id meanwhile
+        let mut users = self.users_mut();
+        let user = users.push_bot(name, avatar);
+        let created = self.bots.create(user, name, avatar, created_by);
+        if created.is_err() {
+            users.pop_bot(user);
+        }
+
+        created
+    }
+
+    /// Revokes the token of the bot and disconnects the bot, like `/kick` does.
+    /// Returns `false` if there's no such bot.
+    pub fn revoke_bot(&self, id: u32) -> io::Result<bool> {
+        let Some(bot) = self.bots.revoke(id)? else {
+            return Ok(false);
+        };
+
+        for client in self.connections(bot.user) {
+            client.send(&ServerMessage::Closed);
+            client.close();
+        }
+
+        info!(bot = id, "bot revoked");
+        Ok(true)
+    }
+
+    /// Posts the text of the user to the channel.
+    pub async fn say(&self, from: u32, chan: u32, text: &str, ip: IpAddr) -> Result<(), PostError> {
+        self.post_text(from, chan, text, MessageType::Text, Some(ip))
+            .await
+    }
+
+    /// Posts what the user does to the channel, like `/me waves`.
+    /// It isn't limited here, the command is.
+    pub async fn act(&self, from: u32, chan: u32, text: &str) -> Result<(), PostError> {
+        self.post_text(from, chan, text, MessageType::Action, None)
+            .await
+    }
+
+    /// Posts the text, limited by the address unless it's `None`.
+    async fn post_text<F>(
+        &self,
+        from: u32,
+        chan: u32,
+        text: &str,
+        content: F,
+        ip: Option<IpAddr>,
+    ) -> Result<(), PostError>
+    where
+        F: FnOnce(String) -> MessageType,
+    {
+        let channel = self.channel(chan).ok_or(PostError::UnknownChannel)?;
+        self.check_muted(from)?;
+        if let Some(ip) = ip {
+            self.limiter
+                .check(Action::Message, Some(from), ip)
+                .map_err(|retry_after| PostError::RateLimited { retry_after })?;
+        }
+
+        self.check_text(chan, text)?;
+        let message = Message {
+            from,
+            chan,
+            content: content(text.into()),
+            author: None,
+        };
+
+        channel.post(message).await;
+        Ok(())
+    }
+
+    /// Posts the text with the webhook of the token.
+    /// The name and the avatar override the webhook ones for this message.
+    pub async fn post_hook(
+        &self,
+        token: &str,
+        text: &str,
+        name: Option<&str>,
+        avatar: Option<&str>,
+        ip: IpAddr,
+    ) -> Result<(), HookError> {
+        let hook = self.hooks.find(token).ok_or(HookError::UnknownHook)?;
+        let channel = self.channel(hook.chan).ok_or(HookError::UnknownChannel)?;
+        self.limiter
+            .check(Action::Hook, Some(hook.id), ip)
+            .map_err(|retry_after| PostError::RateLimited { retry_after })?;
+
+        let too_long = |field: Option<&str>| field.is_some_and(|field| field.len() > AUTHOR_SIZE);
+        if too_long(name) || too_long(avatar) {
+            let max_size = AUTHOR_SIZE;
+            return Err(PostError::TooLarge { max_size }.into());
+        }
+
+        self.check_text(hook.chan, text)?;
+        debug!(hook = hook.id, "webhook message");
+        let author = Author {
+            name: name.map_or(hook.name, Into::into),
+            avatar: avatar.map(Into::into).or(hook.avatar),
+        };
+
+        let message = Message {
+            from: NOBODY,
+            chan: hook.chan,
+            content: MessageType::Text(text.into()),
+            author: Some(author),
+        };
+
+        channel.post(message).await;
+        Ok(())
+    }
+
+    /// Checks the size of the text and writes it to logs.
+    fn check_text(&self, chan: u32, text: &str) -> Result<(), PostError> {
+        let max_size = self.limits.text_size;
+        if text.len() > max_size {
+            return Err(PostError::TooLarge { max_size });
+        }
+
+        // The text is private, so it's written only when asked
+        if self.log_messages {
+            debug!(chan, text, "message");
+        } else {
+            debug!(chan, size = text.len(), "message");
+        }
+
+        Ok(())
+    }
+}
+
+/// Locks the mutex, a panic of another task doesn't make its data unusable.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn locked(wait: Duration) -> LoginError {
+    let retry_after_secs = wait.as_secs_f64().ceil() as u32;
+    LoginError::Locked { retry_after_secs }
+}
+
+/// Why a message isn't posted.
+#[derive(Debug)]
+pub enum PostError {
+    UnknownChannel,
+    TooLarge { max_size: usize },
+    RateLimited { retry_after: Duration },
+    Muted { remaining: Duration },
+}
+
+impl fmt::Display for PostError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownChannel => write!(f, "unknown channel"),
+            Self::TooLarge { max_size } => write!(f, "message is larger than {max_size} bytes"),
+            Self::RateLimited { retry_after } => {
+                write!(f, "too many messages, try again in {retry_after:?}")
+            }
+            Self::Muted { remaining } => {
+                let secs = remaining.as_secs_f64().ceil();
+                write!(f, "you're muted for {secs}s")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PostError {}
@@ -0,0 +1,88 @@
+use crate::{error::Error, metrics::METRICS};
+use base::{api::ServerMessage, encode};
+use bytes::Bytes;
+use std::{
+    net::SocketAddr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tokio::sync::{
+    mpsc::{error::TrySendError, Sender},
+    Notify,
+};
+
+/// A connected client which messages can be sent to from any task.
+pub struct Client {
+    addr: SocketAddr,
+    sender: Sender<Bytes>,
+    closed: AtomicBool,
+    close: Notify,
+}
+
+impl Client {
+    pub fn new(addr: SocketAddr, sender: Sender<Bytes>) -> Self {
+        Self {
+            addr,
+            sender,
+            closed: AtomicBool::new(false),
+            close: Notify::new(),
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns `false` if the message couldn't be sent.
+    pub fn send(&self, message: &ServerMessage) -> bool {
+        match frame(message) {
+            Ok(frame) => self.send_frame(frame),
+            Err(err) => {
+                tracing::error!(addr = %self.addr, error = %err, "couldn't encode message");
+                false
+            }
+        }
+    }
+
+    /// Sends the frame without waiting for the client.
+    /// If the client doesn't keep up with its queue, it gets disconnected.
+    ///
+    /// Returns `false` if the client is gone.
+    pub fn send_frame(&self, frame: Bytes) -> bool {
+        if self.closed.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        match self.sender.try_send(frame) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                if !self.closed.swap(true, Ordering::Relaxed) {
+                    let dropped = METRICS.drop_client();
+                    tracing::warn!(addr = %self.addr, dropped, "too slow, disconnected");
+                    self.close.notify_one();
+                }
+
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    /// Asks the connection to close.
+    pub fn close(&self) {
+        if !self.closed.swap(true, Ordering::Relaxed) {
+            self.close.notify_one();
+        }
+    }
+
+    /// Waits until the client is asked to close.
+    pub async fn closed(&self) {
+        self.close.notified().await
+    }
+}
+
+/// Encodes the message once, so the frame can be shared between clients.
+pub fn frame(message: &ServerMessage) -> Result<Bytes, Error> {
+    let mut buf = Vec::with_capacity(64);
+    encode(message, &mut buf)?;
+    Ok(buf.into())
+}
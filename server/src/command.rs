@@ -0,0 +1,355 @@
+use crate::{
+    chat::{lock, Chat, PostError},
+    limit::Action,
+};
+use base::api::{CommandInfo, ServerMessage};
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{debug, info};
+
+/// Commands of the server with their usage and description.
+const BUILTIN: [(&str, &str, &str); 5] = [
+    ("me", "<action>", "Posts what you do"),
+    (
+        "topic",
+        "[topic | -]",
+        "Shows, sets or clears the channel topic",
+    ),
+    ("invite", "<name>", "Invites the user to the channel"),
+    (
+        "kick",
+        "<name>",
+        "Disconnects the user, only admins can do it",
+    ),
+    (
+        "mute",
+        "<name> [minutes]",
+        "Stops the user from posting, 10 minutes by default and 0 to unmute, only admins can do it",
+    ),
+];
+
+/// Max size of a name of a bot command.
+const NAME_SIZE: usize = 32;
+
+/// Max size of a usage or a description of a bot command.
+const INFO_SIZE: usize = 256;
+
+/// How long a bot has to answer a command.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Minutes a user is muted for by default.
+const MUTE_MINUTES: u64 = 10;
+
+struct BotCommand {
+    bot: u32,
+    info: CommandInfo,
+}
+
+/// A command a bot is asked to run.
+struct Pending {
+    bot: u32,
+    user: u32,
+    chan: u32,
+    at: Instant,
+}
+
+/// Commands registered by bots and the ones they haven't answered yet.
+#[derive(Default)]
+pub struct Commands {
+    bots: Mutex<BTreeMap<String, BotCommand>>,
+    pending: Mutex<HashMap<u64, Pending>>,
+    next_id: AtomicU64,
+}
+
+impl Commands {
+    /// Returns the server commands and then the bot ones ordered by name.
+    pub fn list(&self) -> Vec<CommandInfo> {
+        let builtin = BUILTIN
+            .iter()
+            .map(|&(name, usage, description)| CommandInfo {
+                name: name.into(),
+                usage: usage.into(),
+                description: description.into(),
+            });
+
+        let bots = lock(&self.bots);
+        let bots = bots.values().map(|command| command.info.clone());
+        builtin.chain(bots).collect()
+    }
+
+    /// Registers the command of the bot.
+    /// Returns `false` if the name is wrong or taken by another command.
+    pub fn register(&self, bot: u32, name: &str, usage: &str, description: &str) -> bool {
+        let valid = !name.is_empty()
+            && name.len() <= NAME_SIZE
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            && usage.len() <= INFO_SIZE
+            && description.len() <= INFO_SIZE;
+
+        if !valid || BUILTIN.iter().any(|&(builtin, ..)| builtin == name) {
+            return false;
+        }
+
+        let mut bots = lock(&self.bots);
+        if bots.get(name).is_some_and(|command| command.bot != bot) {
+            return false;
+        }
+
+        let info = CommandInfo {
+            name: name.into(),
+            usage: usage.into(),
+            description: description.into(),
+        };
+
+        bots.insert(name.into(), BotCommand { bot, info });
+        true
+    }
+
+    /// Removes commands of the bot and returns `true` if it had any.
+    pub fn unregister(&self, bot: u32) -> bool {
+        let mut bots = lock(&self.bots);
+        let len = bots.len();
+        bots.retain(|_, command| command.bot != bot);
+        lock(&self.pending).retain(|_, pending| pending.bot != bot);
+        bots.len() != len
+    }
+
+    fn bot(&self, name: &str) -> Option<u32> {
+        lock(&self.bots).get(name).map(|command| command.bot)
+    }
+
+    /// Remembers the command the bot is asked to run and returns its id.
+    fn invoke(&self, bot: u32, user: u32, chan: u32) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+        let mut pending = lock(&self.pending);
+
+        // Forget commands bots haven't answered in time
+        pending.retain(|_, pending| now.duration_since(pending.at) < REPLY_TIMEOUT);
+        let command = Pending {
+            bot,
+            user,
+            chan,
+            at: now,
+        };
+
+        pending.insert(id, command);
+        id
+    }
+
+    /// Takes the command the bot answers, returns its user and channel.
+    fn answer(&self, bot: u32, id: u64) -> Option<(u32, u32)> {
+        let mut pending = lock(&self.pending);
+        match pending.get(&id) {
+            Some(command) if command.bot == bot => {
+                let command = pending.remove(&id)?;
+                Some((command.user, command.chan))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Runs the command of the user in the channel and returns
+/// a reply only the user sees, if there's one.
+/// Commands are limited like messages, whatever they do.
+pub async fn run(
+    chat: &Chat,
+    user: u32,
+    chan: u32,
+    name: &str,
+    args: &str,
+    ip: IpAddr,
+) -> Result<Option<String>, PostError> {
+    chat.limiter()
+        .check(Action::Message, Some(user), ip)
+        .map_err(|retry_after| PostError::RateLimited { retry_after })?;
+
+    let channel = chat.channel(chan).ok_or(PostError::UnknownChannel)?;
+    let args = args.trim();
+    debug!(chan, name, "command");
+    let reply = match name {
+        "me" if args.is_empty() => Some(usage(name)),
+        "me" => {
+            chat.act(user, chan, args).await?;
+            None
+        }
+        "topic" if args.is_empty() => match channel.topic().await {
+            Some(topic) => Some(format!("The topic is: {topic}")),
+            None => Some("There's no topic".into()),
+        },
+        "topic" => {
+            chat.check_muted(user)?;
+            let max_size = chat.limits().text_size;
+            if args.len() > max_size {
+                return Err(PostError::TooLarge { max_size });
+            }
+
+            let topic = (args != "-").then(|| args.to_owned());
+            channel.set_topic(topic).await;
+            None
+        }
+        "invite" | "kick" | "mute" if args.is_empty() => Some(usage(name)),
+        "invite" => {
+            let (target, _) = split(args);
+            let from = chat.users().name(user).unwrap_or_default().to_owned();
+            let notice = ServerMessage::Notice {
+                chan,
+                text: format!("{from} invites you to {}", channel.info().name),
+            };
+
+            let invited = chat
+                .users()
+                .find(target)
+                .into_iter()
+                .filter(|&id| chat.send_to(id, &notice))
+                .count();
+
+            if invited > 0 {
+                Some(format!("{target} is invited"))
+            } else {
+                Some(format!("{target} isn't online"))
+            }
+        }
+        "kick" | "mute" if !chat.users().is_admin(user) => {
+            Some(format!("Only admins can /{name} users"))
+        }
+        "kick" => {
+            let (target, _) = split(args);
+            let mut kicked = 0;
+            for id in chat.users().find(target) {
+                for client in chat.connections(id) {
+                    client.send(&ServerMessage::Closed);
+                    client.close();
+                    kicked += 1;
+                }
+            }
+
+            if kicked > 0 {
+                info!(target, "kicked");
+                Some(format!("{target} is kicked"))
+            } else {
+                Some(format!("{target} isn't online"))
+            }
+        }
+        "mute" => {
+            let (target, minutes) = split(args);
+            let minutes = match minutes {
+                "" => MUTE_MINUTES,
+                minutes => match minutes.parse::<u64>() {
+                    Ok(minutes) => minutes,
+                    Err(_) => return Ok(Some(usage(name))),
+                },
+            };
+
+            let ids = chat.users().find(target);
+            if ids.is_empty() {
+                return Ok(Some(format!("There's no user {target}")));
+            }
+
+            // Durations which don't fit in an `Instant` are out of range
+            let Some(duration) = minutes.checked_mul(60).map(Duration::from_secs) else {
+                return Ok(Some(usage(name)));
+            };
+
+            if !chat.can_mute(duration) {
+                return Ok(Some(usage(name)));
+            }
+
+            let text = match minutes {
+                0 => "You can post again".to_owned(),
+                _ => format!("You're muted for {minutes} minutes"),
+            };
+
+            for id in ids {
+                chat.mute(id, duration);
+                chat.send_to(
+                    id,
+                    &ServerMessage::Notice {
+                        chan,
+                        text: text.clone(),
+                    },
+                );
+            }
+
+            info!(target, minutes, "muted");
+            match minutes {
+                0 => Some(format!("{target} is unmuted")),
+                _ => Some(format!("{target} is muted for {minutes} minutes")),
+            }
+        }
+        name => match chat.commands().bot(name) {
+            Some(bot) => {
+                let commands = chat.commands();
+                let id = commands.invoke(bot, user, chan);
+                let invoke = ServerMessage::Invoke {
+                    id,
+                    chan,
+                    from: user,
+                    name: name.into(),
+                    args: args.into(),
+                };
+
+                if chat.send_to(bot, &invoke) {
+                    None
+                } else {
+                    commands.answer(bot, id);
+                    Some(format!("The bot of /{name} isn't online"))
+                }
+            }
+            None => Some(format!("Unknown command /{name}")),
+        },
+    };
+
+    Ok(reply)
+}
+
+/// Delivers the answer of the bot to the command, either to its user or to the channel.
+/// Returns the channel of the command with the result, or `None` if it's unknown.
+pub async fn answer(
+    chat: &Chat,
+    bot: u32,
+    id: u64,
+    text: &str,
+    public: bool,
+    ip: IpAddr,
+) -> Option<(u32, Result<(), PostError>)> {
+    let Some((user, chan)) = chat.commands().answer(bot, id) else {
+        debug!(id, "answer to an unknown command");
+        return None;
+    };
+
+    if public {
+        return Some((chan, chat.say(bot, chan, text, ip).await));
+    }
+
+    let max_size = chat.limits().text_size;
+    if text.len() > max_size {
+        return Some((chan, Err(PostError::TooLarge { max_size })));
+    }
+
+    let text = text.into();
+    chat.send_to(user, &ServerMessage::Notice { chan, text });
+    Some((chan, Ok(())))
+}
+
+fn usage(name: &str) -> String {
+    let usage = BUILTIN.iter().find(|&&(builtin, ..)| builtin == name);
+    let usage = usage.map_or("", |&(_, usage, _)| usage);
+    format!("Usage: /{name} {usage}")
+}
+
+/// Splits the first word of arguments from the rest.
+fn split(args: &str) -> (&str, &str) {
+    let (first, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    (first, rest.trim())
+}
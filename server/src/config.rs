@@ -0,0 +1,648 @@
+use crate::store::{BlobStore, Credentials, FsStore, S3Store, Url};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+/// The server configuration.
+///
+/// It's read from a TOML file, where every field is optional.
+/// Environment variables and command line flags take precedence over the file.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Local address to listen at.
+    pub address: String,
+    /// Local address to serve metrics at, they aren't served if it's not set.
+    pub metrics_address: Option<String>,
+    pub log_level: LogLevel,
+    pub log_format: LogFormat,
+    /// Write the text of chat messages to logs, only their size is written by default.
+    pub log_messages: bool,
+    pub storage: Storage,
+    pub limits: Limits,
+    pub rate: Rate,
+    pub heartbeat: Heartbeat,
+    pub tls: Option<Tls>,
+    pub shutdown: ShutdownConfig,
+    pub delivery: Delivery,
+    /// Channels created at startup, their ids follow the order.
+    pub channels: Vec<ChannelSeed>,
+    /// Users registered at startup.
+    pub users: Vec<UserSeed>,
+}
+
+impl Config {
+    /// Reads the config from the file.
+    pub fn read(path: &Path) -> Result<Self, ConfigError> {
+        let text =
+            fs::read_to_string(path).map_err(|err| ConfigError::Read(path.to_owned(), err))?;
+
+        toml::from_str(&text).map_err(|err| ConfigError::Parse(path.to_owned(), err))
+    }
+
+    /// Checks the values, so the server doesn't fail later with an obscure error.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |field, reason: &str| Err(ConfigError::Invalid(field, reason.to_owned()));
+
+        let is_address = |address: &str| match address.rsplit_once(':') {
+            Some((host, port)) => !host.is_empty() && port.parse::<u16>().is_ok(),
+            None => false,
+        };
+
+        if !is_address(&self.address) {
+            return invalid("address", "expected host:port");
+        }
+
+        if self
+            .metrics_address
+            .as_deref()
+            .is_some_and(|addr| !is_address(addr))
+        {
+            return invalid("metrics_address", "expected host:port");
+        }
+
+        if let Some(s3) = &self.storage.s3 {
+            if s3.bucket.is_empty() {
+                return invalid("storage.s3.bucket", "must not be empty");
+            }
+        }
+
+        let sizes = [
+            ("limits.upload_size", self.limits.upload_size),
+            ("limits.text_size", self.limits.text_size),
+            ("limits.message_size", self.limits.message_size),
+            ("limits.frame_size", self.limits.frame_size),
+        ];
+
+        for (field, size) in sizes {
+            if size == 0 {
+                return invalid(field, "must be positive");
+            }
+        }
+
+        if self.limits.message_size > base::DECODE_LIMIT {
+            let reason = format!("must not exceed {} bytes", base::DECODE_LIMIT);
+            return invalid("limits.message_size", &reason);
+        }
+
+        if self.limits.frame_size > self.limits.message_size {
+            return invalid("limits.frame_size", "must not exceed limits.message_size");
+        }
+
+        let largest = self.limits.upload_size.max(self.limits.text_size);
+        if largest >= self.limits.message_size {
+            let reason = "must be larger than limits.upload_size and limits.text_size";
+            return invalid("limits.message_size", reason);
+        }
+
+        if self.limits.history == 0 {
+            return invalid("limits.history", "must be positive");
+        }
+
+        let limits = [
+            ("rate.messages", &self.rate.messages),
+            ("rate.uploads", &self.rate.uploads),
+            ("rate.logins", &self.rate.logins),
+            ("rate.signups", &self.rate.signups),
+            ("rate.hooks", &self.rate.hooks),
+        ];
+
+        for (field, limit) in limits {
+            if !(limit.rate > 0. && limit.rate.is_finite()) || limit.burst == 0 {
+                return invalid(field, "rate and burst must be positive");
+            }
+        }
+
+        if self.rate.ip_factor == 0 {
+            return invalid("rate.ip_factor", "must be positive");
+        }
+
+        if self.rate.lockout.failures == 0 {
+            return invalid("rate.lockout.failures", "must be positive");
+        }
+
+        let timeouts = [
+            ("heartbeat.interval", self.heartbeat.interval),
+            ("heartbeat.timeout", self.heartbeat.timeout),
+            ("heartbeat.login", self.heartbeat.login),
+            ("delivery.timeout", self.delivery.timeout),
+            ("delivery.backoff", self.delivery.backoff),
+        ];
+
+        for (field, secs) in timeouts {
+            if secs == 0 {
+                return invalid(field, "must be positive");
+            }
+        }
+
+        if self.delivery.attempts == 0 {
+            return invalid("delivery.attempts", "must be positive");
+        }
+
+        if self.delivery.queue == 0 {
+            return invalid("delivery.queue", "must be positive");
+        }
+
+        if self.delivery.max_backoff < self.delivery.backoff {
+            return invalid("delivery.max_backoff", "must be at least the backoff");
+        }
+
+        if let Some(tls) = &self.tls {
+            if tls.reload == 0 {
+                return invalid("tls.reload", "must be positive");
+            }
+
+            for (field, path) in [("tls.cert", &tls.cert), ("tls.key", &tls.key)] {
+                if let Err(err) = fs::metadata(path) {
+                    return invalid(field, &format!("{}: {err}", path.display()));
+                }
+            }
+        }
+
+        if self.channels.is_empty() {
+            return invalid("channels", "at least one channel is required");
+        }
+
+        let mut webhooks = self.channels.iter().flat_map(|chan| &chan.webhooks);
+        if webhooks.any(|hook| !matches!(hook.url.scheme(), "http" | "https")) {
+            return invalid("channels.webhooks.url", "expected an http or https url");
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let channel = |name: &str, icon: &str| ChannelSeed {
+            name: name.into(),
+            icon: Some(icon.into()),
+            webhooks: vec![],
+        };
+
+        let user = |name: &str, avatar: Option<&str>| UserSeed {
+            name: name.into(),
+            pass: name.into(),
+            avatar: avatar.map(Into::into),
+            admin: name == "admin",
+        };
+
+        Self {
+            address: "0.0.0.0:4567".into(),
+            metrics_address: None,
+            log_level: LogLevel::Info,
+            log_format: LogFormat::Text,
+            log_messages: false,
+            storage: Storage::default(),
+            limits: Limits::default(),
+            rate: Rate::default(),
+            heartbeat: Heartbeat::default(),
+            tls: None,
+            shutdown: ShutdownConfig::default(),
+            delivery: Delivery::default(),
+            channels: vec![
+                channel("Общение", "./images/chatting.png"),
+                channel("Разработка", "./images/development.png"),
+                channel("Программирование", "./images/code.png"),
+                channel("Игры", "./images/games.png"),
+            ],
+            users: vec![
+                user("admin", None),
+                user("test0", Some("./images/test0.jpg")),
+                user("test1", Some("./images/test1.jpg")),
+                user("test2", Some("./images/test2.jpg")),
+                user("test3", Some("./images/test3.jpg")),
+                user("test4", Some("./images/test4.jpg")),
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "info" => Ok(Self::Info),
+            "debug" => Ok(Self::Debug),
+            "trace" => Ok(Self::Trace),
+            _ => Err(format!(
+                "unknown log level {s:?}, expected error, warn, info, debug or trace"
+            )),
+        }
+    }
+}
+
+impl LogLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// A JSON object per line.
+    Json,
+}
+
+impl LogFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Json => "json",
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown log format {s:?}, expected text or json")),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Storage {
+    /// Directory to save uploaded files in.
+    pub files: PathBuf,
+    /// Url the directory of uploaded files is served at.
+    pub files_url: String,
+    /// An S3-compatible storage to save uploaded files in instead of the directory.
+    pub s3: Option<S3>,
+    /// Directory to keep the server state in, like webhooks.
+    /// Without it the state is lost on restart.
+    pub data: Option<PathBuf>,
+}
+
+impl Storage {
+    pub fn store(&self) -> Arc<dyn BlobStore> {
+        match &self.s3 {
+            Some(s3) => {
+                let credentials = Credentials {
+                    access_key: s3.access_key.clone(),
+                    secret_key: s3.secret_key.clone(),
+                };
+
+                let store = S3Store::new(s3.endpoint.clone(), &s3.bucket, &s3.region, credentials);
+                match &s3.url {
+                    Some(url) => Arc::new(store.with_url(url)),
+                    None => Arc::new(store),
+                }
+            }
+            None => Arc::new(FsStore::new(&self.files, &self.files_url)),
+        }
+    }
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self {
+            files: "./static/images".into(),
+            files_url: "./images".into(),
+            s3: None,
+            data: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct S3 {
+    pub endpoint: Url,
+    #[serde(default)]
+    pub bucket: String,
+    #[serde(default = "S3::default_region")]
+    pub region: String,
+    /// Public url of the bucket, if it differs from the endpoint one.
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub access_key: String,
+    #[serde(default)]
+    pub secret_key: String,
+}
+
+impl S3 {
+    pub fn new(endpoint: Url) -> Self {
+        Self {
+            endpoint,
+            bucket: String::new(),
+            region: Self::default_region(),
+            url: None,
+            access_key: String::new(),
+            secret_key: String::new(),
+        }
+    }
+
+    fn default_region() -> String {
+        "us-east-1".into()
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Max size of an uploaded file in bytes.
+    pub upload_size: usize,
+    /// Max size of a text message in bytes.
+    pub text_size: usize,
+    /// Max size of a websocket message in bytes, a larger one closes the connection.
+    pub message_size: usize,
+    /// Max size of a websocket frame in bytes.
+    pub frame_size: usize,
+    /// How many last messages a channel keeps.
+    pub history: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            upload_size: 8 * 1024 * 1024,
+            text_size: 4096,
+            message_size: 9 * 1024 * 1024,
+            frame_size: 9 * 1024 * 1024,
+            history: 1000,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rate {
+    /// Messages and commands a user can send.
+    pub messages: Limit,
+    /// Files a user can upload.
+    pub uploads: Limit,
+    /// Login attempts from an address.
+    pub logins: Limit,
+    /// Sign-ups from an address.
+    pub signups: Limit,
+    /// Messages posted with a webhook.
+    pub hooks: Limit,
+    /// How many times the message and upload limits of an address
+    /// are larger than the ones of a user, since users can share it.
+    pub ip_factor: u32,
+    pub lockout: Lockout,
+}
+
+impl Default for Rate {
+    fn default() -> Self {
+        Self {
+            messages: Limit::new(5., 10),
+            uploads: Limit::new(0.2, 3),
+            logins: Limit::new(0.5, 5),
+            signups: Limit::new(0.05, 3),
+            hooks: Limit::new(1., 20),
+            ip_factor: 4,
+            lockout: Lockout::default(),
+        }
+    }
+}
+
+/// A token bucket.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limit {
+    /// How many actions per second are allowed in the long run.
+    pub rate: f64,
+    /// How many actions can be done at once.
+    pub burst: u32,
+}
+
+impl Limit {
+    pub fn new(rate: f64, burst: u32) -> Self {
+        Self { rate, burst }
+    }
+
+    pub fn scale(self, factor: u32) -> Self {
+        Self {
+            rate: self.rate * factor as f64,
+            burst: self.burst.saturating_mul(factor),
+        }
+    }
+}
+
+/// Locks a user name for an address after failed logins in a row from it.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Lockout {
+    /// How many failed logins from an address lock the name for it.
+    pub failures: u32,
+    /// Seconds the name is locked for.
+    pub duration: u64,
+}
+
+impl Default for Lockout {
+    fn default() -> Self {
+        Self {
+            failures: 5,
+            duration: 300,
+        }
+    }
+}
+
+/// Detects dead connections and ones which never log in.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Heartbeat {
+    /// Seconds without frames from a client before it's pinged.
+    pub interval: u64,
+    /// Seconds to wait for any frame after a ping before the connection is closed.
+    pub timeout: u64,
+    /// Seconds a connection can stay without logging in.
+    pub login: u64,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            interval: 20,
+            timeout: 10,
+            login: 30,
+        }
+    }
+}
+
+/// Retries of events sent to webhook endpoints.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Delivery {
+    /// Seconds to wait for an endpoint to respond.
+    pub timeout: u64,
+    /// How many times an event is sent before it's dropped.
+    pub attempts: u32,
+    /// Seconds before the first retry, the delay doubles after every failure.
+    pub backoff: u64,
+    /// Max seconds between retries.
+    pub max_backoff: u64,
+    /// How many events can wait for an endpoint, the oldest ones are dropped over it.
+    pub queue: usize,
+}
+
+impl Default for Delivery {
+    fn default() -> Self {
+        Self {
+            timeout: 10,
+            attempts: 10,
+            backoff: 1,
+            max_backoff: 600,
+            queue: 10000,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tls {
+    /// PEM file with the certificate chain.
+    pub cert: PathBuf,
+    /// PEM file with the private key.
+    pub key: PathBuf,
+    /// Seconds between checks if the files have changed and should be reloaded.
+    #[serde(default = "Tls::default_reload")]
+    pub reload: u64,
+}
+
+impl Tls {
+    pub fn new(cert: PathBuf, key: PathBuf) -> Self {
+        Self {
+            cert,
+            key,
+            reload: Self::default_reload(),
+        }
+    }
+
+    fn default_reload() -> u64 {
+        60
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// Seconds to wait for connections to close.
+    pub timeout: u64,
+    /// Seconds clients should wait before reconnecting.
+    pub reconnect_after: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 10,
+            reconnect_after: 5,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelSeed {
+    pub name: String,
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Endpoints notified about events of the channel.
+    #[serde(default)]
+    pub webhooks: Vec<Subscription>,
+}
+
+/// An endpoint which gets events as signed JSON.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Subscription {
+    pub url: Url,
+    /// Key of the signature, so the endpoint can check requests come from the server.
+    pub secret: String,
+    /// Events to send, all of them if it's empty.
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+}
+
+impl Subscription {
+    pub fn wants(&self, kind: EventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    /// A text message is posted.
+    Message,
+    /// A file is uploaded.
+    Upload,
+    /// A user comes online, other connections of an online user don't count.
+    Join,
+}
+
+impl EventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Message => "message",
+            Self::Upload => "upload",
+            Self::Join => "join",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserSeed {
+    pub name: String,
+    pub pass: String,
+    #[serde(default)]
+    pub avatar: Option<String>,
+    /// Admins manage webhooks.
+    #[serde(default)]
+    pub admin: bool,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(&'static str, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Read(path, err) => write!(f, "failed to read {}: {err}", path.display()),
+            Self::Parse(path, err) => write!(f, "failed to parse {}: {err}", path.display()),
+            Self::Invalid(field, reason) => write!(f, "invalid {field}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
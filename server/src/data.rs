@@ -0,0 +1,48 @@
+use rand::Rng;
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fs, io, path::Path};
+
+/// Reads the JSON file of the data directory, or returns the default if there's no one.
+pub fn load<T>(file: Option<&Path>) -> io::Result<T>
+where
+    T: DeserializeOwned + Default,
+{
+    let Some(file) = file else {
+        return Ok(T::default());
+    };
+
+    match fs::read(file) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Writes the JSON file of the data directory, it's skipped if the directory isn't set.
+pub fn save<T>(file: Option<&Path>, value: &T) -> io::Result<()>
+where
+    T: Serialize,
+{
+    let Some(file) = file else {
+        return Ok(());
+    };
+
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Write a new file aside and swap it, so a crash doesn't leave a broken one
+    let temp = file.with_extension("json.tmp");
+    fs::write(&temp, serde_json::to_vec_pretty(value)?)?;
+    fs::rename(temp, file)
+}
+
+/// Makes a random token, only its hash is supposed to be saved.
+pub fn token() -> String {
+    hex::encode(rand::thread_rng().gen::<[u8; 32]>())
+}
+
+pub fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,72 @@
+use crate::config::ConfigError;
+use base::EncodeError;
+use std::{fmt, io};
+use tokio::task::JoinError;
+use websocket::tungstenite;
+
+#[derive(Debug)]
+pub enum Error {
+    Config(ConfigError),
+    Bind(io::Error),
+    Tls(io::Error),
+    Handshake(io::Error),
+    Websocket(Box<tungstenite::Error>),
+    Encode(EncodeError),
+    Image(image::ImageError),
+    Store(io::Error),
+    Data(io::Error),
+    Task(JoinError),
+    Timeout(&'static str),
+    ShuttingDown,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Config(err) => write!(f, "config error: {err}"),
+            Self::Bind(err) => write!(f, "failed to bind: {err}"),
+            Self::Tls(err) => write!(f, "failed to load tls certificate: {err}"),
+            Self::Handshake(err) => write!(f, "tls handshake error: {err}"),
+            Self::Websocket(err) => write!(f, "websocket error: {err}"),
+            Self::Encode(err) => write!(f, "encode error: {err}"),
+            Self::Image(err) => write!(f, "image error: {err}"),
+            Self::Store(err) => write!(f, "store error: {err}"),
+            Self::Data(err) => write!(f, "failed to load data: {err}"),
+            Self::Task(err) => write!(f, "task error: {err}"),
+            Self::Timeout(what) => write!(f, "{what} timed out"),
+            Self::ShuttingDown => write!(f, "server is shutting down"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ConfigError> for Error {
+    fn from(err: ConfigError) -> Self {
+        Self::Config(err)
+    }
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(err: tungstenite::Error) -> Self {
+        Self::Websocket(Box::new(err))
+    }
+}
+
+impl From<EncodeError> for Error {
+    fn from(err: EncodeError) -> Self {
+        Self::Encode(err)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(err: image::ImageError) -> Self {
+        Self::Image(err)
+    }
+}
+
+impl From<JoinError> for Error {
+    fn from(err: JoinError) -> Self {
+        Self::Task(err)
+    }
+}
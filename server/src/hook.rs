@@ -0,0 +1,156 @@
+use crate::{
+    chat::PostError,
+    data::{self, hash},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+/// The file webhooks are kept in, inside the data directory.
+const FILE: &str = "hooks.json";
+
+/// An incoming webhook which posts to a channel without a user account.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Hook {
+    pub id: u32,
+    pub chan: u32,
+    /// Shown as the sender unless a message overrides it.
+    pub name: String,
+    pub avatar: Option<String>,
+    /// The admin who created it.
+    pub created_by: u32,
+    /// Only a hash of the token is kept, the token is shown once when it's created.
+    token_hash: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct State {
+    next_id: u32,
+    hooks: Vec<Hook>,
+}
+
+/// Webhooks saved in the data directory if it's set.
+pub struct Hooks {
+    file: Option<PathBuf>,
+    state: Mutex<State>,
+}
+
+impl Hooks {
+    /// Reads webhooks from the data directory or starts with none.
+    pub fn load(data: Option<&Path>) -> io::Result<Self> {
+        let file = data.map(|dir| dir.join(FILE));
+        let state = data::load(file.as_deref())?;
+
+        Ok(Self {
+            file,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Creates a webhook and returns it with its token.
+    pub fn create(
+        &self,
+        chan: u32,
+        name: &str,
+        avatar: Option<&str>,
+        created_by: u32,
+    ) -> io::Result<(Hook, String)> {
+        let token = data::token();
+        let mut state = self.lock();
+        let hook = Hook {
+            id: state.next_id,
+            chan,
+            name: name.to_owned(),
+            avatar: avatar.map(Into::into),
+            created_by,
+            token_hash: hash(&token),
+        };
+
+        // The id is taken only if it's saved, a failed save leaves no gap
+        state.hooks.push(hook.clone());
+        state.next_id += 1;
+        if let Err(err) = self.save(&state) {
+            state.next_id -= 1;
+            state.hooks.pop();
+            return Err(err);
+        }
+
+        Ok((hook, token))
+    }
+
+    /// Returns webhooks of the channel.
+    pub fn list(&self, chan: u32) -> Vec<Hook> {
+        let state = self.lock();
+        let hooks = state.hooks.iter().filter(|hook| hook.chan == chan);
+        hooks.cloned().collect()
+    }
+
+    /// Removes the webhook, so its token stops working.
+    /// Returns `false` if there's no such webhook.
+    pub fn revoke(&self, id: u32) -> io::Result<bool> {
+        let mut state = self.lock();
+        let Some(pos) = state.hooks.iter().position(|hook| hook.id == id) else {
+            return Ok(false);
+        };
+
+        let hook = state.hooks.remove(pos);
+        if let Err(err) = self.save(&state) {
+            state.hooks.insert(pos, hook);
+            return Err(err);
+        }
+
+        Ok(true)
+    }
+
+    /// Finds the webhook by its token.
+    pub fn find(&self, token: &str) -> Option<Hook> {
+        let token_hash = hash(token);
+        let state = self.lock();
+        let mut hooks = state.hooks.iter();
+        hooks.find(|hook| hook.token_hash == token_hash).cloned()
+    }
+
+    fn save(&self, state: &State) -> io::Result<()> {
+        data::save(self.file.as_deref(), state)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Why a webhook can't be managed or used.
+#[derive(Debug)]
+pub enum HookError {
+    NotAdmin,
+    UnknownChannel,
+    UnknownHook,
+    Post(PostError),
+    Save(io::Error),
+}
+
+impl fmt::Display for HookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotAdmin => write!(f, "only admins manage webhooks"),
+            Self::UnknownChannel => write!(f, "unknown channel"),
+            Self::UnknownHook => write!(f, "unknown webhook"),
+            Self::Post(err) => write!(f, "{err}"),
+            Self::Save(err) => write!(f, "couldn't save webhooks: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for HookError {}
+
+impl From<PostError> for HookError {
+    fn from(err: PostError) -> Self {
+        match err {
+            PostError::UnknownChannel => Self::UnknownChannel,
+            err => Self::Post(err),
+        }
+    }
+}
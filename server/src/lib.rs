@@ -1,18 +1,45 @@
 mod args;
-mod event;
+mod bot;
+mod channel;
+mod chat;
+pub mod client;
+mod command;
+pub mod config;
+mod data;
+mod error;
+mod hook;
+mod limit;
 mod listen;
+pub mod log;
 mod manage;
+pub mod metrics;
+mod notify;
+mod server;
+pub mod service;
+mod shutdown;
+pub mod store;
+pub mod thumb;
+mod tls;
 
-use self::{args::Args, listen::listen, manage::manage};
-use tokio::sync::mpsc;
+pub use self::{
+    error::Error,
+    server::{Acceptor, Server, ServerBuilder},
+};
 
-pub async fn run() {
+use self::args::Args;
+
+/// Runs the server configured with command line flags until it's terminated.
+pub async fn run() -> Result<(), Error> {
     use clap::Parser;
 
-    let args = Args::parse();
+    let config = Args::parse().config()?;
+    log::init(config.log_level, config.log_format);
+    let server = Server::bind(config).await?;
+    if let Some(addr) = server.local_addr() {
+        tracing::info!(%addr, "listening");
+    }
 
-    let (sender, receiver) = mpsc::channel(16);
-    let listen = tokio::spawn(listen(args.address(), sender));
-    let manage = tokio::spawn(manage(receiver));
-    let _ = tokio::join!(listen, manage);
+    shutdown::terminated().await;
+    server.shutdown().await;
+    Ok(())
 }
@@ -0,0 +1,246 @@
+use crate::config::{Limit, Rate};
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// How many entries a table can have before the stale ones are removed.
+const PRUNE_AT: usize = 4096;
+
+/// How often a large table is pruned at most, so new keys don't scan it every time.
+const PRUNE_EVERY: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Message,
+    Upload,
+    Login,
+    SignUp,
+    Hook,
+}
+
+/// Token buckets of users and addresses, and failed logins of user names from addresses.
+pub struct Limiter {
+    rate: Rate,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    users: Table<(Action, u32), Bucket>,
+    ips: Table<(Action, IpAddr), Bucket>,
+    failures: Table<(String, IpAddr), Failures>,
+}
+
+impl Limiter {
+    pub fn new(rate: Rate) -> Self {
+        Self {
+            rate,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Takes a token of the user and the address for the action,
+    /// or returns how long to wait until it's allowed.
+    pub fn check(&self, action: Action, user: Option<u32>, ip: IpAddr) -> Result<(), Duration> {
+        let limit = match action {
+            Action::Message => self.rate.messages,
+            Action::Upload => self.rate.uploads,
+            Action::Login => self.rate.logins,
+            Action::SignUp => self.rate.signups,
+            Action::Hook => self.rate.hooks,
+        };
+
+        // An address is shared by many users, so it has a larger limit
+        let ip_limit = match action {
+            Action::Message | Action::Upload | Action::Hook => limit.scale(self.rate.ip_factor),
+            Action::Login | Action::SignUp => limit,
+        };
+
+        let now = Instant::now();
+        let mut state = self.lock();
+        let ip_bucket = state.ips.get(&(action, ip), now, &ip_limit);
+        let ip_wait = ip_bucket.wait(&ip_limit);
+        let user_wait = match user {
+            Some(id) => {
+                let user_bucket = state.users.get(&(action, id), now, &limit);
+                user_bucket.wait(&limit)
+            }
+            None => Duration::ZERO,
+        };
+
+        let wait = ip_wait.max(user_wait);
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+
+        state.ips.get(&(action, ip), now, &ip_limit).take();
+        if let Some(id) = user {
+            state.users.get(&(action, id), now, &limit).take();
+        }
+
+        Ok(())
+    }
+
+    /// Returns how long the user name is locked for the address after failed logins.
+    ///
+    /// The lockout is per address, so failures from elsewhere
+    /// can't keep the user out.
+    pub fn locked(&self, name: &str, ip: IpAddr) -> Option<Duration> {
+        let now = Instant::now();
+        let state = self.lock();
+        let until = state
+            .failures
+            .map
+            .get(&(name.to_owned(), ip))?
+            .locked_until?;
+        until.checked_duration_since(now)
+    }
+
+    pub fn login_failed(&self, name: &str, ip: IpAddr) {
+        let now = Instant::now();
+        let failures = self.rate.lockout.failures;
+        let duration = Duration::from_secs(self.rate.lockout.duration);
+        let mut state = self.lock();
+        let entry = state.failures.get(&(name.to_owned(), ip), now, &duration);
+        entry.count += 1;
+        if entry.count >= failures {
+            entry.count = 0;
+            entry.locked_until = Some(now + duration);
+        }
+    }
+
+    pub fn login_succeeded(&self, name: &str, ip: IpAddr) {
+        self.lock().failures.map.remove(&(name.to_owned(), ip));
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A map which forgets entries that don't matter anymore.
+struct Table<K, V> {
+    map: HashMap<K, V>,
+    pruned: Option<Instant>,
+}
+
+impl<K, V> Default for Table<K, V> {
+    fn default() -> Self {
+        Self {
+            map: HashMap::default(),
+            pruned: None,
+        }
+    }
+}
+
+trait Entry<P> {
+    fn new(now: Instant, params: &P) -> Self;
+    fn update(&mut self, now: Instant, params: &P);
+    fn is_stale(&self, now: Instant) -> bool;
+}
+
+impl<K, V> Table<K, V>
+where
+    K: Eq + Hash,
+{
+    fn get<Q, P>(&mut self, key: &Q, now: Instant, params: &P) -> &mut V
+    where
+        Q: ToOwned<Owned = K> + Eq + Hash + ?Sized,
+        K: std::borrow::Borrow<Q>,
+        V: Entry<P>,
+    {
+        let due = self
+            .pruned
+            .is_none_or(|pruned| now.saturating_duration_since(pruned) >= PRUNE_EVERY);
+
+        if due && self.map.len() >= PRUNE_AT {
+            self.map.retain(|_, entry| !entry.is_stale(now));
+            self.pruned = Some(now);
+        }
+
+        let entry = self
+            .map
+            .entry(key.to_owned())
+            .or_insert_with(|| V::new(now, params));
+
+        entry.update(now, params);
+        entry
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    full_at: Instant,
+}
+
+impl Bucket {
+    fn wait(&self, limit: &Limit) -> Duration {
+        if self.tokens >= 1. {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1. - self.tokens) / limit.rate)
+        }
+    }
+
+    fn take(&mut self) {
+        self.tokens -= 1.;
+    }
+}
+
+impl Entry<Limit> for Bucket {
+    fn new(now: Instant, limit: &Limit) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            updated: now,
+            full_at: now,
+        }
+    }
+
+    fn update(&mut self, now: Instant, limit: &Limit) {
+        let burst = limit.burst as f64;
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.rate).min(burst);
+        self.updated = now;
+        self.full_at = now + Duration::from_secs_f64((burst - self.tokens) / limit.rate);
+    }
+
+    fn is_stale(&self, now: Instant) -> bool {
+        // A full bucket is the same as a new one
+        self.full_at <= now
+    }
+}
+
+/// Failed logins in a row, forgotten when the lockout duration passes after the last one.
+struct Failures {
+    count: u32,
+    locked_until: Option<Instant>,
+    expires: Instant,
+}
+
+impl Entry<Duration> for Failures {
+    fn new(now: Instant, _: &Duration) -> Self {
+        Self {
+            count: 0,
+            locked_until: None,
+            expires: now,
+        }
+    }
+
+    fn update(&mut self, now: Instant, duration: &Duration) {
+        if self.expires <= now {
+            self.count = 0;
+            self.locked_until = None;
+        }
+
+        self.expires = now + *duration;
+    }
+
+    fn is_stale(&self, now: Instant) -> bool {
+        self.expires <= now
+    }
+}
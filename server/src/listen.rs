@@ -1,85 +1,209 @@
-use crate::event::*;
+use crate::{
+    chat::Chat,
+    client::{frame, Client},
+    config::Limits,
+    error::Error,
+    manage::Session,
+    shutdown::{Shutdown, Signal},
+};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::{
-    net::{TcpListener, TcpStream},
-    sync::{
-        mpsc::{self, Sender},
-        oneshot,
-    },
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::mpsc,
+    time::{self, Instant},
+};
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, field, info, info_span, warn, Instrument, Span};
+use websocket::{
+    tungstenite::{self, error::ProtocolError, protocol::WebSocketConfig, Message},
+    WebSocketStream,
 };
-use websocket::tungstenite::{self as ws, Message};
 
-pub async fn listen(addr: String, sender: Sender<Event>) -> ! {
-    let listener = TcpListener::bind(addr).await.expect("bind");
-    let local_addr = listener.local_addr().expect("should have a local adders");
-    println!("listening at {local_addr}");
+/// How many messages can wait to be sent to a client.
+/// A client with the full queue is considered too slow and gets disconnected.
+const CLIENT_QUEUE: usize = 1024;
 
+/// Accepts connections, terminating TLS if the acceptor is set.
+pub async fn listen(
+    listener: &TcpListener,
+    chat: Arc<Chat>,
+    tls: Option<TlsAcceptor>,
+    shutdown: &Shutdown,
+) -> ! {
     loop {
         match listener.accept().await {
             Ok((stream, addr)) => {
-                let connect_sender = sender.clone();
-                let close_sender = sender.clone();
-
-                tokio::spawn(async move {
-                    if let Err(err) = connect(stream, connect_sender).await {
-                        eprintln!("websocket error: {err:?}");
-                    }
+                let chat = Arc::clone(&chat);
+                let tls = tls.clone();
+                let Some(signal) = shutdown.signal() else {
+                    continue;
+                };
 
-                    // Better treat with it via guard
-                    let event = Event {
-                        from: addr,
-                        what: What::CloseConnection,
+                // Every connection is handled in its own task,
+                // so the runtime can spread them over its threads
+                // and an error of one connection doesn't affect others
+                let task = async move {
+                    // A peer which never finishes the handshakes would hold
+                    // the task and delay the shutdown, so they have the login time
+                    let login = Duration::from_secs(chat.heartbeat().login);
+                    let deadline = Instant::now() + login;
+                    let res = match tls {
+                        Some(tls) => match time::timeout_at(deadline, tls.accept(stream)).await {
+                            Ok(Ok(stream)) => connect(stream, addr, chat, signal, deadline).await,
+                            Ok(Err(err)) => Err(Error::Handshake(err)),
+                            Err(_) => Err(Error::Timeout("handshake")),
+                        },
+                        None => connect(stream, addr, chat, signal, deadline).await,
                     };
-                    let _ = close_sender.send(event).await;
 
-                    println!("connection closed {addr}");
-                });
+                    closed(res);
+                };
+
+                tokio::spawn(task.instrument(span(addr)));
             }
             Err(err) => {
-                eprintln!("couldn't get client: {err:?}");
+                warn!(error = %err, "couldn't get client");
                 continue;
             }
         }
     }
 }
 
-async fn connect(stream: TcpStream, sender: Sender<Event>) -> Result<(), ws::Error> {
+async fn connect<S>(
+    stream: S,
+    addr: SocketAddr,
+    chat: Arc<Chat>,
+    signal: Signal,
+    deadline: Instant,
+) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let config = websocket_config(chat.limits());
+    let handshake = websocket::accept_async_with_config(stream, Some(config));
+    let stream = time::timeout_at(deadline, handshake)
+        .await
+        .map_err(|_| Error::Timeout("handshake"))??;
+
+    serve(stream, addr, chat, signal).await
+}
+
+/// The span of a connection, every record inside it has the address
+/// and, once the client is logged in, the user id.
+pub fn span(addr: SocketAddr) -> Span {
+    info_span!("connection", %addr, user = field::Empty)
+}
+
+/// Logs how the connection has ended.
+pub fn closed(res: Result<(), Error>) {
+    match res {
+        Ok(()) => info!("connection closed"),
+        // Probes like health checks only open a connection
+        Err(Error::Websocket(err))
+            if matches!(
+                *err,
+                tungstenite::Error::Protocol(ProtocolError::HandshakeIncomplete)
+            ) =>
+        {
+            debug!("connection closed before the handshake");
+        }
+        Err(err) => info!(error = %err, "connection closed"),
+    }
+}
+
+/// Limits what a client can send, so it can't exhaust the memory.
+pub fn websocket_config(limits: &Limits) -> WebSocketConfig {
+    WebSocketConfig::default()
+        .max_message_size(Some(limits.message_size))
+        .max_frame_size(Some(limits.frame_size))
+}
+
+/// Serves the websocket client until it leaves or the server shuts down.
+pub async fn serve<S>(
+    stream: WebSocketStream<S>,
+    addr: SocketAddr,
+    chat: Arc<Chat>,
+    mut signal: Signal,
+) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    use base::api::ServerMessage;
     use futures::{SinkExt, StreamExt};
 
-    let addr = stream.peer_addr().expect("peer address");
-    let stream = websocket::accept_async(stream).await?;
-    println!("new websocket client: {addr}");
-
-    let (client_sender, mut receiver) = mpsc::channel(16);
-    let (close_sender, mut close_receiver) = oneshot::channel();
-    let event = Event {
-        from: addr,
-        what: What::NewConnection {
-            sender: client_sender,
-            close: close_sender,
-        },
-    };
-    let _ = sender.send(event).await;
+    debug!("websocket connected");
 
+    let heartbeat = chat.heartbeat();
+    let interval = Duration::from_secs(heartbeat.interval);
+    let timeout = Duration::from_secs(heartbeat.timeout);
+    let login = time::sleep(Duration::from_secs(heartbeat.login));
+    let idle = time::sleep(interval);
+    tokio::pin!(login, idle);
+
+    let (sender, mut receiver) = mpsc::channel(CLIENT_QUEUE);
+    let client = Arc::new(Client::new(addr, sender));
+    let mut session = Session::new(chat, Arc::clone(&client));
+
+    // A client is pinged when it's silent for a while,
+    // so a dead connection is noticed even if nothing is sent to it
+    let mut pinged = false;
     let (mut write, mut read) = stream.split();
     loop {
         tokio::select! {
-            Some(res) = read.next() => {
-                match res? {
-                    Message::Binary(bytes) => {
-                        let event = Event {
-                            from: addr,
-                            what: What::BytesReceived(bytes),
-                        };
-
-                        let _ = sender.send(event).await;
+            res = read.next() => {
+                idle.as_mut().reset(Instant::now() + interval);
+                pinged = false;
+                match res.transpose()? {
+                    Some(Message::Binary(bytes)) => {
+                        for reply in session.handle(&bytes).await {
+                            write.feed(Message::Binary(frame(&reply)?)).await?;
+                        }
+
+                        write.flush().await?;
                     }
-                    Message::Ping(bytes) => write.send(Message::Pong(bytes)).await?,
-                    Message::Close(_) => return Ok(()),
+                    Some(Message::Ping(bytes)) => write.send(Message::Pong(bytes)).await?,
+                    Some(Message::Close(_)) | None => return Ok(()),
                     _ => {}
                 }
             }
-            Some(bytes) = receiver.recv() => write.send(Message::Binary(bytes)).await?,
-            Ok(()) = &mut close_receiver => return Ok(()),
+            Some(frame) = receiver.recv() => write.send(Message::Binary(frame)).await?,
+            () = client.closed() => return Ok(()),
+            () = &mut idle => {
+                if pinged {
+                    return Err(Error::Timeout("heartbeat"));
+                }
+
+                write.send(Message::Ping(Default::default())).await?;
+                idle.as_mut().reset(Instant::now() + timeout);
+                pinged = true;
+            }
+            () = &mut login, if !session.is_logged() => {
+                write.feed(Message::Binary(frame(&ServerMessage::Closed)?)).await?;
+                write.send(Message::Close(None)).await?;
+                return Err(Error::Timeout("login"));
+            }
+            () = signal.stopping() => break,
         }
     }
+
+    // Keep sending messages until all requests are handled
+    signal.idle();
+    loop {
+        tokio::select! {
+            Some(frame) = receiver.recv() => write.send(Message::Binary(frame)).await?,
+            () = client.closed() => return Ok(()),
+            () = signal.closing() => break,
+        }
+    }
+
+    while let Ok(frame) = receiver.try_recv() {
+        write.feed(Message::Binary(frame)).await?;
+    }
+
+    let reconnect_after = signal.reconnect_after().as_secs() as u32;
+    let message = ServerMessage::ServerShutdown { reconnect_after };
+    write.feed(Message::Binary(frame(&message)?)).await?;
+    write.send(Message::Close(None)).await?;
+    Ok(())
 }
@@ -0,0 +1,17 @@
+use crate::config::{LogFormat, LogLevel};
+use tracing_subscriber::EnvFilter;
+
+/// Installs the global subscriber which writes logs to stdout.
+///
+/// `RUST_LOG` takes precedence over the level, so it can be set per module.
+/// Records of the `log` crate, like the ones of Rocket, are written too.
+/// Does nothing if a subscriber is already installed.
+pub fn init(level: LogLevel, format: LogFormat) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level.as_str()));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    let _ = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).try_init(),
+    };
+}
@@ -1,6 +1,13 @@
 use server::run;
+use std::process::ExitCode;
 
 #[tokio::main]
-async fn main() {
-    run().await;
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
 }
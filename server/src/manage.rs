@@ -1,274 +1,351 @@
-use crate::event::*;
-use base::{api, decode, encode};
-use rand::Rng;
-use std::{
-    collections::{hash_map::Entry, HashMap},
-    net::SocketAddr,
-    sync::Arc,
-};
-use tokio::sync::{
-    mpsc::{Receiver, Sender},
-    oneshot::Sender as Close,
+use crate::{
+    chat::{locked, Chat, PostError},
+    client::Client,
+    command,
+    error::Error,
+    limit::Action,
+    metrics::METRICS,
+    store::BlobStore,
+    thumb,
 };
-
-#[derive(Clone)]
-struct User {
-    id: u32,
-    name: String,
-    avatar: Option<String>,
+use base::{api, decode};
+use image::ImageError;
+use rand::Rng;
+use std::{sync::Arc, time::Duration};
+use tracing::{debug, info, warn, Span};
+
+/// Handles requests of a single connection.
+pub struct Session {
+    chat: Arc<Chat>,
+    client: Arc<Client>,
+    logged: Option<u32>,
 }
 
-struct Users {
-    ids: HashMap<u32, Arc<User>>,
-    names: HashMap<(String, String), Arc<User>>,
-}
+impl Session {
+    pub fn new(chat: Arc<Chat>, client: Arc<Client>) -> Self {
+        METRICS.connect();
+        Self {
+            chat,
+            client,
+            logged: None,
+        }
+    }
 
-impl Users {
-    fn new() -> Self {
-        let mut users = Self {
-            ids: HashMap::default(),
-            names: HashMap::default(),
-        };
+    /// Handles the request and returns replies for the client.
+    ///
+    /// Replies are written straight to the connection, so they aren't limited
+    /// by the client queue like messages from other tasks are.
+    pub async fn handle(&mut self, bytes: &[u8]) -> Vec<api::ServerMessage> {
+        use api::*;
+
+        let ip = self.client.addr().ip();
+        let chat = Arc::clone(&self.chat);
+        let limiter = chat.limiter();
+        let message = match decode(bytes) {
+            Ok(message) => match message {
+                ClientMessage::SignUp { name, pass } => {
+                    let logged = match self.logged {
+                        Some(_) => Err(LoginError::AlreadyLogged),
+                        None => match limiter.check(Action::SignUp, None, ip) {
+                            Ok(()) => self
+                                .chat
+                                .users_mut()
+                                .push_new(name, pass, None)
+                                .ok_or(LoginError::NameAlreadyExists),
+                            Err(wait) => Err(locked(wait)),
+                        },
+                    };
 
-        users.push_new("admin", "admin", None);
-        users.push_new("test0", "test0", Some("./images/test0.jpg"));
-        users.push_new("test1", "test1", Some("./images/test1.jpg"));
-        users.push_new("test2", "test2", Some("./images/test2.jpg"));
-        users.push_new("test3", "test3", Some("./images/test3.jpg"));
-        users.push_new("test4", "test4", Some("./images/test4.jpg"));
-        users
-    }
+                    if let Ok(id) = logged {
+                        info!(id, "signed up");
+                    }
 
-    fn push_new(&mut self, name: &str, pass: &str, avatar: Option<&str>) -> Option<u32> {
-        let name = name.to_owned();
-        let pass = pass.to_owned();
-        let id = self.ids.len() as u32;
+                    ServerMessage::LoggedIn(logged)
+                }
+                ClientMessage::Login { name, pass } => {
+                    let logged = match self.logged {
+                        Some(_) => Err(LoginError::AlreadyLogged),
+                        None => self.chat.authenticate(name, pass, ip),
+                    };
+
+                    match logged {
+                        Ok(id) => {
+                            self.log_in(id);
+                            info!("logged in");
+                        }
+                        Err(LoginError::WrongNameOrPass) => info!(name, "wrong name or password"),
+                        Err(_) => {}
+                    }
 
-        match self.names.entry((name.clone(), pass)) {
-            Entry::Occupied(_) => None,
-            Entry::Vacant(en) => {
-                let user = Arc::new(User {
-                    id,
+                    ServerMessage::LoggedIn(logged)
+                }
+                ClientMessage::BotLogin { token } => {
+                    let logged = match self.logged {
+                        Some(_) => Err(LoginError::AlreadyLogged),
+                        None => self.chat.verify_bot(token, ip),
+                    };
+
+                    match logged {
+                        Ok(id) => {
+                            self.log_in(id);
+                            info!("bot logged in");
+                        }
+                        Err(LoginError::WrongToken) => info!("wrong bot token"),
+                        Err(_) => {}
+                    }
+
+                    ServerMessage::LoggedIn(logged)
+                }
+                ClientMessage::Say {
+                    request,
+                    chan,
+                    text,
+                } => match self.logged {
+                    Some(id) => match self.chat.say(id, chan, text, ip).await {
+                        Ok(()) => return vec![],
+                        Err(err) => match post_error(request, chan, err) {
+                            Some(reply) => reply,
+                            None => return vec![],
+                        },
+                    },
+                    None => ServerMessage::Closed,
+                },
+                ClientMessage::File {
+                    request,
+                    chan,
+                    ext,
+                    bytes,
+                } => match self.logged {
+                    Some(id) => {
+                        if let Err(err) = self.chat.check_muted(id) {
+                            return post_error(request, chan, err).into_iter().collect();
+                        }
+
+                        if let Err(wait) = limiter.check(Action::Upload, Some(id), ip) {
+                            return vec![rate_limited(request, wait)];
+                        }
+
+                        let limit = self.chat.limits().upload_size;
+                        if bytes.len() > limit {
+                            return vec![too_large(request, limit)];
+                        }
+
+                        let store = self.chat.store();
+                        let (thumb, orig) = match save_file(store, ext, bytes).await {
+                            Ok(saved) => saved,
+                            Err(Error::Image(err)) => {
+                                debug!(error = %err, "rejected file");
+                                let text = rejected(&err);
+                                return vec![ServerMessage::Notice { chan, text }];
+                            }
+                            Err(err) => {
+                                warn!(error = %err, "couldn't save file");
+                                return vec![];
+                            }
+                        };
+                        debug!(chan, url = orig, "file saved");
+                        METRICS.upload(bytes.len());
+
+                        let message = Message {
+                            from: id,
+                            chan,
+                            content: MessageType::File { thumb, orig },
+                            author: None,
+                        };
+
+                        self.post(message).await;
+                        return vec![];
+                    }
+                    None => ServerMessage::Closed,
+                },
+                ClientMessage::Command {
+                    request,
+                    chan,
+                    name,
+                    args,
+                } => match self.logged {
+                    Some(id) => match command::run(&chat, id, chan, name, args, ip).await {
+                        Ok(Some(text)) => ServerMessage::Notice { chan, text },
+                        Ok(None) => return vec![],
+                        Err(err) => match post_error(request, chan, err) {
+                            Some(reply) => reply,
+                            None => return vec![],
+                        },
+                    },
+                    None => ServerMessage::Closed,
+                },
+                ClientMessage::RegisterCommand {
                     name,
-                    avatar: avatar.map(Into::into),
-                });
-                en.insert(Arc::clone(&user));
-                self.ids.insert(id, user);
-                Some(id)
+                    usage,
+                    description,
+                } => match self.logged {
+                    Some(id) if chat.users().is_bot(id) => {
+                        let commands = chat.commands();
+                        if commands.register(id, name, usage, description) {
+                            debug!(name, "command registered");
+                            chat.broadcast(&ServerMessage::Commands(commands.list()));
+                        } else {
+                            info!(name, "couldn't register a command");
+                        }
+
+                        return vec![];
+                    }
+                    // Only bots register commands
+                    Some(_) => return vec![],
+                    None => ServerMessage::Closed,
+                },
+                ClientMessage::CommandReply { id, text, public } => match self.logged {
+                    Some(bot) => match command::answer(&chat, bot, id, text, public, ip).await {
+                        Some((chan, Err(err))) => match post_error(NO_REQUEST, chan, err) {
+                            Some(reply) => reply,
+                            None => return vec![],
+                        },
+                        Some((_, Ok(()))) | None => return vec![],
+                    },
+                    None => ServerMessage::Closed,
+                },
+            },
+            Err(err) => {
+                debug!(error = ?err, "couldn't decode request");
+                METRICS.decode_error();
+                ServerMessage::Closed
             }
-        }
-    }
+        };
 
-    fn get(&self, name: &str, pass: &str) -> Option<u32> {
-        let key = (name.to_owned(), pass.to_owned());
-        self.names.get(&key).map(|user| user.id)
-    }
+        if let ServerMessage::Closed = message {
+            self.client.close();
+        }
 
-    fn get_by_id(&self, id: u32) -> Option<&User> {
-        self.ids.get(&id).map(Arc::as_ref)
-    }
+        // A sign-up only creates the account, the data comes with the login
+        let send_initial_data =
+            matches!(message, ServerMessage::LoggedIn(Ok(_))) && self.logged.is_some();
+        let mut replies = vec![message];
+
+        if send_initial_data {
+            replies.extend(self.chat.users().iter().map(|user| {
+                ServerMessage::User(User {
+                    id: user.id,
+                    name: user.name,
+                    avatar: user.avatar,
+                    bot: user.bot,
+                })
+            }));
+
+            replies.push(ServerMessage::Commands(self.chat.commands().list()));
+
+            // Every channel sends its history and then new messages.
+            // These come through the client queue, so they're written after the replies
+            for chan in self.chat.channels() {
+                chan.join(Arc::clone(&self.client)).await;
+            }
+        }
 
-    fn iter(&self) -> impl Iterator<Item = User> + '_ {
-        self.ids.values().map(|user| user.as_ref().clone())
+        replies
     }
-}
 
-#[derive(Clone)]
-struct Channel {
-    id: u32,
-    name: String,
-    icon: Option<String>,
-}
+    fn log_in(&mut self, id: u32) {
+        self.logged = Some(id);
 
-struct Channels(Vec<Channel>);
+        // Another tab or a reconnect of an online user isn't a join
+        if self.chat.go_online(id, Arc::clone(&self.client)) {
+            let users = self.chat.users();
+            let name = users.name(id).unwrap_or_default();
+            for chan in self.chat.channels() {
+                self.chat.notifier().join(chan.info().id, id, name);
+            }
+        }
 
-impl Channels {
-    fn new() -> Self {
-        Self(vec![
-            Channel {
-                id: 0,
-                name: "Общение".into(),
-                icon: Some("./images/chatting.png".into()),
-            },
-            Channel {
-                id: 1,
-                name: "Разработка".into(),
-                icon: Some("./images/development.png".into()),
-            },
-            Channel {
-                id: 2,
-                name: "Программирование".into(),
-                icon: Some("./images/code.png".into()),
-            },
-            Channel {
-                id: 3,
-                name: "Игры".into(),
-                icon: Some("./images/games.png".into()),
-            },
-        ])
+        Span::current().record("user", id);
+        METRICS.log_in();
     }
 
-    fn iter(&self) -> impl Iterator<Item = Channel> + '_ {
-        self.0.iter().cloned()
+    pub fn is_logged(&self) -> bool {
+        self.logged.is_some()
     }
-}
-
-pub async fn manage(mut receiver: Receiver<Event>) -> ! {
-    use api::*;
 
-    struct Client {
-        sender: Sender<Vec<u8>>,
-        close: Option<Close<()>>,
-        logged: Option<u32>,
+    async fn post(&self, message: api::Message) {
+        match self.chat.channel(message.chan) {
+            Some(chan) => chan.post(message).await,
+            None => debug!(chan = message.chan, "unknown channel"),
+        }
     }
+}
 
-    let mut users = Users::new();
-    let channels = Channels::new();
-    let mut clients: HashMap<SocketAddr, Client> = HashMap::default();
-    let mut history = vec![];
-
-    loop {
-        let event = receiver.recv().await.expect("channel is open");
-
-        match event.what {
-            What::NewConnection { sender, close } => {
-                // Remove old client
-                let _ = clients.insert(
-                    event.from,
-                    Client {
-                        sender,
-                        close: Some(close),
-                        logged: None,
-                    },
-                );
+impl Drop for Session {
+    fn drop(&mut self) {
+        // Leave channels however the connection ends, so its client isn't kept there
+        self.client.close();
+        if let Some(id) = self.logged {
+            for chan in self.chat.channels() {
+                chan.leave(Arc::clone(&self.client));
             }
-            What::CloseConnection => {
-                let _ = clients.remove(&event.from);
-            }
-            What::BytesReceived(bytes) => {
-                let client = clients.get_mut(&event.from).expect("client");
 
-                let message = match decode(&bytes) {
-                    Ok(message) => match message {
-                        ClientMessage::SignUp { name, pass } => ServerMessage::LoggedIn(
-                            users
-                                .push_new(name, pass, None)
-                                .ok_or(LoginError::NameAlreadyExists),
-                        ),
-                        ClientMessage::Login { name, pass } => {
-                            let logged = match users.get(name, pass) {
-                                Some(id) => match client.logged {
-                                    Some(_) => Err(LoginError::AlreadyLogged),
-                                    None => {
-                                        client.logged = Some(id);
-                                        Ok(id)
-                                    }
-                                },
-                                None => Err(LoginError::WrongNameOrPass),
-                            };
-
-                            ServerMessage::LoggedIn(logged)
-                        }
-                        ClientMessage::Say { chan, text } => match client.logged {
-                            Some(id) => {
-                                let user = users.get_by_id(id).expect("user");
-                                let name = &user.name;
-                                println!("{name} ({chan}): {text}");
-
-                                let message = Message {
-                                    from: id,
-                                    chan,
-                                    content: MessageType::Text(text.into()),
-                                };
-
-                                // Send this to all clients
-                                for client in clients.values() {
-                                    let message = ServerMessage::Message(message.clone());
-                                    send(&client.sender, message).await;
-                                }
-
-                                history.push(message);
-                                continue;
-                            }
-                            None => ServerMessage::Closed,
-                        },
-                        ClientMessage::File { chan, ext, bytes } => match client.logged {
-                            Some(id) => {
-                                let saved = save_file(ext, bytes);
-                                println!("saved file {}", saved);
-
-                                let message = Message {
-                                    from: id,
-                                    chan,
-                                    content: MessageType::File(saved),
-                                };
-
-                                // Send this to all clients
-                                for client in clients.values() {
-                                    let message = ServerMessage::Message(message.clone());
-                                    send(&client.sender, message).await;
-                                }
-
-                                history.push(message);
-                                continue;
-                            }
-                            None => ServerMessage::Closed,
-                        },
-                    },
-                    Err(err) => {
-                        println!("{}: decode error {:?}", event.from, err);
-                        ServerMessage::Closed
-                    }
-                };
+            // Commands of a bot are gone with its last connection
+            let commands = self.chat.commands();
+            if self.chat.go_offline(id, &self.client) && commands.unregister(id) {
+                let list = api::ServerMessage::Commands(commands.list());
+                self.chat.broadcast(&list);
+            }
 
-                if let ServerMessage::Closed = message {
-                    client.close.take().map(|close| close.send(()));
-                }
+            METRICS.log_out();
+        }
 
-                let send_initial_data = matches!(message, ServerMessage::LoggedIn(Ok(_)));
-                let sender = &client.sender;
-                send(sender, message).await;
-
-                if send_initial_data {
-                    for user in users.iter() {
-                        let message = ServerMessage::User(User {
-                            id: user.id,
-                            name: user.name,
-                            avatar: user.avatar,
-                        });
-                        send(sender, message).await;
-                    }
+        METRICS.disconnect();
+    }
+}
 
-                    for chan in channels.iter() {
-                        let message = ServerMessage::Channel(Channel {
-                            id: chan.id,
-                            name: chan.name,
-                            icon: chan.icon,
-                            history: history
-                                .iter()
-                                .filter(|message| message.chan == chan.id)
-                                .cloned()
-                                .collect(),
-                        });
-                        send(sender, message).await;
-                    }
-                }
-            }
+/// Returns the reply to a failed post, if the client needs one.
+fn post_error(request: u64, chan: u32, err: PostError) -> Option<api::ServerMessage> {
+    match err {
+        PostError::UnknownChannel => {
+            debug!(chan, "unknown channel");
+            None
+        }
+        PostError::TooLarge { max_size } => Some(too_large(request, max_size)),
+        PostError::RateLimited { retry_after } => Some(rate_limited(request, retry_after)),
+        err @ PostError::Muted { .. } => {
+            let text = err.to_string();
+            Some(api::ServerMessage::Notice { chan, text })
         }
     }
 }
 
-async fn send(sender: &Sender<Vec<u8>>, message: api::ServerMessage) {
-    let mut buf = Vec::with_capacity(64);
-    encode(&message, &mut buf).expect("encode");
-    let _ = sender.send(buf).await;
+fn rate_limited(request: u64, wait: Duration) -> api::ServerMessage {
+    let retry_after_ms = wait.as_millis().try_into().unwrap_or(u32::MAX);
+    api::ServerMessage::RateLimited {
+        request,
+        retry_after_ms,
+    }
 }
 
-fn save_file(ext: &str, bytes: &[u8]) -> String {
-    use std::{fs::File, io::Write, path::PathBuf};
+fn too_large(request: u64, max_size: usize) -> api::ServerMessage {
+    let max_size = max_size.try_into().unwrap_or(u32::MAX);
+    api::ServerMessage::TooLarge { request, max_size }
+}
+
+/// Tells the user why the uploaded file isn't accepted.
+fn rejected(err: &ImageError) -> String {
+    match err {
+        ImageError::Unsupported(_) => "only JPEG and PNG images can be uploaded".into(),
+        _ => "the file isn't a valid image".into(),
+    }
+}
+
+/// Saves an image with its thumbnail and returns their urls.
+async fn save_file(
+    store: &dyn BlobStore,
+    ext: &str,
+    bytes: &[u8],
+) -> Result<(String, String), Error> {
+    // Decoding and resizing takes a while, so do it aside of the async tasks
+    let image = {
+        let ext = ext.to_owned();
+        let bytes = bytes.to_vec();
+        tokio::task::spawn_blocking(move || thumb::make(&ext, &bytes)).await??
+    };
 
-    let mut rng = rand::thread_rng();
     let name = {
+        let mut rng = rand::thread_rng();
         let mut name: String = (0..20)
             .map(|_| match rng.gen_range(0..=2) {
                 0 => rng.gen_range('a'..='z'),
@@ -279,15 +356,13 @@ fn save_file(ext: &str, bytes: &[u8]) -> String {
             .collect();
 
         name.push('.');
-        name.push_str(ext);
+        name.push_str(image.ext());
         name
     };
 
-    let mut path = PathBuf::from("./static/images");
-    path.push(&name);
-    let mut file = File::create(&path).expect("create file");
-    file.write_all(bytes).expect("write");
-    file.flush().expect("flush");
+    let thumb = format!("thumbs/{name}");
+    store.put(&name, image.orig).await.map_err(Error::Store)?;
+    store.put(&thumb, image.thumb).await.map_err(Error::Store)?;
 
-    name
+    Ok((store.url(&thumb), store.url(&name)))
 }
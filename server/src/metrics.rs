@@ -0,0 +1,233 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time,
+};
+use tracing::{debug, warn};
+
+/// Counters of the running server.
+pub static METRICS: Metrics = Metrics::new();
+
+/// The content type of the rendered metrics.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Upper bounds of the fan-out latency buckets in seconds.
+const FAN_OUT_BUCKETS: [f64; 8] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5];
+
+pub struct Metrics {
+    connections: AtomicU64,
+    logged_users: AtomicU64,
+    messages: Mutex<BTreeMap<u32, u64>>,
+    upload_bytes: AtomicU64,
+    decode_errors: AtomicU64,
+    fan_out: Histogram,
+    dropped_clients: AtomicU64,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            connections: AtomicU64::new(0),
+            logged_users: AtomicU64::new(0),
+            messages: Mutex::new(BTreeMap::new()),
+            upload_bytes: AtomicU64::new(0),
+            decode_errors: AtomicU64::new(0),
+            fan_out: Histogram::new(),
+            dropped_clients: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn connect(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn disconnect(&self) {
+        self.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn log_in(&self) {
+        self.logged_users.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn log_out(&self) {
+        self.logged_users.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Counts a message posted to the channel and how long it took to send it to members.
+    pub(crate) fn post(&self, chan: u32, fan_out: Duration) {
+        let mut messages = self.messages.lock().unwrap_or_else(PoisonError::into_inner);
+        *messages.entry(chan).or_default() += 1;
+        drop(messages);
+        self.fan_out.observe(fan_out);
+    }
+
+    pub(crate) fn upload(&self, bytes: usize) {
+        self.upload_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn decode_error(&self) {
+        self.decode_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a client disconnected for being too slow and returns the total.
+    pub fn drop_client(&self) -> u64 {
+        self.dropped_clients.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn dropped_clients(&self) -> u64 {
+        self.dropped_clients.load(Ordering::Relaxed)
+    }
+
+    /// Renders the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let mut out = String::with_capacity(2048);
+
+        header(
+            &mut out,
+            "voki_connections",
+            "gauge",
+            "Open websocket connections.",
+        );
+        let _ = writeln!(out, "voki_connections {}", load(&self.connections));
+
+        let help = "Connections with a logged in user.";
+        header(&mut out, "voki_logged_users", "gauge", help);
+        let _ = writeln!(out, "voki_logged_users {}", load(&self.logged_users));
+
+        let help = "Messages posted to a channel.";
+        header(&mut out, "voki_messages_total", "counter", help);
+        let messages = self.messages.lock().unwrap_or_else(PoisonError::into_inner);
+        for (chan, count) in messages.iter() {
+            let _ = writeln!(out, "voki_messages_total{{chan=\"{chan}\"}} {count}");
+        }
+
+        drop(messages);
+
+        let help = "Bytes of uploaded files.";
+        header(&mut out, "voki_upload_bytes_total", "counter", help);
+        let _ = writeln!(out, "voki_upload_bytes_total {}", load(&self.upload_bytes));
+
+        let help = "Requests which couldn't be decoded.";
+        header(&mut out, "voki_decode_errors_total", "counter", help);
+        let _ = writeln!(
+            out,
+            "voki_decode_errors_total {}",
+            load(&self.decode_errors)
+        );
+
+        let help = "Clients disconnected for being too slow.";
+        header(&mut out, "voki_dropped_clients_total", "counter", help);
+        let dropped = load(&self.dropped_clients);
+        let _ = writeln!(out, "voki_dropped_clients_total {dropped}");
+
+        let help = "Time to send a posted message to all members of the channel.";
+        header(&mut out, "voki_fan_out_seconds", "histogram", help);
+        self.fan_out.render("voki_fan_out_seconds", &mut out);
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+struct Histogram {
+    /// Observations per bucket, the last one is for the ones over all bounds.
+    buckets: [AtomicU64; FAN_OUT_BUCKETS.len() + 1],
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; FAN_OUT_BUCKETS.len() + 1],
+            sum_nanos: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        let bucket = FAN_OUT_BUCKETS
+            .iter()
+            .position(|&bound| secs <= bound)
+            .unwrap_or(FAN_OUT_BUCKETS.len());
+
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        let nanos = duration.as_nanos().try_into().unwrap_or(u64::MAX);
+        self.sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    fn render(&self, name: &str, out: &mut String) {
+        // Buckets are cumulative in the format
+        let mut count = 0;
+        for (bucket, bound) in self.buckets.iter().zip(FAN_OUT_BUCKETS) {
+            count += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {count}");
+        }
+
+        count += self.buckets[FAN_OUT_BUCKETS.len()].load(Ordering::Relaxed);
+        let sum = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let _ = writeln!(out, "{name}_sum {sum}");
+        let _ = writeln!(out, "{name}_count {count}");
+    }
+}
+
+/// Serves the metrics over plain http at `/metrics`.
+pub(crate) async fn listen(listener: &TcpListener) -> ! {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(respond(stream));
+            }
+            Err(err) => warn!(error = %err, "couldn't get metrics client"),
+        }
+    }
+}
+
+async fn respond(mut stream: TcpStream) {
+    const HEAD_SIZE: usize = 2048;
+
+    // Only the request line matters, there's no body to read
+    let mut head = [0; HEAD_SIZE];
+    let mut len = 0;
+    let read = async {
+        while len < HEAD_SIZE && !head[..len].windows(4).any(|end| end == b"\r\n\r\n") {
+            match stream.read(&mut head[len..]).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => len += n,
+            }
+        }
+    };
+
+    if time::timeout(Duration::from_secs(5), read).await.is_err() {
+        return;
+    }
+
+    let head = String::from_utf8_lossy(&head[..len]);
+    let mut request = head.split_whitespace();
+    let (status, body) = match (request.next(), request.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", METRICS.render()),
+        _ => ("404 Not Found", String::new()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {CONTENT_TYPE}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len(),
+    );
+
+    if let Err(err) = stream.write_all(response.as_bytes()).await {
+        debug!(error = %err, "couldn't send metrics");
+    }
+}
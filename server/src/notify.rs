@@ -0,0 +1,413 @@
+use crate::{
+    channel::Post,
+    config::{Delivery as DeliveryConfig, EventKind, Subscription},
+    data,
+};
+use base::api::MessageType;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{watch, Notify},
+    task::{self, JoinHandle},
+    time,
+};
+use tracing::{debug, error, warn};
+
+/// The file undelivered events are kept in, inside the data directory.
+const FILE: &str = "deliveries.json";
+
+/// How often the queues are saved if they've changed.
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An event waiting to be sent to an endpoint.
+#[derive(Clone, Deserialize, Serialize)]
+struct Delivery {
+    id: String,
+    event: EventKind,
+    /// The JSON payload, it's signed as is.
+    body: String,
+    /// Failed attempts to send it.
+    attempts: u32,
+    /// Unix time in milliseconds it's sent at.
+    next_at: u64,
+}
+
+/// A webhook endpoint of a channel with its queue.
+struct Endpoint {
+    chan: u32,
+    sub: Subscription,
+    queue: Mutex<VecDeque<Delivery>>,
+    wake: Notify,
+}
+
+impl Endpoint {
+    /// Identifies the queue of the endpoint in the saved file.
+    fn key(&self) -> String {
+        format!("{} {}", self.chan, self.sub.url)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<Delivery>> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Sends signed events of channels to their webhook endpoints.
+///
+/// Every endpoint has its own queue, so a slow one doesn't hold the others back,
+/// and events are sent to it in order. A failed event is retried with a backoff,
+/// and queues are saved in the data directory to survive restarts.
+pub struct Notifier {
+    endpoints: Vec<Arc<Endpoint>>,
+    config: DeliveryConfig,
+    file: Option<PathBuf>,
+    dirty: AtomicBool,
+    stop: watch::Sender<bool>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Notifier {
+    /// Loads saved queues and starts sending to the endpoints.
+    pub fn spawn(
+        subs: impl IntoIterator<Item = (u32, Subscription)>,
+        config: DeliveryConfig,
+        data: Option<&Path>,
+    ) -> io::Result<Arc<Self>> {
+        let file = data.map(|dir| dir.join(FILE));
+        let mut saved: BTreeMap<String, VecDeque<Delivery>> = data::load(file.as_deref())?;
+
+        let endpoints: Vec<_> = subs
+            .into_iter()
+            .map(|(chan, sub)| {
+                let mut endpoint = Endpoint {
+                    chan,
+                    sub,
+                    queue: Mutex::default(),
+                    wake: Notify::new(),
+                };
+
+                if let Some(queue) = saved.remove(&endpoint.key()) {
+                    endpoint.queue = Mutex::new(queue);
+                }
+
+                Arc::new(endpoint)
+            })
+            .collect();
+
+        for (key, queue) in saved {
+            warn!(
+                endpoint = key,
+                events = queue.len(),
+                "dropped events of a removed webhook"
+            );
+        }
+
+        let (stop, stopped) = watch::channel(false);
+        let notifier = Arc::new(Self {
+            endpoints,
+            config,
+            file,
+            dirty: AtomicBool::new(false),
+            stop,
+            tasks: Mutex::default(),
+        });
+
+        if notifier.endpoints.is_empty() {
+            return Ok(notifier);
+        }
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(notifier.config.timeout))
+            .build()
+            .map_err(io::Error::other)?;
+
+        let mut tasks: Vec<_> = notifier
+            .endpoints
+            .iter()
+            .map(|endpoint| {
+                let send = Arc::clone(&notifier).send(
+                    Arc::clone(endpoint),
+                    client.clone(),
+                    stopped.clone(),
+                );
+
+                tokio::spawn(send)
+            })
+            .collect();
+
+        if notifier.file.is_some() {
+            tasks.push(tokio::spawn(Arc::clone(&notifier).save_changes(stopped)));
+        }
+
+        *notifier.tasks() = tasks;
+        Ok(notifier)
+    }
+
+    /// Sends a posted message as a `message` or an `upload` event.
+    pub fn post(&self, post: &Post) {
+        let message = &post.message;
+        let kind = match &message.content {
+            MessageType::Text(_) | MessageType::Action(_) => EventKind::Message,
+            MessageType::File { .. } => EventKind::Upload,
+        };
+
+        self.push(message.chan, kind, || {
+            let author = message.author.as_ref().map(|author| {
+                json!({
+                    "name": author.name,
+                    "avatar": author.avatar,
+                })
+            });
+
+            let mut body = json!({
+                "event": kind.as_str(),
+                "chan": message.chan,
+                "id": post.id,
+                "from": message.from,
+                "author": author,
+                "time": now() / 1000,
+            });
+
+            match &message.content {
+                MessageType::Text(text) => body["text"] = json!(text),
+                MessageType::Action(text) => {
+                    body["text"] = json!(text);
+                    body["action"] = json!(true);
+                }
+                MessageType::File { thumb, orig } => {
+                    body["thumb"] = json!(thumb);
+                    body["orig"] = json!(orig);
+                }
+            }
+
+            body
+        });
+    }
+
+    /// Sends a `join` event when the user comes online in the channel.
+    pub fn join(&self, chan: u32, user: u32, name: &str) {
+        self.push(chan, EventKind::Join, || {
+            json!({
+                "event": EventKind::Join.as_str(),
+                "chan": chan,
+                "user": user,
+                "name": name,
+                "time": now() / 1000,
+            })
+        });
+    }
+
+    /// Stops sending and saves the queues, events left there are sent after a restart.
+    pub async fn shutdown(&self) {
+        self.stop.send_replace(true);
+        let tasks = std::mem::take(&mut *self.tasks());
+        for task in tasks {
+            let _ = task.await;
+        }
+
+        self.save().await;
+    }
+
+    fn push<F>(&self, chan: u32, kind: EventKind, body: F)
+    where
+        F: FnOnce() -> serde_json::Value,
+    {
+        let mut endpoints = self
+            .endpoints
+            .iter()
+            .filter(|endpoint| endpoint.chan == chan && endpoint.sub.wants(kind))
+            .peekable();
+
+        // Most channels have no webhooks, so the body is made only if it's needed
+        if endpoints.peek().is_none() {
+            return;
+        }
+
+        let body = body().to_string();
+        for endpoint in endpoints {
+            let delivery = Delivery {
+                id: hex::encode(rand::thread_rng().gen::<[u8; 16]>()),
+                event: kind,
+                body: body.clone(),
+                attempts: 0,
+                next_at: 0,
+            };
+
+            let mut queue = endpoint.lock();
+            if queue.len() >= self.config.queue {
+                queue.pop_front();
+                warn!(chan, url = %endpoint.sub.url, "webhook queue is full, dropped the oldest event");
+            }
+
+            queue.push_back(delivery);
+            drop(queue);
+            endpoint.wake.notify_one();
+        }
+
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Sends events of the endpoint one by one until it's stopped.
+    async fn send(
+        self: Arc<Self>,
+        endpoint: Arc<Endpoint>,
+        client: reqwest::Client,
+        mut stopped: watch::Receiver<bool>,
+    ) {
+        loop {
+            let next = endpoint.lock().front().cloned();
+            let Some(delivery) = next else {
+                tokio::select! {
+                    () = endpoint.wake.notified() => continue,
+                    () = stop(&mut stopped) => return,
+                }
+            };
+
+            let wait = delivery.next_at.saturating_sub(now());
+            if wait > 0 {
+                tokio::select! {
+                    () = time::sleep(Duration::from_millis(wait)) => {}
+                    () = stop(&mut stopped) => return,
+                }
+            }
+
+            // An unfinished request is sent again after a restart
+            let sent = tokio::select! {
+                sent = deliver(&client, &endpoint.sub, &delivery) => sent,
+                () = stop(&mut stopped) => return,
+            };
+
+            let mut queue = endpoint.lock();
+
+            // The event could be dropped from a full queue meanwhile
+            let front = match queue.front_mut() {
+                Some(front) if front.id == delivery.id => front,
+                _ => continue,
+            };
+
+            let url = &endpoint.sub.url;
+            match sent {
+                Ok(()) => {
+                    debug!(%url, event = delivery.event.as_str(), "webhook delivered");
+                    queue.pop_front();
+                }
+                Err(err) => {
+                    front.attempts += 1;
+                    let attempts = front.attempts;
+                    if attempts >= self.config.attempts {
+                        warn!(%url, attempts, error = %err, "webhook failed, dropped the event");
+                        queue.pop_front();
+                    } else {
+                        let backoff = self.backoff(attempts);
+                        front.next_at = now() + backoff.as_millis() as u64;
+                        warn!(%url, attempts, retry_after = ?backoff, error = %err, "webhook failed");
+                    }
+                }
+            }
+
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// The delay after the failed attempts, it doubles every time up to the max.
+    fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempts.saturating_sub(1));
+        let secs = self.config.backoff.saturating_mul(factor);
+        Duration::from_secs(secs.min(self.config.max_backoff))
+    }
+
+    async fn save_changes(self: Arc<Self>, mut stopped: watch::Receiver<bool>) {
+        let mut interval = time::interval(SAVE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                () = stop(&mut stopped) => return,
+            }
+
+            if self.dirty.load(Ordering::Relaxed) {
+                self.save().await;
+            }
+        }
+    }
+
+    async fn save(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+
+        self.dirty.store(false, Ordering::Relaxed);
+        let queues: BTreeMap<_, _> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| (endpoint.key(), endpoint.lock().clone()))
+            .filter(|(_, queue)| !queue.is_empty())
+            .collect();
+
+        let file = file.clone();
+        let save = task::spawn_blocking(move || data::save(Some(&file), &queues));
+        if let Err(err) = save.await.unwrap_or_else(|err| Err(io::Error::other(err))) {
+            self.dirty.store(true, Ordering::Relaxed);
+            error!(error = %err, "couldn't save webhook queues");
+        }
+    }
+
+    fn tasks(&self) -> MutexGuard<'_, Vec<JoinHandle<()>>> {
+        self.tasks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Sends the event with a signature of its time and body.
+async fn deliver(
+    client: &reqwest::Client,
+    sub: &Subscription,
+    delivery: &Delivery,
+) -> Result<(), reqwest::Error> {
+    let timestamp = (now() / 1000).to_string();
+    let signature = sign(&sub.secret, &timestamp, &delivery.body);
+    client
+        .post(sub.url.clone())
+        .header("Content-Type", "application/json")
+        .header("X-Voki-Event", delivery.event.as_str())
+        .header("X-Voki-Delivery", &delivery.id)
+        .header("X-Voki-Timestamp", &timestamp)
+        .header("X-Voki-Signature", format!("sha256={signature}"))
+        .body(delivery.body.clone())
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+/// Returns the hex HMAC-SHA256 of `{timestamp}.{body}` with the secret.
+fn sign(secret: &str, timestamp: &str, body: &str) -> String {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("any key size is valid");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Waits until the notifier is asked to stop.
+async fn stop(stopped: &mut watch::Receiver<bool>) {
+    let _ = stopped.wait_for(|&stop| stop).await;
+}
+
+/// Unix time in milliseconds.
+fn now() -> u64 {
+    let since = SystemTime::now().duration_since(UNIX_EPOCH);
+    since.map_or(0, |since| since.as_millis() as u64)
+}
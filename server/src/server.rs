@@ -0,0 +1,318 @@
+use crate::{
+    chat::Chat, config::Config, error::Error, listen, metrics, service::Service,
+    shutdown::Shutdown, store::BlobStore, tls::Certs,
+};
+use std::{future, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::oneshot,
+    task::JoinHandle,
+    time,
+};
+use tracing::{info, warn, Instrument};
+use websocket::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role},
+    WebSocketStream,
+};
+
+/// Configures and starts a server.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), server::Error> {
+/// let server = server::Server::builder()
+///     .address("127.0.0.1:0")
+///     .bind()
+///     .await?;
+///
+/// println!("listening at {:?}", server.local_addr());
+/// server.shutdown().await;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ServerBuilder {
+    config: Config,
+    store: Option<Arc<dyn BlobStore>>,
+}
+
+impl ServerBuilder {
+    pub fn new() -> Self {
+        Self::from_config(Config::default())
+    }
+
+    pub fn from_config(config: Config) -> Self {
+        Self {
+            config,
+            store: None,
+        }
+    }
+
+    /// Sets the local address, use port 0 to bind an ephemeral one.
+    pub fn address<S>(mut self, address: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.address = address.into();
+        self
+    }
+
+    /// Changes the config in place.
+    pub fn configure<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut Config),
+    {
+        f(&mut self.config);
+        self
+    }
+
+    /// Saves uploaded files in the store instead of the configured one.
+    pub fn store(mut self, store: Arc<dyn BlobStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Validates the config, binds the address and starts serving clients.
+    /// Metrics are served at their own address if it's set.
+    pub async fn bind(self) -> Result<Server, Error> {
+        self.config.validate()?;
+
+        // Load certificates before binding to fail early if they're wrong
+        let certs = match &self.config.tls {
+            Some(tls) => Some(Certs::load(tls).map_err(Error::Tls)?),
+            None => None,
+        };
+
+        let listener = TcpListener::bind(&self.config.address)
+            .await
+            .map_err(Error::Bind)?;
+
+        let metrics = match &self.config.metrics_address {
+            Some(address) => Some(TcpListener::bind(address).await.map_err(Error::Bind)?),
+            None => None,
+        };
+
+        let listener = Listener {
+            listener,
+            certs,
+            metrics,
+        };
+
+        self.spawn(Some(listener))
+    }
+
+    /// Validates the config and starts the server without a listener.
+    /// Connections accepted elsewhere are passed to it with an [`Acceptor`],
+    /// and metrics are rendered with [`METRICS`](crate::metrics::METRICS).
+    pub fn start(self) -> Result<Server, Error> {
+        self.config.validate()?;
+        self.spawn(None)
+    }
+
+    fn spawn(self, listener: Option<Listener>) -> Result<Server, Error> {
+        let (addr, metrics_addr) = match &listener {
+            Some(listener) => {
+                let addr = listener.listener.local_addr().map_err(Error::Bind)?;
+                let metrics_addr = match &listener.metrics {
+                    Some(metrics) => Some(metrics.local_addr().map_err(Error::Bind)?),
+                    None => None,
+                };
+
+                (Some(addr), metrics_addr)
+            }
+            None => (None, None),
+        };
+
+        let Self { config, store } = self;
+        let store = store.unwrap_or_else(|| config.storage.store());
+        let reconnect_after = Duration::from_secs(config.shutdown.reconnect_after);
+        let acceptor = Acceptor {
+            chat: Arc::new(Chat::new(&config, store).map_err(Error::Data)?),
+            shutdown: Arc::new(Shutdown::new(reconnect_after)),
+        };
+
+        let (stop, stopped) = oneshot::channel();
+        let task = tokio::spawn(serve(listener, acceptor.clone(), config, stopped));
+
+        Ok(Server {
+            addr,
+            metrics_addr,
+            acceptor,
+            stop: Some(stop),
+            task,
+        })
+    }
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A handle to the running server.
+///
+/// Dropping the handle shuts the server down in the background.
+pub struct Server {
+    addr: Option<SocketAddr>,
+    metrics_addr: Option<SocketAddr>,
+    acceptor: Acceptor,
+    stop: Option<oneshot::Sender<()>>,
+    task: JoinHandle<()>,
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+
+    /// Starts the server with the config.
+    pub async fn bind(config: Config) -> Result<Self, Error> {
+        ServerBuilder::from_config(config).bind().await
+    }
+
+    /// The address the server is bound to or `None` if it's started without a listener.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.addr
+    }
+
+    /// The address metrics are served at or `None` if it's not set.
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addr
+    }
+
+    /// Returns a handle to read and change the chat state.
+    pub fn service(&self) -> Service {
+        Service::new(Arc::clone(&self.acceptor.chat))
+    }
+
+    /// Returns an acceptor to pass connections to the server.
+    pub fn acceptor(&self) -> Acceptor {
+        self.acceptor.clone()
+    }
+
+    /// Shuts the server down gracefully and waits until it's done.
+    pub async fn shutdown(mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
+
+/// Passes websocket connections accepted by another server, like an http one, to the chat.
+#[derive(Clone)]
+pub struct Acceptor {
+    chat: Arc<Chat>,
+    shutdown: Arc<Shutdown>,
+}
+
+impl Acceptor {
+    /// Returns the `Sec-WebSocket-Accept` header value
+    /// for the `Sec-WebSocket-Key` one of a handshake request.
+    pub fn accept_key(key: &[u8]) -> String {
+        derive_accept_key(key)
+    }
+
+    /// Returns `true` if new connections are served,
+    /// so `false` means the server is shutting down.
+    pub fn is_serving(&self) -> bool {
+        self.shutdown.is_running()
+    }
+
+    /// Serves the connection which has finished the websocket handshake.
+    /// Errors of the connection are logged, an error is returned only if it isn't served.
+    pub async fn serve<S>(self, stream: S, addr: SocketAddr) -> Result<(), Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let signal = self.shutdown.signal().ok_or(Error::ShuttingDown)?;
+        let serve = async move {
+            let config = listen::websocket_config(self.chat.limits());
+            let stream = WebSocketStream::from_raw_socket(stream, Role::Server, Some(config)).await;
+
+            listen::closed(listen::serve(stream, addr, self.chat, signal).await);
+        };
+
+        serve.instrument(listen::span(addr)).await;
+        Ok(())
+    }
+}
+
+struct Listener {
+    listener: TcpListener,
+    certs: Option<Certs>,
+    metrics: Option<TcpListener>,
+}
+
+async fn serve(
+    listener: Option<Listener>,
+    acceptor: Acceptor,
+    config: Config,
+    stopped: oneshot::Receiver<()>,
+) {
+    let Acceptor { chat, shutdown } = acceptor;
+    let accept = async {
+        match &listener {
+            Some(Listener {
+                listener,
+                certs,
+                metrics,
+            }) => {
+                let tls = certs.as_ref().map(|certs| certs.acceptor().clone());
+                let watch = async {
+                    match certs {
+                        Some(certs) => certs.watch().await,
+                        None => future::pending().await,
+                    }
+                };
+
+                let metrics = async {
+                    match metrics {
+                        Some(metrics) => metrics::listen(metrics).await,
+                        None => future::pending().await,
+                    }
+                };
+
+                tokio::select! {
+                    _ = listen::listen(listener, Arc::clone(&chat), tls, &shutdown) => {}
+                    _ = watch => {}
+                    _ = metrics => {}
+                }
+            }
+            None => future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        () = accept => {}
+        _ = stopped => {}
+    }
+
+    // The listener is dropped, so no new connections are accepted
+    drop(listener);
+    info!("shutting down");
+    let graceful = async {
+        shutdown.stop().await;
+        chat.flush().await;
+        shutdown.close().await;
+    };
+
+    if time::timeout(Duration::from_secs(config.shutdown.timeout), graceful)
+        .await
+        .is_err()
+    {
+        warn!("shutdown timed out");
+    }
+
+    // Events which aren't sent yet are saved to be sent after a restart
+    chat.notifier().shutdown().await;
+}
@@ -0,0 +1,158 @@
+use crate::chat::Chat;
+use base::api::{LoginError, User};
+use std::{net::IpAddr, sync::Arc};
+
+pub use crate::{
+    bot::{Bot, BotError},
+    channel::{Channel, Post},
+    chat::PostError,
+    hook::{Hook, HookError},
+};
+
+/// Reads and changes the chat state from outside of websocket connections,
+/// so other front ends like an http API share it with the chat clients.
+#[derive(Clone)]
+pub struct Service {
+    chat: Arc<Chat>,
+}
+
+impl Service {
+    pub(crate) fn new(chat: Arc<Chat>) -> Self {
+        Self { chat }
+    }
+
+    /// Returns channels ordered by id.
+    pub fn channels(&self) -> Vec<Channel> {
+        self.chat
+            .channels()
+            .map(|chan| chan.info().clone())
+            .collect()
+    }
+
+    /// Returns up to `limit` messages of the channel posted before the `before` one,
+    /// or the last ones if it's not set, oldest first. `None` means an unknown channel.
+    pub async fn history(&self, chan: u32, before: Option<u64>, limit: usize) -> Option<Vec<Post>> {
+        let chan = self.chat.channel(chan)?;
+        Some(chan.history(before, limit).await)
+    }
+
+    /// Returns users ordered by id.
+    pub fn users(&self) -> Vec<User> {
+        let mut users: Vec<_> = self
+            .chat
+            .users()
+            .iter()
+            .map(|user| User {
+                id: user.id,
+                name: user.name,
+                avatar: user.avatar,
+                bot: user.bot,
+            })
+            .collect();
+
+        users.sort_unstable_by_key(|user| user.id);
+        users
+    }
+
+    /// Returns the id of the user with the credentials.
+    /// Failed attempts count towards the lockout like the websocket logins do.
+    pub fn authenticate(&self, name: &str, pass: &str, ip: IpAddr) -> Result<u32, LoginError> {
+        self.chat.verify(name, pass, ip)
+    }
+
+    /// Returns the user id of the bot with the token.
+    /// Only unknown tokens are limited, like wrong passwords are.
+    pub fn verify_bot(&self, token: &str, ip: IpAddr) -> Result<u32, LoginError> {
+        self.chat.verify_bot(token, ip)
+    }
+
+    /// Posts the text of the user to the channel, limited like the websocket messages are.
+    pub async fn say(&self, from: u32, chan: u32, text: &str, ip: IpAddr) -> Result<(), PostError> {
+        self.chat.say(from, chan, text, ip).await
+    }
+
+    /// Creates a webhook for the channel and returns it with its token.
+    /// Only admins can do it.
+    pub fn create_hook(
+        &self,
+        user: u32,
+        chan: u32,
+        name: &str,
+        avatar: Option<&str>,
+    ) -> Result<(Hook, String), HookError> {
+        self.check_admin(user, HookError::NotAdmin)?;
+        self.chat.channel(chan).ok_or(HookError::UnknownChannel)?;
+        let hooks = self.chat.hooks();
+        hooks
+            .create(chan, name, avatar, user)
+            .map_err(HookError::Save)
+    }
+
+    /// Returns webhooks of the channel to an admin.
+    pub fn hooks(&self, user: u32, chan: u32) -> Result<Vec<Hook>, HookError> {
+        self.check_admin(user, HookError::NotAdmin)?;
+        self.chat.channel(chan).ok_or(HookError::UnknownChannel)?;
+        Ok(self.chat.hooks().list(chan))
+    }
+
+    /// Revokes the webhook, only admins can do it.
+    pub fn revoke_hook(&self, user: u32, id: u32) -> Result<(), HookError> {
+        self.check_admin(user, HookError::NotAdmin)?;
+        match self.chat.hooks().revoke(id) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(HookError::UnknownHook),
+            Err(err) => Err(HookError::Save(err)),
+        }
+    }
+
+    /// Posts the text with the webhook of the token, limited by the webhook rate.
+    /// The name and the avatar override the webhook ones for this message.
+    pub async fn post_hook(
+        &self,
+        token: &str,
+        text: &str,
+        name: Option<&str>,
+        avatar: Option<&str>,
+        ip: IpAddr,
+    ) -> Result<(), HookError> {
+        self.chat.post_hook(token, text, name, avatar, ip).await
+    }
+
+    /// Creates a bot account and returns it with its token.
+    /// Only admins can do it.
+    pub fn create_bot(
+        &self,
+        user: u32,
+        name: &str,
+        avatar: Option<&str>,
+    ) -> Result<(Bot, String), BotError> {
+        self.check_admin(user, BotError::NotAdmin)?;
+        self.chat
+            .create_bot(name, avatar, user)
+            .map_err(BotError::Save)
+    }
+
+    /// Returns bot accounts to an admin.
+    pub fn bots(&self, user: u32) -> Result<Vec<Bot>, BotError> {
+        self.check_admin(user, BotError::NotAdmin)?;
+        Ok(self.chat.bots().list())
+    }
+
+    /// Revokes the token of the bot and disconnects it, only admins can do it.
+    pub fn revoke_bot(&self, user: u32, id: u32) -> Result<(), BotError> {
+        self.check_admin(user, BotError::NotAdmin)?;
+        match self.chat.revoke_bot(id) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(BotError::UnknownBot),
+            Err(err) => Err(BotError::Save(err)),
+        }
+    }
+
+    fn check_admin<E>(&self, user: u32, err: E) -> Result<(), E> {
+        if self.chat.users().is_admin(user) {
+            Ok(())
+        } else {
+            Err(err)
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+use tokio::sync::{mpsc, watch};
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Running,
+    Stopping,
+    Closing,
+}
+
+/// Counts tasks and waits until all of them are done.
+struct Tracker {
+    sender: Mutex<Option<mpsc::Sender<()>>>,
+    receiver: Mutex<Option<mpsc::Receiver<()>>>,
+}
+
+impl Tracker {
+    fn new() -> Self {
+        let (sender, receiver) = mpsc::channel(1);
+        Self {
+            sender: Mutex::new(Some(sender)),
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+
+    /// Returns a token to hold while the task runs or `None` if it's too late to start.
+    fn token(&self) -> Option<mpsc::Sender<()>> {
+        lock(&self.sender).clone()
+    }
+
+    async fn wait(&self) {
+        lock(&self.sender).take();
+        let receiver = lock(&self.receiver).take();
+        if let Some(mut receiver) = receiver {
+            let _ = receiver.recv().await;
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Coordinates connections during a graceful shutdown.
+pub struct Shutdown {
+    state: watch::Sender<State>,
+    busy: Tracker,
+    open: Tracker,
+    reconnect_after: Duration,
+}
+
+impl Shutdown {
+    pub fn new(reconnect_after: Duration) -> Self {
+        let (state, _) = watch::channel(State::Running);
+        Self {
+            state,
+            busy: Tracker::new(),
+            open: Tracker::new(),
+            reconnect_after,
+        }
+    }
+
+    /// Returns `true` until the shutdown starts.
+    pub fn is_running(&self) -> bool {
+        *self.state.borrow() == State::Running
+    }
+
+    /// Returns a signal for a new connection or `None` if the server is shutting down.
+    pub fn signal(&self) -> Option<Signal> {
+        Some(Signal {
+            state: self.state.subscribe(),
+            busy: Some(self.busy.token()?),
+            _open: self.open.token()?,
+            reconnect_after: self.reconnect_after,
+        })
+    }
+
+    /// Stops connections from reading new requests
+    /// and waits until they finish the current ones.
+    pub async fn stop(&self) {
+        self.state.send_replace(State::Stopping);
+        self.busy.wait().await;
+    }
+
+    /// Lets connections say goodbye to clients and waits until they're closed.
+    pub async fn close(&self) {
+        self.state.send_replace(State::Closing);
+        self.open.wait().await;
+    }
+}
+
+/// A connection side of the shutdown.
+pub struct Signal {
+    state: watch::Receiver<State>,
+    busy: Option<mpsc::Sender<()>>,
+    _open: mpsc::Sender<()>,
+    reconnect_after: Duration,
+}
+
+impl Signal {
+    /// Waits until the server stops handling requests.
+    pub async fn stopping(&mut self) {
+        let _ = self.state.wait_for(|&state| state != State::Running).await;
+    }
+
+    /// Tells the server the connection doesn't handle requests anymore.
+    pub fn idle(&mut self) {
+        self.busy = None;
+    }
+
+    /// Waits until the connection should be closed.
+    pub async fn closing(&mut self) {
+        let _ = self.state.wait_for(|&state| state == State::Closing).await;
+    }
+
+    /// How soon clients can try to reconnect.
+    pub fn reconnect_after(&self) -> Duration {
+        self.reconnect_after
+    }
+}
+
+/// Waits for a signal to terminate the server.
+pub async fn terminated() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(err) => {
+                tracing::warn!(error = %err, "couldn't handle SIGTERM");
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
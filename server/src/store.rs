@@ -0,0 +1,334 @@
+use futures::future::BoxFuture;
+pub use reqwest::Url;
+use reqwest::{Method, RequestBuilder};
+use std::{
+    fs::Metadata,
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A storage of uploaded files.
+pub trait BlobStore: Send + Sync {
+    /// Stores the blob by the key, replacing an existing one.
+    fn put<'a>(&'a self, key: &'a str, bytes: Vec<u8>) -> BoxFuture<'a, io::Result<()>>;
+
+    /// Returns the url by which clients can fetch the blob.
+    fn url(&self, key: &str) -> String;
+
+    /// Checks the store is reachable and looks writable without changing it,
+    /// since readiness probes call it often.
+    fn check(&self) -> BoxFuture<'_, io::Result<()>>;
+}
+
+/// Stores blobs as files in a local directory.
+pub struct FsStore {
+    root: PathBuf,
+    url: String,
+}
+
+impl FsStore {
+    /// Creates the store in the `root` directory which is served at the `url`.
+    pub fn new<P, U>(root: P, url: U) -> Self
+    where
+        P: Into<PathBuf>,
+        U: Into<String>,
+    {
+        Self {
+            root: root.into(),
+            url: url.into(),
+        }
+    }
+}
+
+impl BlobStore for FsStore {
+    fn put<'a>(&'a self, key: &'a str, bytes: Vec<u8>) -> BoxFuture<'a, io::Result<()>> {
+        use tokio::fs;
+
+        Box::pin(async move {
+            let path = self.root.join(key);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+
+            fs::write(path, bytes).await
+        })
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("{}/{key}", self.url.trim_end_matches('/'))
+    }
+
+    fn check(&self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(async move {
+            // The directory is created with the first upload,
+            // until then the closest existing parent has to be writable
+            for dir in self.root.ancestors() {
+                let dir = match dir.as_os_str().is_empty() {
+                    true => Path::new("."),
+                    false => dir,
+                };
+
+                match tokio::fs::metadata(dir).await {
+                    Ok(meta) => return writable(dir, &meta),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                    Err(err) => return Err(err),
+                }
+            }
+
+            let err = format!("{} doesn't exist", self.root.display());
+            Err(io::Error::new(io::ErrorKind::NotFound, err))
+        })
+    }
+}
+
+fn writable(dir: &Path, meta: &Metadata) -> io::Result<()> {
+    if !meta.is_dir() {
+        let err = format!("{} isn't a directory", dir.display());
+        Err(io::Error::other(err))
+    } else if meta.permissions().readonly() {
+        let err = format!("{} is read-only", dir.display());
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, err))
+    } else {
+        Ok(())
+    }
+}
+
+/// Credentials of an S3-compatible storage.
+pub struct Credentials {
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+/// Stores blobs in a bucket of an S3-compatible storage.
+///
+/// The bucket is addressed in path style, so it works with self-hosted
+/// storages like MinIO as well as with AWS.
+pub struct S3Store {
+    client: reqwest::Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    credentials: Credentials,
+    url: String,
+}
+
+impl S3Store {
+    pub fn new(endpoint: Url, bucket: &str, region: &str, credentials: Credentials) -> Self {
+        let url = format!("{}/{bucket}", endpoint.as_str().trim_end_matches('/'));
+        Self {
+            client: reqwest::Client::new(),
+            endpoint,
+            bucket: bucket.into(),
+            region: region.into(),
+            credentials,
+            url,
+        }
+    }
+
+    /// Sets the public url of the bucket if it differs from the endpoint one.
+    pub fn with_url<U>(mut self, url: U) -> Self
+    where
+        U: Into<String>,
+    {
+        self.url = url.into();
+        self
+    }
+
+    async fn put_object(&self, key: &str, bytes: Vec<u8>) -> Result<(), reqwest::Error> {
+        let path = format!("/{}/{}", self.bucket, encode_path(key));
+        self.request(Method::PUT, &path, &bytes)
+            .header("content-type", content_type(key))
+            .body(bytes)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Checks the bucket exists and the credentials are accepted.
+    async fn head_bucket(&self) -> Result<(), reqwest::Error> {
+        let path = format!("/{}", self.bucket);
+        self.request(Method::HEAD, &path, b"")
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Builds a request to the path of the endpoint signed with the credentials.
+    fn request(&self, method: Method, path: &str, payload: &[u8]) -> RequestBuilder {
+        let mut url = self.endpoint.clone();
+        url.set_path(path);
+
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.to_owned(),
+            (None, _) => String::new(),
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let signed = sign(&Request {
+            method: method.as_str(),
+            path,
+            query: "",
+            host: &host,
+            payload,
+            region: &self.region,
+            credentials: &self.credentials,
+            time: now,
+        });
+
+        self.client
+            .request(method, url)
+            .header("x-amz-date", signed.date)
+            .header("x-amz-content-sha256", signed.payload_hash)
+            .header("authorization", signed.authorization)
+    }
+}
+
+impl BlobStore for S3Store {
+    fn put<'a>(&'a self, key: &'a str, bytes: Vec<u8>) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move { self.put_object(key, bytes).await.map_err(io::Error::other) })
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("{}/{}", self.url.trim_end_matches('/'), encode_path(key))
+    }
+
+    fn check(&self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(async move { self.head_bucket().await.map_err(io::Error::other) })
+    }
+}
+
+/// A request to an S3-compatible storage to sign.
+pub struct Request<'a> {
+    pub method: &'a str,
+    /// The encoded path.
+    pub path: &'a str,
+    /// The canonical query string, its parameters are encoded and sorted.
+    pub query: &'a str,
+    pub host: &'a str,
+    pub payload: &'a [u8],
+    pub region: &'a str,
+    pub credentials: &'a Credentials,
+    /// Unix time of the request.
+    pub time: u64,
+}
+
+/// Values of the headers which authenticate a request.
+pub struct Signed {
+    /// The `x-amz-date` header.
+    pub date: String,
+    /// The `x-amz-content-sha256` header.
+    pub payload_hash: String,
+    /// The `authorization` header.
+    pub authorization: String,
+}
+
+/// Signs the request with AWS Signature Version 4.
+///
+/// Only the host, the date and the payload hash headers are signed,
+/// so others can be added to the request freely.
+pub fn sign(req: &Request) -> Signed {
+    use hmac::{Hmac, Mac};
+    use sha2::{Digest, Sha256};
+
+    fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("any key size is valid");
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+    let date = format_time(req.time);
+    let day = &date[..8];
+    let payload_hash = hex::encode(Sha256::digest(req.payload));
+    let canonical = format!(
+        "{method}\n{path}\n{query}\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{date}\n\n{SIGNED_HEADERS}\n{payload_hash}",
+        method = req.method,
+        path = req.path,
+        query = req.query,
+        host = req.host,
+    );
+
+    let scope = format!("{day}/{}/s3/aws4_request", req.region);
+    let to_sign = format!(
+        "AWS4-HMAC-SHA256\n{date}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical.as_bytes())),
+    );
+
+    let key = format!("AWS4{}", req.credentials.secret_key);
+    let key = hmac(key.as_bytes(), day);
+    let key = hmac(&key, req.region);
+    let key = hmac(&key, "s3");
+    let key = hmac(&key, "aws4_request");
+    let signature = hex::encode(hmac(&key, &to_sign));
+
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={SIGNED_HEADERS}, Signature={signature}",
+        req.credentials.access_key,
+    );
+
+    Signed {
+        date,
+        payload_hash,
+        authorization,
+    }
+}
+
+/// Formats unix time as `YYYYMMDDTHHMMSSZ`.
+fn format_time(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let (hour, min, sec) = (rem / 3600, rem % 3600 / 60, rem % 60);
+
+    // Convert days since the epoch to a civil date
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}{month:02}{day:02}T{hour:02}{min:02}{sec:02}Z")
+}
+
+/// Returns the media type of the blob for browsers to show it.
+fn content_type(key: &str) -> &'static str {
+    let ext = key.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+    match ext.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Percent-encodes the key keeping the path separators.
+fn encode_path(key: &str) -> String {
+    use std::fmt::Write;
+
+    let mut encoded = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+
+    encoded
+}
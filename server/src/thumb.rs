@@ -0,0 +1,145 @@
+use image::{
+    error::ImageFormatHint, metadata::Orientation, DynamicImage, ImageDecoder, ImageError,
+    ImageFormat, ImageReader,
+};
+use std::io::Cursor;
+
+/// Maximum width and height of a thumbnail.
+pub const THUMB_SIZE: u32 = 320;
+
+pub struct Image {
+    pub format: ImageFormat,
+    pub orig: Vec<u8>,
+    pub thumb: Vec<u8>,
+}
+
+impl Image {
+    pub fn ext(&self) -> &'static str {
+        self.format.extensions_str()[0]
+    }
+}
+
+/// Formats which can be uploaded, others are rejected.
+const FORMATS: [ImageFormat; 2] = [ImageFormat::Jpeg, ImageFormat::Png];
+
+/// Checks an uploaded image and makes a thumbnail of it.
+///
+/// Metadata like EXIF, which can have the location of a photo, is stripped
+/// from the original without re-encoding it. Only an original which has to be
+/// rotated according to its EXIF orientation is encoded again, like the thumbnail.
+pub fn make(ext: &str, bytes: &[u8]) -> Result<Image, ImageError> {
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    if reader.format().is_none() {
+        if let Some(format) = ImageFormat::from_extension(ext) {
+            reader.set_format(format);
+        }
+    }
+
+    let format = match reader.format() {
+        Some(format) if FORMATS.contains(&format) => format,
+        Some(format) => return Err(unsupported(ImageFormatHint::Exact(format))),
+        None => return Err(unsupported(ImageFormatHint::Name(ext.into()))),
+    };
+
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    let orig = match orientation {
+        Orientation::NoTransforms => strip(format, bytes),
+        _ => None,
+    };
+
+    let orig = match orig {
+        Some(orig) => orig,
+        None => encode(&image, format)?,
+    };
+
+    if image.width() > THUMB_SIZE || image.height() > THUMB_SIZE {
+        image = image.thumbnail(THUMB_SIZE, THUMB_SIZE);
+    }
+
+    Ok(Image {
+        format,
+        orig,
+        thumb: encode(&image, format)?,
+    })
+}
+
+/// Removes metadata from the file, returns `None` if its structure isn't understood.
+fn strip(format: ImageFormat, bytes: &[u8]) -> Option<Vec<u8>> {
+    match format {
+        ImageFormat::Jpeg => strip_jpeg(bytes),
+        ImageFormat::Png => strip_png(bytes),
+        _ => None,
+    }
+}
+
+/// Drops APP1 segments, which hold EXIF and XMP, and APP13 ones with IPTC.
+fn strip_jpeg(bytes: &[u8]) -> Option<Vec<u8>> {
+    const APP1: u8 = 0xe1;
+    const APP13: u8 = 0xed;
+    const SOS: u8 = 0xda;
+
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(bytes.get(..2).filter(|soi| soi == &[0xff, 0xd8])?);
+    let mut pos = 2;
+    loop {
+        let marker = *bytes.get(pos + 1).filter(|_| bytes[pos] == 0xff)?;
+
+        // Markers can be padded with fill bytes
+        if marker == 0xff {
+            pos += 1;
+            continue;
+        }
+
+        let len = u16::from_be_bytes([*bytes.get(pos + 2)?, *bytes.get(pos + 3)?]) as usize;
+        let segment = bytes.get(pos..pos + 2 + len)?;
+
+        // The compressed data follows the scan header up to the end
+        if marker == SOS {
+            out.extend_from_slice(&bytes[pos..]);
+            return Some(out);
+        }
+
+        if marker != APP1 && marker != APP13 {
+            out.extend_from_slice(segment);
+        }
+
+        pos += segment.len();
+    }
+}
+
+/// Drops the EXIF chunk and text chunks, which can have comments or XMP.
+fn strip_png(bytes: &[u8]) -> Option<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    const DROPPED: [&[u8]; 4] = [b"eXIf", b"tEXt", b"zTXt", b"iTXt"];
+
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(bytes.get(..8).filter(|&signature| signature == SIGNATURE)?);
+    let mut pos = 8;
+    while pos < bytes.len() {
+        let len = u32::from_be_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?) as usize;
+
+        // Length, type, data and CRC
+        let chunk = bytes.get(pos..pos.checked_add(12 + len)?)?;
+        if !DROPPED.contains(&&chunk[4..8]) {
+            out.extend_from_slice(chunk);
+        }
+
+        pos += chunk.len();
+    }
+
+    Some(out)
+}
+
+fn unsupported(hint: ImageFormatHint) -> ImageError {
+    ImageError::Unsupported(hint.into())
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, ImageError> {
+    let mut buf = Cursor::new(Vec::with_capacity(1024));
+    image.write_to(&mut buf, format)?;
+    Ok(buf.into_inner())
+}
@@ -0,0 +1,126 @@
+use crate::config::Tls;
+use rustls::{
+    crypto::ring,
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig,
+};
+use std::{
+    fmt, fs,
+    io::{self, BufReader},
+    path::Path,
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, SystemTime},
+};
+use tokio::time;
+use tokio_rustls::TlsAcceptor;
+
+/// Terminates TLS with a certificate which is reloaded when its files change.
+pub struct Certs {
+    config: Tls,
+    resolver: Arc<Resolver>,
+    acceptor: TlsAcceptor,
+}
+
+impl Certs {
+    pub fn load(config: &Tls) -> io::Result<Self> {
+        let resolver = Arc::new(Resolver {
+            key: RwLock::new(Arc::new(load_key(config)?)),
+        });
+
+        let server = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::clone(&resolver) as _);
+
+        Ok(Self {
+            config: config.clone(),
+            resolver,
+            acceptor: TlsAcceptor::from(Arc::new(server)),
+        })
+    }
+
+    pub fn acceptor(&self) -> &TlsAcceptor {
+        &self.acceptor
+    }
+
+    /// Checks the files periodically and reloads the certificate when they change.
+    /// New connections use the new certificate, established ones keep the old.
+    pub async fn watch(&self) -> ! {
+        let interval = Duration::from_secs(self.config.reload);
+        let mut loaded = modified(&self.config);
+        loop {
+            time::sleep(interval).await;
+
+            let current = modified(&self.config);
+            if current == loaded {
+                continue;
+            }
+
+            loaded = current;
+            match load_key(&self.config) {
+                Ok(key) => {
+                    *self
+                        .resolver
+                        .key
+                        .write()
+                        .unwrap_or_else(PoisonError::into_inner) = Arc::new(key);
+
+                    tracing::info!("tls certificate reloaded");
+                }
+                Err(err) => tracing::warn!(error = %err, "failed to reload tls certificate"),
+            }
+        }
+    }
+}
+
+struct Resolver {
+    key: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, _: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let key = self.key.read().unwrap_or_else(PoisonError::into_inner);
+        Some(Arc::clone(&key))
+    }
+}
+
+impl fmt::Debug for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Resolver").finish_non_exhaustive()
+    }
+}
+
+fn load_key(config: &Tls) -> io::Result<CertifiedKey> {
+    fn open(path: &Path) -> io::Result<BufReader<fs::File>> {
+        fs::File::open(path)
+            .map(BufReader::new)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.display())))
+    }
+
+    let invalid = |path: &Path, what| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {what}", path.display()),
+        )
+    };
+
+    let certs = rustls_pemfile::certs(&mut open(&config.cert)?).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(invalid(&config.cert, "no certificates found"));
+    }
+
+    let key = rustls_pemfile::private_key(&mut open(&config.key)?)?
+        .ok_or_else(|| invalid(&config.key, "no private key found"))?;
+
+    let key = ring::sign::any_supported_type(&key)
+        .map_err(|_| invalid(&config.key, "unsupported private key"))?;
+
+    Ok(CertifiedKey::new(certs, key))
+}
+
+fn modified(config: &Tls) -> [Option<SystemTime>; 2] {
+    let modified = |path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    [modified(&config.cert), modified(&config.key)]
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use base::api::{ClientMessage, MessageType, ServerMessage};
+use common::{Client, Server};
+
+async fn admin(server: &Server) -> Client {
+    let mut client = server.connect().await;
+    client
+        .send(&ClientMessage::Login {
+            name: "admin",
+            pass: "admin",
+        })
+        .await;
+
+    assert!(matches!(
+        client.recv().await,
+        ServerMessage::LoggedIn(Ok(_))
+    ));
+    client
+}
+
+async fn run(client: &mut Client, name: &str, args: &str) {
+    let command = ClientMessage::Command {
+        request: 0,
+        chan: 0,
+        name,
+        args,
+    };
+
+    client.send(&command).await;
+}
+
+async fn notice(client: &mut Client) -> String {
+    match client
+        .recv_until(|message| matches!(message, ServerMessage::Notice { .. }))
+        .await
+    {
+        ServerMessage::Notice { chan, text } => {
+            assert_eq!(chan, 0);
+            text
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[tokio::test]
+async fn builtin_commands() {
+    let server = Server::start().await;
+    let mut alice = server.login("alice").await;
+    let commands = alice
+        .recv_until(|message| matches!(message, ServerMessage::Commands(_)))
+        .await;
+
+    let ServerMessage::Commands(commands) = commands else {
+        unreachable!();
+    };
+
+    let names: Vec<_> = commands
+        .iter()
+        .map(|command| command.name.as_str())
+        .collect();
+    assert_eq!(names, ["me", "topic", "invite", "kick", "mute"]);
+
+    // Actions are posted to the channel
+    run(&mut alice, "me", "waves").await;
+    let message = alice
+        .recv_until(|message| matches!(message, ServerMessage::Message(_)))
+        .await;
+
+    match message {
+        ServerMessage::Message(message) => match message.content {
+            MessageType::Action(text) => assert_eq!(text, "waves"),
+            _ => panic!("unexpected message"),
+        },
+        _ => unreachable!(),
+    }
+
+    // Topics are sent to everyone and replies only to the user
+    run(&mut alice, "topic", "Releases").await;
+    let topic = alice
+        .recv_until(|message| matches!(message, ServerMessage::Topic { chan: 0, .. }))
+        .await;
+
+    assert!(matches!(
+        topic,
+        ServerMessage::Topic { topic: Some(topic), .. } if topic == "Releases"
+    ));
+
+    run(&mut alice, "topic", "").await;
+    assert_eq!(notice(&mut alice).await, "The topic is: Releases");
+    run(&mut alice, "unknown", "").await;
+    assert_eq!(notice(&mut alice).await, "Unknown command /unknown");
+
+    // Only admins mute and kick
+    run(&mut alice, "kick", "admin").await;
+    assert_eq!(notice(&mut alice).await, "Only admins can /kick users");
+
+    let mut admin = admin(&server).await;
+    for minutes in ["1000000000000000000", "300000000000000000"] {
+        run(&mut admin, "mute", &format!("alice {minutes}")).await;
+        assert_eq!(notice(&mut admin).await, "Usage: /mute <name> [minutes]");
+    }
+
+    run(&mut admin, "mute", "alice 5").await;
+    assert_eq!(notice(&mut alice).await, "You're muted for 5 minutes");
+    assert_eq!(notice(&mut admin).await, "alice is muted for 5 minutes");
+
+    let say = ClientMessage::Say {
+        request: 0,
+        chan: 0,
+        text: "hi",
+    };
+    alice.send(&say).await;
+    assert!(notice(&mut alice).await.starts_with("you're muted for"));
+
+    run(&mut admin, "mute", "alice 0").await;
+    assert_eq!(notice(&mut alice).await, "You can post again");
+    assert_eq!(notice(&mut admin).await, "alice is unmuted");
+
+    // Invites and kicks reach online users only
+    run(&mut admin, "invite", "bob").await;
+    assert_eq!(notice(&mut admin).await, "bob isn't online");
+    run(&mut admin, "invite", "alice").await;
+    assert!(notice(&mut alice).await.starts_with("admin invites you to"));
+    assert_eq!(notice(&mut admin).await, "alice is invited");
+
+    run(&mut admin, "kick", "alice").await;
+    assert_eq!(notice(&mut admin).await, "alice is kicked");
+    alice.closed().await;
+}
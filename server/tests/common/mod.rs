@@ -0,0 +1,288 @@
+#![allow(dead_code)]
+
+use base::{
+    api::{ClientMessage, ServerMessage},
+    decode, encode,
+};
+use futures::{SinkExt, StreamExt};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, SocketAddr, TcpListener},
+    path::PathBuf,
+    process::{Child, ChildStdout, Command, ExitStatus, Stdio},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpSocket, TcpStream},
+    sync::mpsc,
+    time,
+};
+use websocket::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+const LENIENT_CONFIG: &str = r#"
+[rate]
+messages = { rate = 1000.0, burst = 1000 }
+uploads = { rate = 1000.0, burst = 1000 }
+logins = { rate = 1000.0, burst = 1000 }
+signups = { rate = 1000.0, burst = 1000 }
+"#;
+
+/// A server process listening on a free local port.
+pub struct Server {
+    pub addr: SocketAddr,
+    pub files: PathBuf,
+    child: Child,
+}
+
+impl Server {
+    pub async fn start() -> Self {
+        Self::start_with(|_| {}).await
+    }
+
+    /// Starts the server, letting the caller add flags and variables.
+    /// The address is passed with a variable, so a flag can override it.
+    pub async fn start_with<F>(configure: F) -> Self
+    where
+        F: FnOnce(&mut Command),
+    {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("free port");
+
+        let files = std::env::temp_dir().join(format!("voki-test-{}", addr.port()));
+
+        // Tests sign up many users from one address, so limits are raised.
+        // A test can pass its own config with the flag
+        let config = files.with_extension("toml");
+        std::fs::write(&config, LENIENT_CONFIG).expect("write config");
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_server"));
+        command
+            .env("VOKI_ADDRESS", addr.to_string())
+            .env("VOKI_CONFIG", &config)
+            .arg("--files")
+            .arg(&files)
+            .stdout(Stdio::null());
+
+        configure(&mut command);
+        let child = command.spawn().expect("run server");
+
+        let server = Self { addr, files, child };
+        for _ in 0..100 {
+            if TcpStream::connect(addr).await.is_ok() {
+                return server;
+            }
+
+            time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("server didn't start");
+    }
+
+    pub async fn connect(&self) -> Client {
+        let url = format!("ws://{}", self.addr);
+        let (stream, _) = websocket::connect_async(url).await.expect("connect");
+        Client(stream)
+    }
+
+    /// Connects from another local address, like `127.0.0.2`.
+    pub async fn connect_from(&self, ip: IpAddr) -> Client {
+        let socket = TcpSocket::new_v4().expect("socket");
+        socket.bind(SocketAddr::new(ip, 0)).expect("bind");
+        let stream = socket.connect(self.addr).await.expect("connect");
+        let url = format!("ws://{}", self.addr);
+        let (stream, _) = websocket::client_async(url, MaybeTlsStream::Plain(stream))
+            .await
+            .expect("handshake");
+
+        Client(stream)
+    }
+
+    /// Connects a client logged in as a new user.
+    pub async fn login(&self, name: &str) -> Client {
+        let mut client = self.connect().await;
+        client
+            .send(&ClientMessage::SignUp { name, pass: name })
+            .await;
+        client
+            .send(&ClientMessage::Login { name, pass: name })
+            .await;
+        for _ in 0..2 {
+            let logged = client
+                .recv_until(|message| matches!(message, ServerMessage::LoggedIn(_)))
+                .await;
+            assert!(matches!(logged, ServerMessage::LoggedIn(Ok(_))));
+        }
+
+        client
+    }
+
+    /// Takes the output of the server, if it's started with a piped one.
+    pub fn stdout(&mut self) -> ChildStdout {
+        self.child.stdout.take().expect("piped stdout")
+    }
+
+    pub fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    /// Asks the server to shut down gracefully.
+    #[cfg(unix)]
+    pub fn terminate(&self) {
+        let status = Command::new("kill")
+            .arg("-TERM")
+            .arg(self.child.id().to_string())
+            .status()
+            .expect("run kill");
+
+        assert!(status.success());
+    }
+
+    /// Waits until the server process exits.
+    pub async fn exited(&mut self) -> ExitStatus {
+        for _ in 0..100 {
+            if let Some(status) = self.child.try_wait().expect("wait server") {
+                return status;
+            }
+
+            time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("server didn't exit");
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.files);
+        let _ = std::fs::remove_file(self.files.with_extension("toml"));
+    }
+}
+
+pub struct Client(WebSocketStream<MaybeTlsStream<TcpStream>>);
+
+impl Client {
+    pub async fn send(&mut self, message: &ClientMessage<'_>) {
+        let mut buf = Vec::with_capacity(64);
+        encode(message, &mut buf).expect("encode");
+        self.send_raw(Message::Binary(buf.into())).await;
+    }
+
+    pub async fn send_raw(&mut self, message: Message) {
+        self.0.send(message).await.expect("send");
+    }
+
+    /// Receives the next server message.
+    pub async fn recv(&mut self) -> ServerMessage {
+        self.try_recv().await.expect("server message")
+    }
+
+    /// Receives the next server message or `None` if the connection is closed.
+    pub async fn try_recv(&mut self) -> Option<ServerMessage> {
+        let wait = async {
+            while let Some(Ok(message)) = self.0.next().await {
+                if let Message::Binary(bytes) = message {
+                    return Some(decode(&bytes).expect("decode"));
+                }
+            }
+
+            None
+        };
+
+        time::timeout(Duration::from_secs(5), wait)
+            .await
+            .expect("timeout")
+    }
+
+    /// Skips messages until the one matching the predicate.
+    pub async fn recv_until<F>(&mut self, mut f: F) -> ServerMessage
+    where
+        F: FnMut(&ServerMessage) -> bool,
+    {
+        loop {
+            let message = self.recv().await;
+            if f(&message) {
+                break message;
+            }
+        }
+    }
+
+    /// Waits until the server closes the connection.
+    pub async fn closed(&mut self) {
+        while self.try_recv().await.is_some() {}
+    }
+}
+
+/// A request received by the stand-in.
+pub struct Request {
+    /// The request line and the headers as they're sent.
+    pub head: String,
+    /// Headers with lowercase names.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> &str {
+        self.headers.get(name).map_or("", String::as_str)
+    }
+}
+
+/// Accepts requests like an HTTP service would do, answers with
+/// the given statuses and then with 200, and passes requests to the channel.
+pub fn stand_in(
+    listener: tokio::net::TcpListener,
+    statuses: &[u16],
+) -> mpsc::UnboundedReceiver<Request> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut statuses: VecDeque<_> = statuses.iter().copied().collect();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![];
+            let head_len = loop {
+                let mut chunk = [0; 1024];
+                let n = stream.read(&mut chunk).await.unwrap();
+                buf.extend_from_slice(&chunk[..n]);
+                if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break pos + 4;
+                }
+            };
+
+            let head = String::from_utf8_lossy(&buf[..head_len]).into_owned();
+            let headers: HashMap<_, _> = head
+                .lines()
+                .skip(1)
+                .filter_map(|line| line.split_once(": "))
+                .map(|(name, value)| (name.to_lowercase(), value.to_owned()))
+                .collect();
+
+            let len = headers
+                .get("content-length")
+                .map_or(0, |len| len.parse().unwrap());
+            while buf.len() < head_len + len {
+                let mut chunk = [0; 1024];
+                let n = stream.read(&mut chunk).await.unwrap();
+                buf.extend_from_slice(&chunk[..n]);
+            }
+
+            let status = statuses.pop_front().unwrap_or(200);
+            let response = format!(
+                "HTTP/1.1 {status} Status\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+            );
+
+            stream.write_all(response.as_bytes()).await.unwrap();
+            let body = buf.split_off(head_len);
+            let _ = tx.send(Request {
+                head,
+                headers,
+                body,
+            });
+        }
+    });
+
+    rx
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use base::api::{ClientMessage, ServerMessage};
+use common::Server;
+use std::{
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+fn write_config(name: &str, text: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("voki-{name}-{}.toml", std::process::id()));
+    std::fs::write(&path, text).expect("write config");
+    path
+}
+
+fn run_with_config(name: &str, text: &str) -> Output {
+    let path = write_config(name, text);
+    let output = Command::new(env!("CARGO_BIN_EXE_server"))
+        .arg("--config")
+        .arg(&path)
+        .output()
+        .expect("run server");
+
+    let _ = std::fs::remove_file(path);
+    output
+}
+
+fn assert_fails(output: Output, message: &str) {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains(message), "unexpected error: {stderr}");
+}
+
+#[test]
+fn invalid_values() {
+    assert_fails(
+        run_with_config("zero-upload", "[limits]\nupload_size = 0\n"),
+        "invalid limits.upload_size: must be positive",
+    );
+
+    assert_fails(
+        run_with_config("no-port", "address = \"127.0.0.1\"\n"),
+        "invalid address: expected host:port",
+    );
+
+    assert_fails(
+        run_with_config(
+            "no-cert",
+            "[tls]\ncert = \"missing.pem\"\nkey = \"missing.pem\"\n",
+        ),
+        "invalid tls.cert: missing.pem",
+    );
+}
+
+#[test]
+fn malformed_file() {
+    assert_fails(
+        run_with_config("unknown", "adress = \"127.0.0.1:4567\"\n"),
+        "unknown field `adress`",
+    );
+
+    assert_fails(
+        run_with_config("type", "[limits]\nhistory = \"many\"\n"),
+        "failed to parse",
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_server"))
+        .arg("--config")
+        .arg("missing.toml")
+        .output()
+        .expect("run server");
+
+    assert_fails(output, "failed to read missing.toml");
+}
+
+#[tokio::test]
+async fn precedence() {
+    // The address from the file is overridden by the variable,
+    // and the invalid history from the variable by the flag
+    let config = write_config(
+        "precedence",
+        r#"
+            address = "127.0.0.1:1"
+
+            [limits]
+            history = 5
+
+            [[channels]]
+            name = "general"
+        "#,
+    );
+
+    let server = Server::start_with(|command| {
+        command
+            .arg("--config")
+            .arg(&config)
+            .env("VOKI_HISTORY", "0")
+            .arg("--history")
+            .arg("1");
+    })
+    .await;
+
+    let mut alice = server.login("alice").await;
+    for text in ["first", "second"] {
+        alice
+            .send(&ClientMessage::Say {
+                request: 0,
+                chan: 0,
+                text,
+            })
+            .await;
+    }
+
+    alice
+        .recv_until(|message| matches!(message, ServerMessage::Message(_)))
+        .await;
+    alice
+        .recv_until(|message| matches!(message, ServerMessage::Message(_)))
+        .await;
+
+    let mut bob = server.login("bob").await;
+    match bob
+        .recv_until(|message| matches!(message, ServerMessage::Channel(_)))
+        .await
+    {
+        ServerMessage::Channel(chan) => {
+            assert_eq!(chan.name, "general");
+            assert_eq!(chan.history.len(), 1);
+        }
+        _ => unreachable!(),
+    }
+
+    let _ = std::fs::remove_file(config);
+}
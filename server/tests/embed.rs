@@ -0,0 +1,73 @@
+use base::{
+    api::{ClientMessage, ServerMessage},
+    decode, encode,
+};
+use futures::{SinkExt, StreamExt};
+use server::{config::ChannelSeed, Server};
+use tokio::net::TcpStream;
+use websocket::tungstenite::Message;
+
+#[tokio::test]
+async fn embedded_server() {
+    let server = Server::builder()
+        .address("127.0.0.1:0")
+        .configure(|config| {
+            config.channels = vec![ChannelSeed {
+                name: "embedded".into(),
+                icon: None,
+                webhooks: vec![],
+            }];
+        })
+        .bind()
+        .await
+        .expect("bind");
+
+    let addr = server.local_addr().expect("bound address");
+    assert_ne!(addr.port(), 0);
+
+    let url = format!("ws://{addr}");
+    let (mut stream, _) = websocket::connect_async(url).await.expect("connect");
+    for message in [
+        ClientMessage::SignUp {
+            name: "alice",
+            pass: "alice",
+        },
+        ClientMessage::Login {
+            name: "alice",
+            pass: "alice",
+        },
+    ] {
+        let mut buf = vec![];
+        encode(&message, &mut buf).expect("encode");
+        stream
+            .send(Message::Binary(buf.into()))
+            .await
+            .expect("send");
+    }
+
+    let mut server = Some(server);
+    let mut channels = vec![];
+    let mut reconnect = None;
+    while let Some(Ok(message)) = stream.next().await {
+        if let Message::Binary(bytes) = message {
+            match decode(&bytes).expect("decode") {
+                ServerMessage::Channel(chan) => {
+                    channels.push(chan.name);
+
+                    // Shut down in the background while the client is connected
+                    if let Some(server) = server.take() {
+                        tokio::spawn(server.shutdown());
+                    }
+                }
+                ServerMessage::ServerShutdown { reconnect_after } => {
+                    reconnect = Some(reconnect_after)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    assert_eq!(channels, ["embedded"]);
+    assert_eq!(reconnect, Some(5));
+    assert!(TcpStream::connect(addr).await.is_err());
+}
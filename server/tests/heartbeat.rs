@@ -0,0 +1,82 @@
+mod common;
+
+use base::api::{ClientMessage, ServerMessage};
+use common::Server;
+use std::time::Duration;
+use tokio::time;
+
+const CONFIG: &str = r#"
+[heartbeat]
+interval = 1
+timeout = 1
+login = 1
+
+[rate]
+signups = { rate = 1000.0, burst = 1000 }
+"#;
+
+async fn start(name: &str) -> Server {
+    let path = std::env::temp_dir().join(format!("voki-{name}-{}.toml", std::process::id()));
+    std::fs::write(&path, CONFIG).expect("write config");
+    let server = Server::start_with(|command| {
+        command.arg("--config").arg(&path);
+    })
+    .await;
+
+    let _ = std::fs::remove_file(path);
+    server
+}
+
+#[tokio::test]
+async fn login_deadline() {
+    let server = start("login-deadline").await;
+    let mut client = server.connect().await;
+    assert!(matches!(client.recv().await, ServerMessage::Closed));
+    client.closed().await;
+}
+
+#[tokio::test]
+async fn handshake_deadline() {
+    use tokio::{io::AsyncReadExt, net::TcpStream};
+
+    let server = start("handshake-deadline").await;
+    let mut stream = TcpStream::connect(server.addr).await.expect("connect");
+
+    // Nothing is sent, so the server gives up on the handshake and closes the socket
+    let mut buf = [0; 64];
+    let read = time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await;
+    assert!(matches!(read, Ok(Ok(0) | Err(_))), "{read:?}");
+}
+
+#[tokio::test]
+async fn pinged_client_stays() {
+    let server = start("pinged-client").await;
+    let mut alice = server.login("alice").await;
+
+    // Reading answers pings, so the connection outlives a few intervals
+    let read = async { while alice.try_recv().await.is_some() {} };
+
+    assert!(time::timeout(Duration::from_secs(4), read).await.is_err());
+
+    let text = "still here";
+    alice
+        .send(&ClientMessage::Say {
+            request: 0,
+            chan: 0,
+            text,
+        })
+        .await;
+    alice
+        .recv_until(|message| matches!(message, ServerMessage::Message(_)))
+        .await;
+}
+
+#[tokio::test]
+async fn silent_client_is_closed() {
+    let server = start("silent-client").await;
+    let mut alice = server.login("alice").await;
+
+    // Not reading means pings aren't answered
+    time::sleep(Duration::from_secs(4)).await;
+    alice.closed().await;
+}
@@ -0,0 +1,225 @@
+mod common;
+
+use base::{
+    api::{Channel, ClientMessage, LoginError, ServerMessage},
+    decode, encode,
+};
+use common::Server;
+use websocket::tungstenite::Message;
+
+async fn start(name: &str, config: &str) -> Server {
+    let path = std::env::temp_dir().join(format!("voki-{name}-{}.toml", std::process::id()));
+    std::fs::write(&path, config).expect("write config");
+    let server = Server::start_with(|command| {
+        command.arg("--config").arg(&path);
+    })
+    .await;
+
+    let _ = std::fs::remove_file(path);
+    server
+}
+
+#[tokio::test]
+async fn message_rate() {
+    let server = start(
+        "message-rate",
+        "[rate]\nmessages = { rate = 1.0, burst = 2 }\n",
+    )
+    .await;
+    let mut alice = server.login("alice").await;
+    for (request, text) in [(1, "one"), (2, "two"), (3, "three")] {
+        let say = ClientMessage::Say {
+            request,
+            chan: 0,
+            text,
+        };
+
+        alice.send(&say).await;
+    }
+
+    // The reply names the post it answers
+    let mut delivered = 0;
+    let mut limited = None;
+    while delivered < 2 || limited.is_none() {
+        match alice.recv().await {
+            ServerMessage::Message(_) => delivered += 1,
+            ServerMessage::RateLimited {
+                request,
+                retry_after_ms,
+            } => limited = Some((request, retry_after_ms)),
+            _ => {}
+        }
+    }
+
+    assert_eq!(delivered, 2);
+    assert!(matches!(limited, Some((3, 1..=1000))));
+}
+
+#[tokio::test]
+async fn command_rate() {
+    let server = start(
+        "command-rate",
+        "[rate]\nmessages = { rate = 1.0, burst = 2 }\n",
+    )
+    .await;
+    let mut alice = server.login("alice").await;
+
+    // An action is limited once, as a command
+    for (name, args) in [("me", "waves"), ("me", "smiles"), ("invite", "bob")] {
+        let command = ClientMessage::Command {
+            request: 0,
+            chan: 0,
+            name,
+            args,
+        };
+
+        alice.send(&command).await;
+    }
+
+    let mut delivered = 0;
+    let mut limited = None;
+    while delivered < 2 || limited.is_none() {
+        match alice.recv().await {
+            ServerMessage::Message(_) => delivered += 1,
+            ServerMessage::RateLimited { retry_after_ms, .. } => limited = Some(retry_after_ms),
+            ServerMessage::Notice { text, .. } => panic!("unexpected notice: {text}"),
+            _ => {}
+        }
+    }
+
+    assert_eq!(delivered, 2);
+    assert!(matches!(limited, Some(1..=1000)));
+}
+
+#[tokio::test]
+async fn signup_rate() {
+    let server = start(
+        "signup-rate",
+        "[rate]\nsignups = { rate = 0.01, burst = 2 }\n",
+    )
+    .await;
+    server.login("alice").await;
+    server.login("bob").await;
+
+    let mut client = server.connect().await;
+    let signup = ClientMessage::SignUp {
+        name: "eve",
+        pass: "eve",
+    };
+
+    client.send(&signup).await;
+    assert!(matches!(
+        client.recv().await,
+        ServerMessage::LoggedIn(Err(LoginError::Locked {
+            retry_after_secs: 1..
+        }))
+    ));
+}
+
+#[tokio::test]
+async fn login_lockout() {
+    let server = start("lockout", "[rate.lockout]\nfailures = 2\nduration = 60\n").await;
+
+    let mut client = server.connect().await;
+    for pass in ["wrong", "wrong", "admin"] {
+        let login = ClientMessage::Login {
+            name: "admin",
+            pass,
+        };
+
+        client.send(&login).await;
+        let expected = match pass {
+            "wrong" => matches!(
+                client.recv().await,
+                ServerMessage::LoggedIn(Err(LoginError::WrongNameOrPass))
+            ),
+            _ => matches!(
+                client.recv().await,
+                ServerMessage::LoggedIn(Err(LoginError::Locked {
+                    retry_after_secs: 1..=60
+                }))
+            ),
+        };
+
+        assert!(expected, "unexpected reply to {pass}");
+    }
+
+    // Other names aren't affected
+    let login = ClientMessage::Login {
+        name: "test0",
+        pass: "test0",
+    };
+
+    client.send(&login).await;
+    assert!(matches!(
+        client.recv().await,
+        ServerMessage::LoggedIn(Ok(_))
+    ));
+
+    // Nor the name from other addresses, so failures can't keep the user out
+    let mut other = server.connect_from([127, 0, 0, 2].into()).await;
+    let login = ClientMessage::Login {
+        name: "admin",
+        pass: "admin",
+    };
+
+    other.send(&login).await;
+    assert!(matches!(other.recv().await, ServerMessage::LoggedIn(Ok(_))));
+}
+
+#[tokio::test]
+async fn size_limits() {
+    let config =
+        "[limits]\ntext_size = 16\nupload_size = 1024\nmessage_size = 4096\nframe_size = 4096\n";
+    let server = start("size-limits", config).await;
+    let mut alice = server.login("alice").await;
+
+    let text = "a message over the text limit";
+    alice
+        .send(&ClientMessage::Say {
+            request: 0,
+            chan: 0,
+            text,
+        })
+        .await;
+    alice
+        .recv_until(|message| matches!(message, ServerMessage::TooLarge { max_size: 16, .. }))
+        .await;
+
+    let bytes = vec![0; 2000];
+    let file = ClientMessage::File {
+        request: 0,
+        chan: 0,
+        ext: "png",
+        bytes: &bytes,
+    };
+
+    alice.send(&file).await;
+    alice
+        .recv_until(|message| matches!(message, ServerMessage::TooLarge { max_size: 1024, .. }))
+        .await;
+
+    // A message over the websocket limit closes the connection
+    alice.send_raw(Message::Binary(vec![0; 5000].into())).await;
+    alice.closed().await;
+    server.login("bob").await;
+}
+
+#[test]
+fn decode_limit() {
+    let chan = ServerMessage::Channel(Channel {
+        id: 0,
+        name: String::new(),
+        icon: None,
+        topic: None,
+        history: vec![],
+    });
+
+    let mut bytes = vec![];
+    encode(&chan, &mut bytes).expect("encode");
+
+    // The history length is the last field, claim a huge one
+    let len = bytes.len();
+    bytes[len - 8..].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+    assert!(decode::<ServerMessage>(&bytes).is_err());
+}
@@ -0,0 +1,60 @@
+#![cfg(unix)]
+
+mod common;
+
+use base::api::{ClientMessage, ServerMessage};
+use common::Server;
+use serde_json::Value;
+use std::{io::Read, process::Stdio};
+
+#[tokio::test]
+async fn json_logs_without_content() {
+    let mut server = Server::start_with(|command| {
+        command
+            .env("VOKI_LOG_FORMAT", "json")
+            .env("RUST_LOG", "debug")
+            .stdout(Stdio::piped());
+    })
+    .await;
+
+    let mut alice = server.login("alice").await;
+    let text = "a secret text";
+    alice
+        .send(&ClientMessage::Say {
+            request: 0,
+            chan: 0,
+            text,
+        })
+        .await;
+    alice
+        .recv_until(|message| matches!(message, ServerMessage::Message(_)))
+        .await;
+
+    server.terminate();
+    alice.closed().await;
+    assert!(server.exited().await.success());
+
+    let mut output = String::new();
+    server
+        .stdout()
+        .read_to_string(&mut output)
+        .expect("read output");
+
+    assert!(!output.contains(text));
+
+    let records: Vec<Value> = output
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("json record"))
+        .collect();
+
+    // The message is logged in the span of its connection with the user id
+    let message = records
+        .iter()
+        .find(|record| record["fields"]["message"] == "message")
+        .expect("message record");
+
+    assert_eq!(message["fields"]["size"], text.len());
+    assert_eq!(message["span"]["name"], "connection");
+    assert!(message["span"]["addr"].is_string());
+    assert!(message["span"]["user"].is_number());
+}
@@ -0,0 +1,125 @@
+use base::{
+    api::{ClientMessage, ServerMessage},
+    decode, encode,
+};
+use futures::{SinkExt, StreamExt};
+use server::Server;
+use std::{net::SocketAddr, time::Duration};
+use tokio::time;
+use websocket::tungstenite::Message;
+
+async fn scrape(addr: SocketAddr) -> String {
+    let res = reqwest::get(format!("http://{addr}/metrics"))
+        .await
+        .expect("scrape");
+
+    assert!(res.status().is_success());
+    let content_type = &res.headers()["content-type"];
+    assert!(content_type.to_str().unwrap().starts_with("text/plain"));
+    res.text().await.expect("metrics")
+}
+
+/// Scrapes until the samples have the values, since they're updated
+/// concurrently with replies to clients.
+async fn expect(addr: SocketAddr, samples: &[(&str, &str)]) {
+    let mut metrics = String::new();
+    for _ in 0..100 {
+        metrics = scrape(addr).await;
+        let found = samples.iter().all(|(name, value)| {
+            metrics
+                .lines()
+                .any(|line| line.strip_prefix(name) == Some(&format!(" {value}")))
+        });
+
+        if found {
+            return;
+        }
+
+        time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("expected {samples:?} in:\n{metrics}");
+}
+
+#[tokio::test]
+async fn scrape_metrics() {
+    let server = Server::builder()
+        .address("127.0.0.1:0")
+        .configure(|config| config.metrics_address = Some("127.0.0.1:0".into()))
+        .bind()
+        .await
+        .expect("bind");
+
+    let addr = server.local_addr().expect("bound address");
+    let metrics = server.metrics_addr().expect("metrics address");
+    expect(metrics, &[("voki_connections", "0")]).await;
+
+    let url = format!("ws://{addr}");
+    let (mut alice, _) = websocket::connect_async(&url).await.expect("connect");
+    let send = |message: &ClientMessage| {
+        let mut buf = vec![];
+        encode(message, &mut buf).expect("encode");
+        Message::Binary(buf.into())
+    };
+
+    let signup = send(&ClientMessage::SignUp {
+        name: "alice",
+        pass: "alice",
+    });
+
+    let login = send(&ClientMessage::Login {
+        name: "alice",
+        pass: "alice",
+    });
+
+    let say = send(&ClientMessage::Say {
+        request: 0,
+        chan: 0,
+        text: "hi",
+    });
+
+    alice.send(signup).await.expect("send");
+    alice.send(login).await.expect("send");
+    alice.send(say).await.expect("send");
+    while let Some(Ok(message)) = alice.next().await {
+        if let Message::Binary(bytes) = message {
+            if let ServerMessage::Message(_) = decode(&bytes).expect("decode") {
+                break;
+            }
+        }
+    }
+
+    // A request which can't be decoded closes the connection
+    let (mut bob, _) = websocket::connect_async(&url).await.expect("connect");
+    bob.send(Message::Binary(vec![255; 4].into()))
+        .await
+        .expect("send");
+    while let Some(Ok(_)) = bob.next().await {}
+
+    expect(
+        metrics,
+        &[
+            ("voki_connections", "1"),
+            ("voki_logged_users", "1"),
+            ("voki_messages_total{chan=\"0\"}", "1"),
+            ("voki_decode_errors_total", "1"),
+            ("voki_fan_out_seconds_count", "1"),
+            ("voki_dropped_clients_total", "0"),
+        ],
+    )
+    .await;
+
+    let res = reqwest::get(format!("http://{metrics}/"))
+        .await
+        .expect("request");
+
+    assert_eq!(res.status(), reqwest::StatusCode::NOT_FOUND);
+    drop(alice);
+    expect(
+        metrics,
+        &[("voki_connections", "0"), ("voki_logged_users", "0")],
+    )
+    .await;
+
+    server.shutdown().await;
+}
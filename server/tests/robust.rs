@@ -0,0 +1,233 @@
+mod common;
+
+use base::api::{ClientMessage, LoginError, MessageType, ServerMessage};
+use common::{Client, Server};
+use websocket::tungstenite::Message;
+
+/// Checks the server still serves the chat.
+async fn assert_alive(server: &mut Server, name: &str) {
+    assert!(server.is_running());
+
+    let mut client = server.login(name).await;
+    client
+        .send(&ClientMessage::Say {
+            request: 0,
+            chan: 0,
+            text: name,
+        })
+        .await;
+    let message = client
+        .recv_until(|message| matches!(message, ServerMessage::Message(_)))
+        .await;
+
+    match message {
+        ServerMessage::Message(message) => match message.content {
+            MessageType::Text(text) => assert_eq!(text, name),
+            _ => panic!("unexpected message"),
+        },
+        _ => unreachable!(),
+    }
+}
+
+async fn assert_closed(client: &mut Client) {
+    assert!(matches!(client.recv().await, ServerMessage::Closed));
+    client.closed().await;
+}
+
+#[tokio::test]
+async fn malformed_bytes() {
+    let mut server = Server::start().await;
+
+    let mut client = server.connect().await;
+    client.send_raw(Message::Binary(vec![0xFF; 7].into())).await;
+    assert_closed(&mut client).await;
+
+    // Unknown variant
+    let mut client = server.connect().await;
+    client
+        .send_raw(Message::Binary(vec![99, 0, 0, 0].into()))
+        .await;
+    assert_closed(&mut client).await;
+
+    // A string claiming to be huge
+    let mut bytes = vec![1, 0, 0, 0];
+    bytes.extend(u64::MAX.to_le_bytes());
+    let mut client = server.connect().await;
+    client.send_raw(Message::Binary(bytes.into())).await;
+    assert_closed(&mut client).await;
+
+    // Empty frame
+    let mut client = server.connect().await;
+    client.send_raw(Message::Binary(vec![].into())).await;
+    assert_closed(&mut client).await;
+
+    assert_alive(&mut server, "malformed").await;
+}
+
+#[tokio::test]
+async fn unexpected_frames() {
+    let mut server = Server::start().await;
+
+    let mut client = server.connect().await;
+    client.send_raw(Message::Text("hello".into())).await;
+    client.send_raw(Message::Ping(vec![1, 2, 3].into())).await;
+    client
+        .send(&ClientMessage::Login {
+            name: "admin",
+            pass: "admin",
+        })
+        .await;
+
+    assert!(matches!(
+        client.recv().await,
+        ServerMessage::LoggedIn(Ok(_)),
+    ));
+
+    assert_alive(&mut server, "frames").await;
+}
+
+#[tokio::test]
+async fn requests_before_login() {
+    let mut server = Server::start().await;
+
+    let mut client = server.connect().await;
+    client
+        .send(&ClientMessage::Say {
+            request: 0,
+            chan: 0,
+            text: "hi",
+        })
+        .await;
+    assert_closed(&mut client).await;
+
+    let mut client = server.connect().await;
+    client
+        .send(&ClientMessage::File {
+            request: 0,
+            chan: 0,
+            ext: "png",
+            bytes: &[1, 2, 3],
+        })
+        .await;
+    assert_closed(&mut client).await;
+
+    assert_alive(&mut server, "before").await;
+}
+
+#[tokio::test]
+async fn out_of_order_requests() {
+    let mut server = Server::start().await;
+
+    let mut client = server.login("twice").await;
+    client
+        .send(&ClientMessage::Login {
+            name: "twice",
+            pass: "twice",
+        })
+        .await;
+
+    let logged = client
+        .recv_until(|message| matches!(message, ServerMessage::LoggedIn(_)))
+        .await;
+    assert!(matches!(
+        logged,
+        ServerMessage::LoggedIn(Err(LoginError::AlreadyLogged)),
+    ));
+
+    client
+        .send(&ClientMessage::SignUp {
+            name: "other",
+            pass: "other",
+        })
+        .await;
+
+    let signed = client
+        .recv_until(|message| matches!(message, ServerMessage::LoggedIn(_)))
+        .await;
+    assert!(matches!(
+        signed,
+        ServerMessage::LoggedIn(Err(LoginError::AlreadyLogged)),
+    ));
+
+    let mut other = server.connect().await;
+    other
+        .send(&ClientMessage::SignUp {
+            name: "twice",
+            pass: "twice",
+        })
+        .await;
+    assert!(matches!(
+        other.recv().await,
+        ServerMessage::LoggedIn(Err(LoginError::NameAlreadyExists)),
+    ));
+
+    // Unknown channel is ignored, broken and unsupported files get a notice
+    client
+        .send(&ClientMessage::Say {
+            request: 0,
+            chan: 99,
+            text: "?",
+        })
+        .await;
+    client
+        .send(&ClientMessage::File {
+            request: 0,
+            chan: 0,
+            ext: "png",
+            bytes: b"not an image",
+        })
+        .await;
+    client
+        .recv_until(|message| {
+            matches!(message, ServerMessage::Notice { text, .. } if text.contains("valid image"))
+        })
+        .await;
+
+    client
+        .send(&ClientMessage::File {
+            request: 0,
+            chan: 0,
+            ext: "gif",
+            bytes: b"GIF89a\x01\x00\x01\x00",
+        })
+        .await;
+    client
+        .recv_until(|message| {
+            matches!(message, ServerMessage::Notice { text, .. } if text.contains("JPEG and PNG"))
+        })
+        .await;
+
+    assert_alive(&mut server, "order").await;
+}
+
+#[tokio::test]
+async fn abrupt_disconnects() {
+    let mut server = Server::start().await;
+
+    // Drop the connection before the handshake
+    drop(tokio::net::TcpStream::connect(server.addr).await.unwrap());
+
+    // Drop the connection in the middle of the login
+    let mut client = server.connect().await;
+    client
+        .send(&ClientMessage::Login {
+            name: "admin",
+            pass: "admin",
+        })
+        .await;
+    drop(client);
+
+    // Drop logged in clients while the channel is busy
+    for n in 0..10 {
+        let mut client = server.login(&format!("gone{n}")).await;
+        client
+            .send(&ClientMessage::Say {
+                request: 0,
+                chan: 0,
+                text: "bye",
+            })
+            .await;
+    }
+
+    assert_alive(&mut server, "abrupt").await;
+}
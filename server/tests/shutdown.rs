@@ -0,0 +1,44 @@
+#![cfg(unix)]
+
+mod common;
+
+use base::api::{ClientMessage, ServerMessage};
+use common::Server;
+
+#[tokio::test]
+async fn graceful_shutdown() {
+    let mut server = Server::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    alice
+        .recv_until(|message| matches!(message, ServerMessage::Channel(_)))
+        .await;
+
+    alice
+        .send(&ClientMessage::Say {
+            request: 0,
+            chan: 0,
+            text: "bye",
+        })
+        .await;
+
+    bob.recv_until(|message| matches!(message, ServerMessage::Message(_)))
+        .await;
+
+    server.terminate();
+
+    for client in [&mut alice, &mut bob] {
+        let message = client
+            .recv_until(|message| matches!(message, ServerMessage::ServerShutdown { .. }))
+            .await;
+
+        assert!(matches!(
+            message,
+            ServerMessage::ServerShutdown { reconnect_after: 5 }
+        ));
+
+        client.closed().await;
+    }
+
+    assert!(server.exited().await.success());
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use base::api::{ClientMessage, MessageType, ServerMessage};
+use common::Server;
+use image::{DynamicImage, GenericImageView, ImageError, ImageFormat, ImageReader, Rgb, RgbImage};
+use server::thumb::{self, THUMB_SIZE};
+use std::io::Cursor;
+
+/// Something which shouldn't leave the uploader's device.
+const LOCATION: &[u8] = b"GPS 55.7558N 37.6173E";
+
+fn image(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+    let image = RgbImage::from_pixel(width, height, Rgb([200, 100, 50]));
+    let mut buf = Cursor::new(vec![]);
+    DynamicImage::ImageRgb8(image)
+        .write_to(&mut buf, format)
+        .expect("encode");
+    buf.into_inner()
+}
+
+/// Adds an EXIF segment with the orientation and the location to the JPEG.
+fn with_exif(jpeg: &[u8], orientation: u16) -> Vec<u8> {
+    let mut exif = b"Exif\0\0II*\0\x08\0\0\0".to_vec();
+    exif.extend_from_slice(&1u16.to_le_bytes());
+    exif.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0]);
+    exif.extend_from_slice(&orientation.to_le_bytes());
+    exif.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    exif.extend_from_slice(LOCATION);
+
+    let len = (exif.len() + 2) as u16;
+    let mut out = jpeg[..2].to_vec();
+    out.extend_from_slice(&[0xff, 0xe1]);
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(&exif);
+    out.extend_from_slice(&jpeg[2..]);
+    out
+}
+
+fn has_exif(bytes: &[u8]) -> bool {
+    let found = |needle: &[u8]| bytes.windows(needle.len()).any(|window| window == needle);
+    found(b"Exif") || found(LOCATION)
+}
+
+fn size(bytes: &[u8]) -> (u32, u32) {
+    ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .expect("format")
+        .decode()
+        .expect("decode")
+        .dimensions()
+}
+
+#[test]
+fn thumbnails() {
+    // Large images are shrunk keeping the aspect ratio
+    let png = image(1280, 640, ImageFormat::Png);
+    let made = thumb::make("png", &png).expect("png");
+    assert_eq!(made.format, ImageFormat::Png);
+    assert_eq!(made.ext(), "png");
+    assert_eq!(size(&made.thumb), (THUMB_SIZE, THUMB_SIZE / 2));
+
+    let jpeg = image(240, 960, ImageFormat::Jpeg);
+    let made = thumb::make("jpg", &jpeg).expect("jpeg");
+    assert_eq!(made.format, ImageFormat::Jpeg);
+    assert_eq!(size(&made.thumb), (THUMB_SIZE / 4, THUMB_SIZE));
+
+    // Small ones keep their size
+    let small = image(100, 50, ImageFormat::Png);
+    let made = thumb::make("png", &small).expect("small");
+    assert_eq!(size(&made.thumb), (100, 50));
+}
+
+#[test]
+fn originals_are_kept() {
+    let jpeg = image(800, 600, ImageFormat::Jpeg);
+    let made = thumb::make("jpeg", &jpeg).expect("jpeg");
+    assert_eq!(made.orig, jpeg);
+
+    // The extension doesn't matter when the content is known
+    let png = image(10, 10, ImageFormat::Png);
+    let made = thumb::make("jpg", &png).expect("png");
+    assert_eq!(made.format, ImageFormat::Png);
+    assert_eq!(made.orig, png);
+}
+
+#[test]
+fn metadata_is_stripped() {
+    // Without a rotation the original is only stripped, not encoded again
+    let jpeg = image(80, 40, ImageFormat::Jpeg);
+    let made = thumb::make("jpg", &with_exif(&jpeg, 1)).expect("jpeg");
+    assert!(!has_exif(&made.orig));
+    assert_eq!(made.orig, jpeg);
+
+    // A rotated one is stored the way it's shown
+    let made = thumb::make("jpg", &with_exif(&jpeg, 6)).expect("rotated");
+    assert!(!has_exif(&made.orig));
+    assert!(!has_exif(&made.thumb));
+    assert_eq!(size(&made.orig), (40, 80));
+    assert_eq!(size(&made.thumb), (40, 80));
+}
+
+#[tokio::test]
+async fn uploads_without_metadata() {
+    let server = Server::start().await;
+    let mut alice = server.login("alice").await;
+    let jpeg = with_exif(&image(80, 40, ImageFormat::Jpeg), 1);
+    alice
+        .send(&ClientMessage::File {
+            request: 1,
+            chan: 0,
+            ext: "jpg",
+            bytes: &jpeg,
+        })
+        .await;
+
+    let message = alice
+        .recv_until(|message| matches!(message, ServerMessage::Message(_)))
+        .await;
+
+    let ServerMessage::Message(message) = message else {
+        unreachable!();
+    };
+
+    let MessageType::File { orig, .. } = message.content else {
+        panic!("unexpected message");
+    };
+
+    let name = orig.rsplit('/').next().expect("file name");
+    let stored = std::fs::read(server.files.join(name)).expect("stored original");
+    assert!(!has_exif(&stored));
+    assert_eq!(size(&stored), (80, 40));
+}
+
+#[test]
+fn rejected_formats() {
+    let unsupported =
+        |ext, bytes: &[u8]| matches!(thumb::make(ext, bytes), Err(ImageError::Unsupported(_)));
+
+    assert!(unsupported("gif", b"GIF89a\x01\x00\x01\x00"));
+    assert!(unsupported("webp", b"RIFF\x00\x00\x00\x00WEBPVP8 "));
+    assert!(unsupported("txt", b"plain text"));
+
+    // Known but broken files fail to decode
+    let broken = thumb::make("png", b"not an image");
+    assert!(matches!(broken, Err(err) if !matches!(err, ImageError::Unsupported(_))));
+}
@@ -0,0 +1,106 @@
+use base::{
+    api::{ClientMessage, ServerMessage},
+    decode, encode,
+};
+use futures::{SinkExt, StreamExt};
+use rustls::{crypto::ring, pki_types::CertificateDer, ClientConfig, RootCertStore};
+use server::{config::Tls, Server};
+use std::{net::SocketAddr, path::Path, sync::Arc, time::Duration};
+use tokio::{net::TcpStream, time};
+use tokio_rustls::TlsConnector;
+use websocket::tungstenite::Message;
+
+/// Writes a new self-signed certificate and returns it.
+fn write_cert(dir: &Path) -> CertificateDer<'static> {
+    let rcgen::CertifiedKey { cert, key_pair } =
+        rcgen::generate_simple_self_signed(vec!["localhost".into()]).expect("generate");
+
+    std::fs::write(dir.join("cert.pem"), cert.pem()).expect("write cert");
+    std::fs::write(dir.join("key.pem"), key_pair.serialize_pem()).expect("write key");
+    cert.der().clone()
+}
+
+/// Connects over TLS trusting only the certificate and logs in.
+async fn login(addr: SocketAddr, cert: CertificateDer<'static>) -> Result<(), String> {
+    let mut roots = RootCertStore::empty();
+    roots.add(cert).expect("add root");
+
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .expect("protocol versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let tcp = TcpStream::connect(addr).await.expect("connect");
+    let tls = TlsConnector::from(Arc::new(config))
+        .connect("localhost".try_into().expect("server name"), tcp)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let url = format!("wss://localhost:{}", addr.port());
+    let (mut stream, _) = websocket::client_async(url, tls)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let mut buf = vec![];
+    let login = ClientMessage::Login {
+        name: "admin",
+        pass: "admin",
+    };
+
+    encode(&login, &mut buf).expect("encode");
+    stream
+        .send(Message::Binary(buf.into()))
+        .await
+        .expect("send");
+    match stream.next().await {
+        Some(Ok(Message::Binary(bytes))) => match decode(&bytes).expect("decode") {
+            ServerMessage::LoggedIn(Ok(_)) => Ok(()),
+            _ => Err("not logged in".into()),
+        },
+        _ => Err("no reply".into()),
+    }
+}
+
+#[tokio::test]
+async fn secure_websocket() {
+    let dir = std::env::temp_dir().join(format!("voki-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("create dir");
+    let first = write_cert(&dir);
+
+    let server = Server::builder()
+        .address("127.0.0.1:0")
+        .configure(|config| {
+            config.tls = Some(Tls {
+                reload: 1,
+                ..Tls::new(dir.join("cert.pem"), dir.join("key.pem"))
+            });
+        })
+        .bind()
+        .await
+        .expect("bind");
+
+    let addr = server.local_addr().expect("bound address");
+    login(addr, first.clone()).await.expect("login over tls");
+
+    // A plain websocket can't connect to the secure listener
+    let plain = format!("ws://{addr}");
+    assert!(websocket::connect_async(plain).await.is_err());
+
+    // The new certificate is picked up without a restart
+    let second = write_cert(&dir);
+    let mut reloaded = false;
+    for _ in 0..50 {
+        time::sleep(Duration::from_millis(100)).await;
+        if login(addr, second.clone()).await.is_ok() {
+            reloaded = true;
+            break;
+        }
+    }
+
+    assert!(reloaded, "certificate wasn't reloaded");
+    assert!(login(addr, first).await.is_err());
+
+    server.shutdown().await;
+    let _ = std::fs::remove_dir_all(dir);
+}
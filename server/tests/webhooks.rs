@@ -0,0 +1,193 @@
+mod common;
+
+use base::{api::ClientMessage, encode};
+use common::{stand_in, Request};
+use futures::SinkExt;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use server::{
+    config::{ChannelSeed, Subscription},
+    store::Url,
+    Server,
+};
+use sha2::Sha256;
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::Path,
+    time::Duration,
+};
+use tokio::{net::TcpListener, sync::mpsc, time};
+use websocket::tungstenite::Message;
+
+const SECRET: &str = "secret";
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Checks the signature like a receiver is supposed to do.
+fn verify(request: &Request) {
+    let timestamp = request.header("x-voki-timestamp");
+    let signature = request.header("x-voki-signature");
+    let signature = signature.strip_prefix("sha256=").expect("sha256 signature");
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(&request.body);
+    mac.verify_slice(&hex::decode(signature).unwrap())
+        .expect("valid signature");
+}
+
+fn json(request: &Request) -> Value {
+    serde_json::from_slice(&request.body).expect("json body")
+}
+
+async fn server(url: &str, data: &Path) -> Server {
+    let url = Url::parse(url).unwrap();
+    let data = data.to_owned();
+    Server::builder()
+        .address("127.0.0.1:0")
+        .configure(move |config| {
+            config.storage.data = Some(data);
+            config.delivery.backoff = 1;
+            config.channels = vec![ChannelSeed {
+                name: "hooked".into(),
+                icon: None,
+                webhooks: vec![Subscription {
+                    url,
+                    secret: SECRET.into(),
+                    events: vec![],
+                }],
+            }];
+        })
+        .bind()
+        .await
+        .expect("bind")
+}
+
+async fn next(requests: &mut mpsc::UnboundedReceiver<Request>) -> Request {
+    time::timeout(Duration::from_secs(10), requests.recv())
+        .await
+        .expect("request in time")
+        .expect("request")
+}
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("voki-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[tokio::test]
+async fn signed_events() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let mut requests = stand_in(listener, &[500]);
+    let data = temp_dir("signed-events");
+    let server = server(&url, &data).await;
+
+    let url = format!("ws://{}", server.local_addr().unwrap());
+    let (mut stream, _) = websocket::connect_async(url).await.expect("connect");
+    for message in [
+        ClientMessage::SignUp {
+            name: "alice",
+            pass: "alice",
+        },
+        ClientMessage::Login {
+            name: "alice",
+            pass: "alice",
+        },
+        ClientMessage::Say {
+            request: 0,
+            chan: 0,
+            text: "hello",
+        },
+    ] {
+        let mut buf = vec![];
+        encode(&message, &mut buf).expect("encode");
+        stream
+            .send(Message::Binary(buf.into()))
+            .await
+            .expect("send");
+    }
+
+    // The first attempt fails, so the same event is sent again after the backoff
+    let failed = next(&mut requests).await;
+    let join = next(&mut requests).await;
+    assert_eq!(
+        failed.header("x-voki-delivery"),
+        join.header("x-voki-delivery")
+    );
+    assert_eq!(join.header("x-voki-event"), "join");
+    verify(&join);
+    let body = json(&join);
+    assert_eq!(body["chan"], 0);
+    assert_eq!(body["name"], "alice");
+
+    // Events are sent in order, the message waits for the join
+    let message = next(&mut requests).await;
+    assert_eq!(message.header("x-voki-event"), "message");
+    assert_eq!(message.header("content-type"), "application/json");
+    verify(&message);
+    let body = json(&message);
+    assert_eq!(body["event"], "message");
+    assert_eq!(body["text"], "hello");
+    assert_eq!(body["from"], json(&join)["user"]);
+
+    // Another connection of an online user isn't a join
+    let url = format!("ws://{}", server.local_addr().unwrap());
+    let (mut other, _) = websocket::connect_async(url).await.expect("connect");
+    for message in [
+        ClientMessage::Login {
+            name: "alice",
+            pass: "alice",
+        },
+        ClientMessage::Say {
+            request: 0,
+            chan: 0,
+            text: "again",
+        },
+    ] {
+        let mut buf = vec![];
+        encode(&message, &mut buf).expect("encode");
+        other.send(Message::Binary(buf.into())).await.expect("send");
+    }
+
+    let message = next(&mut requests).await;
+    assert_eq!(message.header("x-voki-event"), "message");
+    assert_eq!(json(&message)["text"], "again");
+
+    server.shutdown().await;
+    std::fs::remove_dir_all(data).unwrap();
+}
+
+#[tokio::test]
+async fn queue_survives_restart() {
+    // Reserve a port, the endpoint is down until the restart
+    let addr: SocketAddr = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    };
+
+    let url = format!("http://{addr}/hook");
+    let data = temp_dir("queue-survives-restart");
+    let first = server(&url, &data).await;
+    first
+        .service()
+        .say(0, 0, "queued", LOCALHOST)
+        .await
+        .unwrap();
+
+    // Let it fail once, the next attempt is after the backoff
+    time::sleep(Duration::from_millis(300)).await;
+    first.shutdown().await;
+    let saved = std::fs::read_to_string(data.join("deliveries.json")).unwrap();
+    assert!(saved.contains("queued"));
+
+    let listener = TcpListener::bind(addr).await.unwrap();
+    let mut requests = stand_in(listener, &[]);
+    let second = server(&url, &data).await;
+    let request = next(&mut requests).await;
+    verify(&request);
+    assert_eq!(json(&request)["text"], "queued");
+
+    second.shutdown().await;
+    std::fs::remove_dir_all(data).unwrap();
+}
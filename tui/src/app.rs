@@ -0,0 +1,161 @@
+use base::api::ServerMessage;
+use client::{Event, Reply, State};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::path::PathBuf;
+
+/// Lines the history scrolls by with page keys.
+const PAGE: u16 = 5;
+
+/// What the user asks the client to do.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    Login { name: String, pass: String },
+    Send { chan: u32, text: String },
+    Upload { chan: u32, path: PathBuf },
+    Quit,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Field {
+    #[default]
+    Name,
+    Pass,
+}
+
+/// What the input line is for.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    #[default]
+    Message,
+    Upload,
+}
+
+/// The state of the terminal UI, the chat itself is in [`State`].
+#[derive(Default)]
+pub struct App {
+    pub name: String,
+    pub pass: String,
+    pub field: Field,
+    pub chan: u32,
+    pub input: String,
+    pub mode: Mode,
+    /// Lines the history is scrolled up by from its end.
+    pub scroll: u16,
+    /// The last error or warning, it's shown until the next one.
+    pub status: Option<String>,
+}
+
+impl App {
+    /// Handles the pressed key, returns the action it makes.
+    pub fn key(&mut self, key: KeyEvent, state: &State) -> Option<Action> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if ctrl && key.code == KeyCode::Char('c') {
+            return Some(Action::Quit);
+        }
+
+        match state.login() {
+            Some(_) => self.chat_key(key, ctrl, state),
+            None => self.login_key(key),
+        }
+    }
+
+    fn login_key(&mut self, key: KeyEvent) -> Option<Action> {
+        let field = match self.field {
+            Field::Name => &mut self.name,
+            Field::Pass => &mut self.pass,
+        };
+
+        match key.code {
+            KeyCode::Esc => return Some(Action::Quit),
+            KeyCode::Char(c) => field.push(c),
+            KeyCode::Backspace => {
+                field.pop();
+            }
+            KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down => {
+                self.field = match self.field {
+                    Field::Name => Field::Pass,
+                    Field::Pass => Field::Name,
+                };
+            }
+            KeyCode::Enter if self.name.is_empty() => self.field = Field::Name,
+            KeyCode::Enter if self.pass.is_empty() => self.field = Field::Pass,
+            KeyCode::Enter => {
+                self.status = None;
+                return Some(Action::Login {
+                    name: self.name.clone(),
+                    pass: self.pass.clone(),
+                });
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    fn chat_key(&mut self, key: KeyEvent, ctrl: bool, state: &State) -> Option<Action> {
+        match key.code {
+            KeyCode::Char('o') if ctrl => {
+                self.mode = Mode::Upload;
+                self.input.clear();
+            }
+            KeyCode::Esc if self.mode == Mode::Upload => {
+                self.mode = Mode::Message;
+                self.input.clear();
+            }
+            KeyCode::Esc => return Some(Action::Quit),
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Tab => self.select(state, 1),
+            KeyCode::BackTab => self.select(state, -1),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_add(PAGE),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(PAGE),
+            KeyCode::Enter if self.input.trim().is_empty() => {}
+            KeyCode::Enter => {
+                let input = std::mem::take(&mut self.input);
+                let chan = self.chan;
+                self.scroll = 0;
+                return Some(match std::mem::take(&mut self.mode) {
+                    Mode::Message => Action::Send { chan, text: input },
+                    Mode::Upload => Action::Upload {
+                        chan,
+                        path: input.trim().into(),
+                    },
+                });
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Moves the channel selection by the step, wrapping around the list.
+    fn select(&mut self, state: &State, step: i64) {
+        let len = state.channels().count() as i64;
+        if len > 0 {
+            self.chan = (self.chan as i64 + step).rem_euclid(len) as u32;
+            self.scroll = 0;
+        }
+    }
+
+    /// Shows replies and server messages the user should know about.
+    pub fn event(&mut self, event: &Event) {
+        self.status = match event {
+            Event::Reply { reply, .. } => match reply {
+                Reply::LoggedIn(Ok(_)) | Reply::SignedUp(Ok(_)) => None,
+                Reply::LoggedIn(Err(err)) | Reply::SignedUp(Err(err)) => Some(err.to_string()),
+                Reply::RateLimited { retry_after } => Some(format!(
+                    "too many messages, try again in {:.1}s",
+                    retry_after.as_secs_f32()
+                )),
+                Reply::TooLarge { max_size } => Some(format!("too large, max {max_size} bytes")),
+            },
+            Event::Message(ServerMessage::Closed) => Some("closed by the server".into()),
+            Event::Message(ServerMessage::ServerShutdown { reconnect_after }) => Some(format!(
+                "the server shuts down, reconnect after {reconnect_after}s"
+            )),
+            Event::Message(_) => return,
+        };
+    }
+}
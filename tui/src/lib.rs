@@ -0,0 +1,6 @@
+//! The terminal client of voki.
+
+pub mod app;
+pub mod ui;
+
+pub use self::app::{Action, App};
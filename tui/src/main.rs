@@ -0,0 +1,122 @@
+use clap::Parser;
+use client::{Client, Request};
+use crossterm::event::{Event as TermEvent, EventStream, KeyEventKind};
+use futures::StreamExt;
+use ratatui::DefaultTerminal;
+use std::{io, path::Path, process::ExitCode};
+use voki_tui::{ui, Action, App};
+
+/// Chats with voki in the terminal.
+///
+/// Tab and Shift+Tab switch channels, PageUp and PageDown scroll the history,
+/// Ctrl+O uploads a file and Esc quits.
+#[derive(Parser)]
+#[clap(author, version, about)]
+struct Args {
+    /// Websocket url of the chat server
+    #[clap(long, env = "VOKI_URL", default_value = "ws://localhost:4567")]
+    url: String,
+
+    /// User name, the login form is filled with it
+    #[clap(long, env = "VOKI_NAME")]
+    name: Option<String>,
+
+    /// User password, with the name it logs in right away
+    #[clap(long, env = "VOKI_PASS")]
+    pass: Option<String>,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    let mut client = match Client::connect(&args.url).await {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("error: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut app = App {
+        name: args.name.unwrap_or_default(),
+        pass: args.pass.unwrap_or_default(),
+        ..App::default()
+    };
+
+    if !app.name.is_empty() && !app.pass.is_empty() {
+        let login = Request::Login {
+            name: app.name.clone(),
+            pass: app.pass.clone(),
+        };
+
+        let _ = client.sender().send(login);
+    }
+
+    let mut terminal = ratatui::init();
+    let res = run(&mut terminal, &mut app, &mut client).await;
+    ratatui::restore();
+
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(terminal: &mut DefaultTerminal, app: &mut App, client: &mut Client) -> io::Result<()> {
+    let mut keys = EventStream::new();
+    loop {
+        terminal.draw(|frame| ui::draw(frame, app, client.state()))?;
+        tokio::select! {
+            event = keys.next() => {
+                let key = match event {
+                    Some(Ok(TermEvent::Key(key))) if key.kind == KeyEventKind::Press => key,
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => return Err(err),
+                    None => return Ok(()),
+                };
+
+                let Some(action) = app.key(key, client.state()) else {
+                    continue;
+                };
+
+                let sent = match action {
+                    Action::Quit => return Ok(()),
+                    Action::Login { name, pass } => client.sender().send(Request::Login { name, pass }),
+                    Action::Send { chan, text } => client.say(chan, &text),
+                    Action::Upload { chan, path } => match read(&path).await {
+                        Ok((ext, bytes)) => client.upload(chan, &ext, bytes),
+                        Err(err) => {
+                            app.status = Some(err);
+                            continue;
+                        }
+                    },
+                };
+
+                if sent.is_err() {
+                    return Err(io::Error::other("the connection is closed"));
+                }
+            }
+            event = client.next() => match event {
+                Some(event) => app.event(&event),
+                None => return Err(io::Error::other("disconnected from the server")),
+            },
+        }
+    }
+}
+
+/// Reads the file to upload, returns its extension and bytes.
+async fn read(path: &Path) -> Result<(String, Vec<u8>), String> {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .ok_or_else(|| format!("{} has no extension", path.display()))?;
+
+    let bytes = tokio::fs::read(path)
+        .await
+        .map_err(|err| format!("couldn't read {}: {err}", path.display()))?;
+
+    Ok((ext.to_lowercase(), bytes))
+}
@@ -0,0 +1,159 @@
+use crate::app::{App, Field, Mode};
+use client::{state::MessageContent, State};
+use ratatui::{
+    layout::{Constraint, Layout, Position, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span, Text},
+    widgets::{Block, List, ListItem, ListState, Paragraph, Wrap},
+    Frame,
+};
+
+/// Draws the login form until the user is logged in and then the chat.
+pub fn draw(frame: &mut Frame, app: &App, state: &State) {
+    match state.login() {
+        Some(_) => chat(frame, app, state),
+        None => login(frame, app),
+    }
+}
+
+fn login(frame: &mut Frame, app: &App) {
+    let [_, area, _] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(8),
+        Constraint::Fill(1),
+    ])
+    .areas(frame.area());
+
+    let [_, area, _] = Layout::horizontal([
+        Constraint::Fill(1),
+        Constraint::Length(40),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+
+    let block = Block::bordered().title(" voki ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let [name, pass, _, status] = Layout::vertical([Constraint::Length(2); 4]).areas(inner);
+    let field = |label: &'static str, value: String, selected: bool| {
+        let style = match selected {
+            true => Style::new().bold(),
+            false => Style::new(),
+        };
+
+        Paragraph::new(Line::from(vec![Span::styled(label, style), value.into()]))
+    };
+
+    let hidden = "*".repeat(app.pass.chars().count());
+    frame.render_widget(
+        field("Name: ", app.name.clone(), app.field == Field::Name),
+        name,
+    );
+    frame.render_widget(field("Password: ", hidden, app.field == Field::Pass), pass);
+    if let Some(err) = &app.status {
+        frame.render_widget(Paragraph::new(err.as_str()).fg(Color::Red), status);
+    }
+
+    let (area, len) = match app.field {
+        Field::Name => (name, "Name: ".len() + app.name.chars().count()),
+        Field::Pass => (pass, "Password: ".len() + app.pass.chars().count()),
+    };
+
+    frame.set_cursor_position(Position::new(area.x + len as u16, area.y));
+}
+
+fn chat(frame: &mut Frame, app: &App, state: &State) {
+    let [channels, chat] =
+        Layout::horizontal([Constraint::Percentage(25), Constraint::Fill(1)]).areas(frame.area());
+
+    let topic = state.topic(app.chan);
+    let [topic_area, history, input] = Layout::vertical([
+        Constraint::Length(topic.is_some().into()),
+        Constraint::Fill(1),
+        Constraint::Length(3),
+    ])
+    .areas(chat);
+
+    channel_list(frame, app, state, channels);
+    if let Some(topic) = topic {
+        let topic = Paragraph::new(topic.to_string()).fg(Color::Gray).italic();
+        frame.render_widget(topic, topic_area);
+    }
+
+    messages(frame, app, state, history);
+
+    let title = match (&app.status, app.mode) {
+        (Some(status), _) => Line::from(format!(" {status} ")).fg(Color::Red),
+        (None, Mode::Message) => Line::from(" Message, Ctrl+O uploads a file "),
+        (None, Mode::Upload) => Line::from(" Path of the file to upload, Esc cancels "),
+    };
+
+    let block = Block::bordered().title(title);
+    let inner = block.inner(input);
+    let len = app.input.chars().count() as u16;
+
+    // Keep the end of a long input in sight
+    let scroll = (len + 1).saturating_sub(inner.width);
+    let text = Paragraph::new(app.input.as_str())
+        .scroll((0, scroll))
+        .block(block);
+
+    frame.render_widget(text, input);
+    frame.set_cursor_position(Position::new(inner.x + len - scroll, inner.y));
+}
+
+/// Lists channels with their last messages like the web client does.
+fn channel_list(frame: &mut Frame, app: &App, state: &State, area: Rect) {
+    let items = state.channels().map(|chan| {
+        let last = chan.last_message().to_string();
+        ListItem::new(Text::from(vec![
+            Line::from(chan.name().to_owned()).bold(),
+            Line::from(last).fg(Color::DarkGray),
+        ]))
+    });
+
+    let list = List::new(items)
+        .block(Block::bordered().title(" Channels "))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+
+    let mut selected = ListState::default().with_selected(Some(app.chan as usize));
+    frame.render_stateful_widget(list, area, &mut selected);
+}
+
+/// Renders the history of the current channel grouped by senders, the end is at the bottom.
+fn messages(frame: &mut Frame, app: &App, state: &State, area: Rect) {
+    let mut lines = vec![];
+    for (user, rows) in state.messages(app.chan) {
+        lines.push(Line::from(user.name.to_string()).bold().fg(Color::Cyan));
+        for row in rows {
+            match row {
+                MessageContent::Text(text) => {
+                    lines.extend(text.lines().map(|line| Line::from(format!("  {line}"))));
+                }
+                MessageContent::File { orig, .. } => {
+                    lines.push(Line::from(format!("  [file] {orig}")).fg(Color::Blue));
+                }
+                MessageContent::Action(text) => {
+                    let action = format!("  * {} {}", user.name, text.trim());
+                    lines.push(Line::from(action).italic());
+                }
+                MessageContent::Notice(text) => {
+                    lines.push(Line::from(format!("  {text}")).fg(Color::DarkGray));
+                }
+            }
+        }
+
+        lines.push(Line::default());
+    }
+
+    let block = Block::bordered();
+    let inner = block.inner(area);
+    let history = Paragraph::new(lines).wrap(Wrap { trim: false });
+
+    // Scroll so the last lines are at the bottom, or above them if the user scrolled up
+    let total = history.line_count(inner.width) as u16;
+    let end = total.saturating_sub(inner.height);
+    let history = history.scroll((end.saturating_sub(app.scroll), 0));
+    frame.render_widget(history.block(block), area);
+}
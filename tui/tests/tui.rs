@@ -0,0 +1,147 @@
+use base::api::{self, MessageType, ServerMessage};
+use client::State;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{backend::TestBackend, Terminal};
+use voki_tui::{ui, Action, App};
+
+fn key(code: KeyCode) -> KeyEvent {
+    KeyEvent::new(code, KeyModifiers::NONE)
+}
+
+fn typed(app: &mut App, state: &State, text: &str) {
+    for c in text.chars() {
+        assert_eq!(app.key(key(KeyCode::Char(c)), state), None);
+    }
+}
+
+fn state() -> State {
+    let message = |from, chan, text: &str| api::Message {
+        from,
+        chan,
+        content: MessageType::Text(text.into()),
+        author: None,
+    };
+
+    let user = |id, name: &str| api::User {
+        id,
+        name: name.into(),
+        avatar: None,
+        bot: false,
+    };
+
+    let mut state = State::default();
+    state.apply(&ServerMessage::LoggedIn(Ok(1)));
+    state.apply(&ServerMessage::User(user(1, "alice")));
+    state.apply(&ServerMessage::User(user(2, "bob")));
+    state.apply(&ServerMessage::Channel(api::Channel {
+        id: 0,
+        name: "general".into(),
+        icon: None,
+        topic: Some("Releases".into()),
+        history: vec![message(1, 0, "hi"), message(1, 0, "there")],
+    }));
+
+    state.apply(&ServerMessage::Channel(api::Channel {
+        id: 1,
+        name: "random".into(),
+        icon: None,
+        topic: None,
+        history: vec![message(2, 1, "cats")],
+    }));
+
+    state.apply(&ServerMessage::Message(message(2, 0, "hello")));
+    state
+}
+
+fn render(app: &App, state: &State) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(80, 20)).expect("terminal");
+    terminal
+        .draw(|frame| ui::draw(frame, app, state))
+        .expect("draw");
+
+    let buffer = terminal.backend().buffer();
+    let mut screen = String::new();
+    for y in 0..buffer.area.height {
+        for x in 0..buffer.area.width {
+            screen.push_str(buffer[(x, y)].symbol());
+        }
+
+        screen.push('\n');
+    }
+
+    screen
+}
+
+#[test]
+fn login() {
+    let state = State::default();
+    let mut app = App::default();
+
+    typed(&mut app, &state, "alice");
+    assert_eq!(app.key(key(KeyCode::Enter), &state), None);
+    typed(&mut app, &state, "secret");
+
+    let screen = render(&app, &state);
+    assert!(screen.contains("Name: alice"));
+    assert!(screen.contains("Password: ******"));
+
+    assert_eq!(
+        app.key(key(KeyCode::Enter), &state),
+        Some(Action::Login {
+            name: "alice".into(),
+            pass: "secret".into()
+        })
+    );
+}
+
+#[test]
+fn chat() {
+    let state = state();
+    let mut app = App::default();
+    let screen = render(&app, &state);
+
+    // Channels with their last messages
+    assert!(screen.contains("general"));
+    assert!(screen.contains("random"));
+    assert!(screen.contains("cats"));
+    assert!(screen.contains("Releases"));
+
+    // The history is grouped by senders
+    assert_eq!(screen.matches("alice").count(), 1);
+    assert!(screen.contains("  hi"));
+    assert!(screen.contains("  there"));
+    assert!(screen.contains("  hello"));
+
+    typed(&mut app, &state, "hey");
+    assert_eq!(
+        app.key(key(KeyCode::Enter), &state),
+        Some(Action::Send {
+            chan: 0,
+            text: "hey".into()
+        })
+    );
+
+    assert!(app.input.is_empty());
+    assert_eq!(app.key(key(KeyCode::Tab), &state), None);
+    assert_eq!(app.chan, 1);
+    assert_eq!(app.key(key(KeyCode::Tab), &state), None);
+    assert_eq!(app.chan, 0);
+    assert_eq!(app.key(key(KeyCode::BackTab), &state), None);
+    assert_eq!(app.chan, 1);
+
+    let upload = KeyEvent::new(KeyCode::Char('o'), KeyModifiers::CONTROL);
+    assert_eq!(app.key(upload, &state), None);
+    typed(&mut app, &state, "cat.png");
+    assert_eq!(
+        app.key(key(KeyCode::Enter), &state),
+        Some(Action::Upload {
+            chan: 1,
+            path: "cat.png".into()
+        })
+    );
+
+    // Esc leaves the upload mode first and quits then
+    assert_eq!(app.key(upload, &state), None);
+    assert_eq!(app.key(key(KeyCode::Esc), &state), None);
+    assert_eq!(app.key(key(KeyCode::Esc), &state), Some(Action::Quit));
+}
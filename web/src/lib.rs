@@ -1,13 +1,7 @@
-mod post;
-mod socket;
-mod state;
 mod view;
 
-use self::{
-    socket::socket,
-    state::{Channel, Message, State, User},
-    view::{Action, App, Data, Event, Props},
-};
+use self::view::{Action, App, Data, Event, Props};
+use client::{state, Request, Sender, State};
 use std::{cell::RefCell, rc::Rc};
 use wasm_bindgen::prelude::*;
 use yew::{AppHandle, Callback};
@@ -27,112 +21,94 @@ impl View {
 
 #[wasm_bindgen(start)]
 pub fn main() -> Result<(), JsValue> {
-    use base::api::{ClientMessage, ServerMessage};
+    use base::api::ServerMessage;
+    use client::Event;
     use gloo::{console::log, utils::document};
 
-    let (write, read) = {
-        let host = document()
-            .location()
-            .expect_throw("location")
-            .host()
-            .expect_throw("host");
+    let url = {
+        let location = document().location().expect_throw("location");
+        let host = location.host().expect_throw("host");
 
-        let url = format!("ws://{}:4567", host);
-        log!("url", &url);
-        socket(&url)
-    };
-
-    let state = Rc::new(RefCell::new(State::default()));
-    let view = {
-        let root = document().get_element_by_id("root").expect_throw("root");
-
-        let app = yew::start_app_with_props_in_element::<App>(
-            root,
-            Props {
-                data: Data {
-                    state: Rc::clone(&state),
-                    current_channel: 0,
-                },
-                onaction: Callback::from({
-                    let write = write.clone();
-                    move |action| match action {
-                        Action::Send { chan, text } => {
-                            write.request(ClientMessage::Say { chan, text: &text })
-                        }
-                        Action::File { chan, ext, bytes } => write.request(ClientMessage::File {
-                            chan,
-                            ext: &ext,
-                            bytes: &bytes,
-                        }),
-                    }
-                }),
-                onlogin: Callback::from(move |(name, pass): (String, String)| {
-                    if !name.is_empty() && !pass.is_empty() {
-                        write.request(ClientMessage::Login {
-                            name: &name,
-                            pass: &pass,
-                        })
-                    }
-                }),
-            },
-        );
+        // Browsers don't allow insecure sockets from a secure page
+        let scheme = match location.protocol().expect_throw("protocol").as_str() {
+            "https:" => "wss",
+            _ => "ws",
+        };
 
-        View { app }
+        // The chat is served at the same host as the page
+        format!("{scheme}://{host}/ws")
     };
 
-    read.register(move |message| match message {
-        ServerMessage::Closed => log!("closed"),
-        ServerMessage::LoggedIn(logged) => match logged {
-            Ok(id) => {
-                state.borrow_mut().set_login(id);
-                view.update();
-            }
+    log!("url", &url);
+    wasm_futures::spawn_local(async move {
+        let (sender, mut receiver) = match client::connect(&url).await {
+            Ok(connection) => connection,
             Err(err) => {
                 log!("error", err.to_string());
-                state.borrow_mut().retry = true;
+                return;
+            }
+        };
+
+        let state = Rc::new(RefCell::new(State::default()));
+        let view = start(Rc::clone(&state), sender);
+        while let Some(event) = receiver.recv().await {
+            let Event::Message(message) = event else {
+                continue;
+            };
+
+            if state.borrow_mut().apply(&message) {
                 view.update();
             }
-        },
-        ServerMessage::User(user) => {
-            state.borrow_mut().push_user(
-                user.id,
-                User {
-                    name: user.name.into(),
-                    avatar: user.avatar.map(Into::into),
-                },
-            );
-
-            view.update();
-        }
-        ServerMessage::Channel(chan) => {
-            {
-                let mut state = state.borrow_mut();
-                state.push_channel(chan.id, Channel::new(&chan.name, chan.icon.as_deref()));
-                for message in chan.history {
-                    state.push_message(
-                        message.chan,
-                        Message {
-                            from: message.from,
-                            content: message.content.into(),
-                        },
-                    );
+
+            match message {
+                ServerMessage::Closed => log!("closed"),
+                ServerMessage::LoggedIn(Err(err)) => log!("error", err.to_string()),
+                ServerMessage::ServerShutdown { reconnect_after } => {
+                    log!("server shutdown, reconnect after", reconnect_after)
+                }
+                ServerMessage::RateLimited { retry_after_ms, .. } => {
+                    log!("rate limited, retry after ms", retry_after_ms)
                 }
+                ServerMessage::TooLarge { max_size, .. } => log!("too large, max size", max_size),
+                _ => {}
             }
-
-            view.update();
-        }
-        ServerMessage::Message(message) => {
-            state.borrow_mut().push_message(
-                message.chan,
-                Message {
-                    from: message.from,
-                    content: message.content.into(),
-                },
-            );
-
-            view.update();
         }
+
+        log!("disconnected");
     });
 
     Ok(())
 }
+
+fn start(state: Rc<RefCell<State>>, sender: Sender) -> View {
+    use gloo::utils::document;
+
+    let root = document().get_element_by_id("root").expect_throw("root");
+    let app = yew::start_app_with_props_in_element::<App>(
+        root,
+        Props {
+            data: Data {
+                state,
+                current_channel: 0,
+            },
+            onaction: Callback::from({
+                let sender = sender.clone();
+                move |action| {
+                    let request = match action {
+                        Action::Send { chan, text } => Request::text(chan, &text),
+                        Action::File { chan, ext, bytes } => Request::File { chan, ext, bytes },
+                    };
+
+                    let _ = sender.send(request);
+                }
+            }),
+            onlogin: Callback::from(move |(name, pass): (String, String)| {
+                if !name.is_empty() && !pass.is_empty() {
+                    let _ = sender.send(Request::Login { name, pass });
+                }
+            }),
+        },
+    );
+
+    View { app }
+}
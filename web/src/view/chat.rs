@@ -1,5 +1,5 @@
 use crate::{
-    state::MessageContent,
+    state::{Command, MessageContent},
     view::{
         svg::{src, Svg},
         Data,
@@ -15,6 +15,7 @@ pub struct MessageProps {
     avatar: Option<Rc<str>>,
     name: Rc<str>,
     rows: Vector<MessageContent>,
+    onopen: Callback<Rc<str>>,
 }
 
 #[function_component(Message)]
@@ -48,13 +49,22 @@ pub fn message(props: &MessageProps) -> Html {
                                     }
                                 </p>
                             },
-                            MessageContent::File(file) =>{
-                                let mut src = String::from("./images/");
-                                src.push_str(file);
+                            MessageContent::File { thumb, orig } => {
+                                let onclick = {
+                                    let orig = Rc::clone(orig);
+                                    props.onopen.reform(move |_: MouseEvent| Rc::clone(&orig))
+                                };
+
                                 html! {
-                                    <img { src } />
+                                    <img class="thumb" src={ thumb.to_string() } { onclick } />
                                 }
                             },
+                            MessageContent::Action(text) => html! {
+                                <p class="text action">{ format!("{} {}", props.name, text.trim()) }</p>
+                            },
+                            MessageContent::Notice(text) => html! {
+                                <p class="text notice">{ text.to_string() }</p>
+                            },
                         })
                     }
                 </div>
@@ -71,30 +81,82 @@ pub enum SendEvent {
 #[derive(PartialEq, Properties)]
 pub struct InputProps {
     onsend: Callback<SendEvent>,
+    commands: Vector<Command>,
+}
+
+/// Returns the name being typed if the text is a command without arguments yet.
+fn command_query(text: &str) -> Option<&str> {
+    let name = text.strip_prefix('/')?;
+    let typing = !name.starts_with('/') && !name.contains(char::is_whitespace);
+    typing.then_some(name)
 }
 
 #[function_component(Input)]
 pub fn input(props: &InputProps) -> Html {
+    let query = use_state(|| None::<Rc<str>>);
+    let suggestions: Vec<Command> = match query.as_deref() {
+        Some(query) => props
+            .commands
+            .iter()
+            .filter(|command| command.name.starts_with(query))
+            .cloned()
+            .collect(),
+        None => vec![],
+    };
+
     let node_send = NodeRef::default();
     let send = {
         let node = node_send.clone();
         let onsend = props.onsend.clone();
+        let query = query.clone();
         move || {
             let text: web_sys::HtmlTextAreaElement = node.cast().expect_throw("cast");
             onsend.emit(SendEvent::Text(text.value().into()));
             text.set_value("");
             text.set_attribute("style", "")
                 .expect_throw("set attribute");
+            query.set(None);
         }
     };
 
-    let oninput = Callback::from(|ev: InputEvent| {
-        let element: web_sys::Element = ev.target_dyn_into().expect_throw("target");
-        let height = element.scroll_height();
-        let height = format!("height: {}px", height.min(200));
-        element
-            .set_attribute("style", &height)
-            .expect_throw("set attribute");
+    // Completes the command name, so arguments can be typed after it
+    let complete = {
+        let node = node_send.clone();
+        let query = query.clone();
+        move |name: &str| {
+            let text: web_sys::HtmlTextAreaElement = node.cast().expect_throw("cast");
+            text.set_value(&format!("/{name} "));
+            text.focus().expect_throw("focus");
+            query.set(None);
+        }
+    };
+
+    let oninput = Callback::from({
+        let query = query.clone();
+        move |ev: InputEvent| {
+            let element: web_sys::HtmlTextAreaElement = ev.target_dyn_into().expect_throw("target");
+            let height = element.scroll_height();
+            let height = format!("height: {}px", height.min(200));
+            element
+                .set_attribute("style", &height)
+                .expect_throw("set attribute");
+
+            query.set(command_query(&element.value()).map(Into::into));
+        }
+    });
+
+    // Tab isn't a key press, so it's caught when the key goes down
+    let onkeydown = Callback::from({
+        let complete = complete.clone();
+        let first = suggestions.first().map(|command| Rc::clone(&command.name));
+        move |ev: KeyboardEvent| {
+            if ev.key() == "Tab" {
+                if let Some(name) = &first {
+                    ev.prevent_default();
+                    complete(name);
+                }
+            }
+        }
     });
 
     let onkeypress = Callback::from({
@@ -145,6 +207,26 @@ pub fn input(props: &InputProps) -> Html {
 
     html! {
         <div class="input">
+            if !suggestions.is_empty() {
+                <div class="suggestions">
+                    {
+                        for suggestions.iter().map(|command| {
+                            let onclick = {
+                                let complete = complete.clone();
+                                let name = Rc::clone(&command.name);
+                                Callback::from(move |_: MouseEvent| complete(&name))
+                            };
+
+                            html! {
+                                <div class="suggestion" { onclick }>
+                                    <p class="usage">{ format!("/{} {}", command.name, command.usage) }</p>
+                                    <p class="description">{ command.description.to_string() }</p>
+                                </div>
+                            }
+                        })
+                    }
+                </div>
+            }
             <input
                 ref={ node_attach }
                 { onchange }
@@ -152,7 +234,7 @@ pub fn input(props: &InputProps) -> Html {
                 type="file"
                 accept="image/jpg, image/jpeg, image/png"
             />
-            <textarea ref={ node_send } { oninput } { onkeypress }></textarea>
+            <textarea ref={ node_send } { oninput } { onkeydown } { onkeypress }></textarea>
             <div class="button" onclick={ onclick_send }>
                 <Svg content={ src!("/icons/send.svg") } />
             </div>
@@ -173,6 +255,8 @@ pub enum Event {
         ext: String,
         bytes: Vec<u8>,
     },
+    Open(Rc<str>),
+    Close,
 }
 
 #[derive(PartialEq, Properties)]
@@ -181,7 +265,10 @@ pub struct Props {
     pub onfile: Callback<(u32, String, Vec<u8>)>,
 }
 
-pub struct Chat;
+pub struct Chat {
+    lightbox: Option<Rc<str>>,
+    keep_scroll: bool,
+}
 
 impl Chat {
     fn scroll_to_end(&mut self) {
@@ -199,7 +286,10 @@ impl Component for Chat {
     type Properties = Props;
 
     fn create(_: &Context<Self>) -> Self {
-        Self
+        Self {
+            lightbox: None,
+            keep_scroll: false,
+        }
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
@@ -212,6 +302,14 @@ impl Component for Chat {
                 ext,
                 bytes,
             } => ctx.props().onfile.emit((channel, ext, bytes)),
+            Event::Open(image) => {
+                self.lightbox = Some(image);
+                self.keep_scroll = true;
+            }
+            Event::Close => {
+                self.lightbox = None;
+                self.keep_scroll = true;
+            }
             _ => {}
         }
 
@@ -231,31 +329,45 @@ impl Component for Chat {
             },
         });
 
+        let onopen = ctx.link().callback(Event::Open);
+        let onclose = ctx.link().callback(|_: MouseEvent| Event::Close);
+
         let state = data.state.borrow();
+        let commands = state.commands();
         html! {
             <div class="chat">
+                if let Some(topic) = state.topic(data.current_channel) {
+                    <div class="topic">{ topic.to_string() }</div>
+                }
                 <div class="messages">
                     {
-                        for state.messages(data.current_channel).into_iter().map(|(from, messages)| {
-                            let user = state.user(from).cloned().unwrap_or_default();
-
+                        for state.messages(data.current_channel).into_iter().map(|(user, messages)| {
                             html! {
                                 <Message
                                     avatar={ user.avatar }
                                     name={ user.name }
                                     rows={ messages.clone() }
+                                    onopen={ onopen.clone() }
                                 />
                             }
                         })
                     }
                 </div>
                 <div class="pad"/>
-                <Input { onsend } />
+                <Input { onsend } { commands } />
+                if let Some(image) = &self.lightbox {
+                    <div class="lightbox" onclick={ onclose }>
+                        <img src={ image.to_string() } />
+                    </div>
+                }
             </div>
         }
     }
 
     fn rendered(&mut self, _: &Context<Self>, _: bool) {
-        self.scroll_to_end();
+        // Opening or closing the lightbox shouldn't move the history
+        if !std::mem::take(&mut self.keep_scroll) {
+            self.scroll_to_end();
+        }
     }
 }